@@ -0,0 +1,14 @@
+-- The outcome of replaying each game's export under its competition's ruleset
+create table if not exists game_replays (
+    game_id int primary key references games(id) on delete cascade
+    -- null when the game couldn't be replayed
+  , score smallint
+  , turns smallint
+  , num_strikes smallint
+  , end_condition text
+    -- why the game couldn't be replayed, e.g. an illegal action or an unsupported variant
+  , error text
+    -- the replay disagrees with the game's reported score or turns, or hit an illegal action
+  , mismatched boolean not null
+  , replayed_at timestamptz not null default now()
+);
@@ -0,0 +1,213 @@
+-- Multi-variant ("decathlon") competitions, whose seeds each have their own variant, and
+-- optionally their own time control and options. Matchpoints are still computed per seed.
+alter table competitions
+    alter column variant_id drop not null
+    -- named after this instead of a variant, when the seeds each have their own
+  , add column if not exists title text check(length(title) > 0)
+  , add constraint competitions_variant_or_title check((variant_id is null) = (title is not null))
+  , add constraint competitions_title_key
+        unique (end_date, num_players, title, scoring_type, base_time_seconds, turn_time_seconds);
+
+-- null means the competition's
+alter table competition_seeds
+    add column if not exists deckplay_enabled boolean
+  , add column if not exists empty_clues_enabled boolean
+  , add column if not exists base_time_seconds smallint check(base_time_seconds is null or (base_time_seconds < 3600 and base_time_seconds > 0))
+  , add column if not exists turn_time_seconds smallint check(turn_time_seconds is null or (turn_time_seconds < 3600 and turn_time_seconds > 0))
+  , add constraint competition_seeds_time_control_check
+        check((base_time_seconds is null) = (turn_time_seconds is null))
+    -- the standings tell seeds apart by their base name
+  , add constraint competition_seeds_competition_id_base_name_key unique (competition_id, base_name);
+
+-- the competition names and everything built on them are recreated to take the title
+drop view if exists series_player_scores;
+drop view if exists series_competition_results;
+drop view if exists computed_competition_standings;
+drop materialized view if exists competition_names;
+
+create materialized view if not exists competition_names as (
+    select
+        competitions.id competition_id
+      , concat(
+            to_char(competitions.end_datetime, 'YYYY-MM-DD')
+          , ' '
+          , cast(competitions.num_players as text)
+          , 'p '
+          , coalesce(variants.name, competitions.title)
+          , (case
+                when scoring_type = 'speedrun'
+                    then 'speedrun'
+                -- constraint ensures turn_time_seconds is also not null
+                when base_time_seconds is not null
+                    then concat(
+                        ' ['
+                      , to_char(base_time_seconds * '1 second'::interval, 'MI:SS')
+                      , ' + '
+                      , to_char(turn_time_seconds * '1 second'::interval, 'MI:SS')
+                      , ']'
+                    )
+                else ''
+            end)
+        ) as name
+    from competitions
+    left join variants on competitions.variant_id = variants.id
+);
+
+-- `refresh materialized view concurrently` requires a unique index
+create unique index if not exists competition_names_competition_id_idx
+on competition_names (competition_id);
+
+create or replace view computed_competition_standings as (
+    select
+        competition_names.name competition_name
+      , final_rank
+      , fractional_MP
+      , sum_MP
+      , player_name
+      , base_seed_name
+      , seed_matchpoints
+      , replay_URL
+      , site_game_id
+      , score
+      , turns
+      , datetime_game_started
+      , datetime_game_ended
+      , character_name
+      , variants.max_score
+      , cast(score as double precision) / variants.max_score normalized_score
+      , score = variants.max_score is_perfect_score
+      , end_condition
+    from competition_standings
+    join competition_names using(competition_id)
+    -- seeds may each have their own variant
+    join competition_seeds on
+        competition_standings.competition_id = competition_seeds.competition_id
+        and base_seed_name = competition_seeds.base_name
+    join variants on competition_seeds.variant_id = variants.id
+);
+
+create or replace view series_competition_results as (
+    with first_n_competitions_by_series_and_player as (
+        select
+            series.id series_id
+          , series.name series_name
+          , series.first_n
+          , series.top_n
+          , series.normalized_scores
+          , player_name
+          , competition_name
+          , fractional_MP
+          , avg(normalized_score) normalized_score
+            -- only counts games whose variant's max score is known
+          , count(is_perfect_score) num_games
+          , count(*) filter(where is_perfect_score) num_perfect_scores
+            -- under the logic that competition name starts with deadline date, and that we
+            -- won't have two competitions with the same date in the same series
+          , row_number() over(partition by series.id, player_name order by competition_name)
+                as nth_competition_by_series_and_player
+        from computed_competition_standings
+        join competition_names on competition_name = competition_names.name
+        join series_competitions using(competition_id)
+        join series on series_id = series.id
+        group by
+            series.id
+          , series_name
+          , first_n
+          , top_n
+          , normalized_scores
+          , player_name
+          , competition_name
+          , fractional_MP
+    ),
+
+    top_n_competitions_by_series_and_player as (
+        select
+            series_id
+          , series_name
+          , top_n
+          , player_name
+          , competition_name
+          , fractional_MP
+          , normalized_score
+          , num_games
+          , num_perfect_scores
+          , row_number() over(
+                partition by series_id, player_name
+                order by
+                    case
+                        when normalized_scores
+                            then normalized_score
+                        else fractional_MP
+                    end desc nulls last
+            ) as ranked_performance_by_series_and_player
+        from first_n_competitions_by_series_and_player
+        where (
+            case
+                when first_n is not null
+                    then nth_competition_by_series_and_player <= first_n
+                else true
+            end
+        )
+    )
+    select
+        series_name
+      , player_name
+      , competition_name
+      , fractional_MP
+      , normalized_score
+      , num_games
+      , num_perfect_scores
+    from top_n_competitions_by_series_and_player
+    where (
+        case
+            when top_n is not null
+                then ranked_performance_by_series_and_player <= top_n
+            else true
+        end
+    )
+);
+
+create or replace view series_player_scores as (
+    with measured_results as (
+        select
+            series_competition_results.*
+          , case
+                when series.normalized_scores
+                    then normalized_score
+                else fractional_MP
+            end as measure
+        from series_competition_results
+        join series on series_name = series.name
+    ),
+    base_view as (
+        select
+            player_name
+          , series_name
+          , case
+                when series_name like 'All-time%'
+                    -- null rather than an error when none of the player's games has a measure,
+                    -- e.g. in a normalized series on variants with an unknown max score
+                    then median(measure) * (1 + log(20, nullif(count(measure), 0)))
+                    -- use this factor if we want to stop inflating past 100 competitions
+                    -- add an extra 1 to the competitions count so that a player with
+                    -- 1 competition has nonzero score
+                    --* greatest(log(100, count(measure) + 1), 1)
+                else sum(measure)
+            end as score
+          , avg(fractional_mp) mean_frac_mp
+          , avg(normalized_score) mean_normalized_score
+          , cast(sum(num_perfect_scores) as double precision) / nullif(sum(num_games), 0)
+                as perfect_score_rate
+        from measured_results
+        group by player_name, series_name
+    )
+    select
+        rank() over(partition by series_name order by score desc nulls last) rank
+      , player_name
+      , series_name
+      , score
+      , mean_frac_mp
+      , mean_normalized_score
+      , perfect_score_rate
+    from base_view
+);
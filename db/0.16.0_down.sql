@@ -0,0 +1,388 @@
+drop view if exists series_player_scores;
+drop view if exists series_competition_results;
+drop view if exists computed_competition_standings;
+
+alter table competition_standings
+    drop column if exists penalty_MP;
+
+create or replace function compute_competition_standings(competition_ids smallint[])
+returns setof competition_standings
+as $$
+        with base_cte as (
+            select
+                competitions.id competition_id
+              , competition_seeds.id seed_id
+              , competition_seeds.base_name base_seed_name
+              , games.id game_id
+                -- if we start allowing play on different sites, revisit this
+              , concat('https://hanab.live/replay/', games.site_game_id) replay_URL
+              , games.site_game_id
+                -- strikeouts and terminations are credited with the score reached before they
+                -- ended, rather than the site's 0
+              , coalesce(games.pre_strikeout_score, games.score) score
+              , games.end_condition
+              , games.turns
+              , games.datetime_started datetime_game_started
+              , games.datetime_ended datetime_game_ended
+              , competitions.scoring_type
+              , games.team_id
+            from competitions
+            join competition_seeds on competition_seeds.competition_id = competitions.id
+            join games on competition_seeds.id = games.seed_id
+            where
+                games.datetime_ended < competitions.end_datetime
+                -- games played on a seed before the competition was announced don't count
+                and (
+                    competitions.start_datetime is null
+                    or games.datetime_started >= competitions.start_datetime
+                )
+                -- games of unregistered teams only count if the competition allows them
+                and (not competitions.registration_required or games.team_id is not null)
+                and competitions.id = any(competition_ids)
+        ),
+        game_participation as (
+            select
+                seed_id
+              , game_id
+              , datetime_game_started
+              , coalesce(primary_accounts.id, actual_accounts.id) player_id
+              , case 
+                    when whitelisted_games.game_id is not null
+                        then 1
+                    else 0
+                end as is_whitelisted_game
+            from base_cte
+            join game_players using(game_id)
+            join players actual_accounts on game_players.player_id = actual_accounts.id
+            left join aliases on actual_accounts.id = aliases.alias_id
+            left join players primary_accounts on aliases.primary_id = primary_accounts.id
+            left join whitelisted_games using(game_id)
+            where not exists (
+                select b.game_id
+                from blacklisted_games b
+                where b.game_id = base_cte.game_id
+            )
+        ),
+        prioritized_games as (
+            select
+                game_id
+              , row_number() over(
+                    partition by seed_id, player_id
+                    order by is_whitelisted_game desc, datetime_game_started
+                ) priority
+            from game_participation
+        ),
+        selected_game_ids as (
+            select distinct game_id
+            from prioritized_games
+            where priority = 1
+        ),
+        games_selected as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , team_id
+              , cast(case
+                    when scoring_type = 'speedrun'
+                        then rank() over(partition by seed_id order by
+                            score desc,
+                            datetime_game_ended - datetime_game_started
+                        )
+                    else  -- standard
+                        rank() over(partition by seed_id order by score desc, turns)
+                end as int) as seed_rank
+              , cast(count(*) over(partition by seed_id) as int) num_seed_participants
+              , cast(count(*) over(partition by competition_id) as int) num_comp_participants
+            from base_cte
+            join selected_game_ids using(game_id)
+        ),
+        competition_num_unique_seeds as (
+            select competitions.id, count(distinct competition_seeds.id) num_seeds
+            from competitions
+            join competition_seeds on competition_seeds.competition_id = competitions.id
+            where competitions.id = any(competition_ids)
+            group by competitions.id
+        ),
+        computed_mp as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , (
+                    2 * num_seed_participants
+                    - (cast(count(*) over(partition by seed_id, seed_rank) as int) - 1)
+                    - 2 * seed_rank
+                ) as seed_matchpoints
+              , 2 * (num_comp_participants - num_seeds) as max_MP
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , team_id
+            from games_selected
+            join competition_num_unique_seeds on competition_id = competition_num_unique_seeds.id
+        ),
+        computed_mp_with_primary_player_ids as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , seed_matchpoints
+              , max_MP
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , coalesce(primary_accounts.id, actual_accounts.id) player_id
+              , coalesce(primary_accounts.name, actual_accounts.name) player_name
+              , game_players.character_id
+              , team_id
+            from computed_mp
+            join game_players using(game_id)
+            join players actual_accounts on game_players.player_id = actual_accounts.id
+            left join aliases on actual_accounts.id = aliases.alias_id
+            left join players primary_accounts on aliases.primary_id = primary_accounts.id
+        ),
+        mp_agg as (
+            select
+                competition_id
+              , sum(seed_matchpoints) over(partition by competition_id, player_id) as sum_MP
+              , player_id
+              , player_name
+              , seed_id
+              , base_seed_name
+              , seed_matchpoints
+              , max_MP
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , character_id
+              , team_id
+            from computed_mp_with_primary_player_ids
+        ),
+        competition_player_sum_MP as (
+            select distinct
+                competition_id
+              , player_id
+              , sum_MP
+            from mp_agg
+        ),
+        competition_player_ranks as (
+            select
+                competition_id
+              , player_id 
+              , rank() over(partition by competition_id order by sum_MP desc) final_rank
+            from competition_player_sum_MP
+        )
+    select
+        mp_agg.competition_id
+      , final_rank
+      , case
+            when max_MP = 0
+                then null
+            else
+                cast(sum_MP as real)/ max_MP
+        end as fractional_MP
+      , sum_MP
+      , player_name
+      , base_seed_name
+      , seed_matchpoints
+      , replay_URL
+      , site_game_id
+      , score
+      , turns
+      , datetime_game_started
+      , datetime_game_ended
+      , characters.name character_name
+      , end_condition
+      , teams.name team_name
+      , max_MP
+    from mp_agg
+    join competition_player_ranks cpr using(competition_id, player_id)
+    -- characters are dealt to each player of each game, rather than to seeds
+    left join characters on mp_agg.character_id = characters.id
+    left join teams on mp_agg.team_id = teams.id
+$$ language sql stable;
+
+
+create or replace view computed_competition_standings as (
+    select
+        competition_names.name competition_name
+      , final_rank
+      , fractional_MP
+      , sum_MP
+      , player_name
+      , base_seed_name
+      , seed_matchpoints
+      , replay_URL
+      , site_game_id
+      , score
+      , turns
+      , datetime_game_started
+      , datetime_game_ended
+      , character_name
+      , variants.max_score
+      , cast(score as double precision) / variants.max_score normalized_score
+      , score = variants.max_score is_perfect_score
+      , end_condition
+      , team_name
+      , max_MP
+    from competition_standings
+    join competition_names using(competition_id)
+    -- seeds may each have their own variant
+    join competition_seeds on
+        competition_standings.competition_id = competition_seeds.competition_id
+        and base_seed_name = competition_seeds.base_name
+    join variants on competition_seeds.variant_id = variants.id
+);
+
+create or replace view series_competition_results as (
+    with first_n_competitions_by_series_and_player as (
+        select
+            series.id series_id
+          , series.name series_name
+          , series.first_n
+          , series.top_n
+          , series.normalized_scores
+          , player_name
+          , competition_name
+          , fractional_MP
+          , avg(normalized_score) normalized_score
+            -- only counts games whose variant's max score is known
+          , count(is_perfect_score) num_games
+          , count(*) filter(where is_perfect_score) num_perfect_scores
+            -- under the logic that competition name starts with deadline date, and that we
+            -- won't have two competitions with the same date in the same series
+          , row_number() over(partition by series.id, player_name order by competition_name)
+                as nth_competition_by_series_and_player
+        from computed_competition_standings
+        join competition_names on competition_name = competition_names.name
+        join series_competitions using(competition_id)
+        join series on series_id = series.id
+        group by
+            series.id
+          , series_name
+          , first_n
+          , top_n
+          , normalized_scores
+          , player_name
+          , competition_name
+          , fractional_MP
+    ),
+
+    top_n_competitions_by_series_and_player as (
+        select
+            series_id
+          , series_name
+          , top_n
+          , player_name
+          , competition_name
+          , fractional_MP
+          , normalized_score
+          , num_games
+          , num_perfect_scores
+          , row_number() over(
+                partition by series_id, player_name
+                order by
+                    case
+                        when normalized_scores
+                            then normalized_score
+                        else fractional_MP
+                    end desc nulls last
+            ) as ranked_performance_by_series_and_player
+        from first_n_competitions_by_series_and_player
+        where (
+            case
+                when first_n is not null
+                    then nth_competition_by_series_and_player <= first_n
+                else true
+            end
+        )
+    )
+    select
+        series_name
+      , player_name
+      , competition_name
+      , fractional_MP
+      , normalized_score
+      , num_games
+      , num_perfect_scores
+    from top_n_competitions_by_series_and_player
+    where (
+        case
+            when top_n is not null
+                then ranked_performance_by_series_and_player <= top_n
+            else true
+        end
+    )
+);
+
+create or replace view series_player_scores as (
+    with measured_results as (
+        select
+            series_competition_results.*
+          , case
+                when series.normalized_scores
+                    then normalized_score
+                else fractional_MP
+            end as measure
+        from series_competition_results
+        join series on series_name = series.name
+    ),
+    base_view as (
+        select
+            player_name
+          , series_name
+          , case
+                when series_name like 'All-time%'
+                    -- null rather than an error when none of the player's games has a measure,
+                    -- e.g. in a normalized series on variants with an unknown max score
+                    then median(measure) * (1 + log(20, nullif(count(measure), 0)))
+                    -- use this factor if we want to stop inflating past 100 competitions
+                    -- add an extra 1 to the competitions count so that a player with
+                    -- 1 competition has nonzero score
+                    --* greatest(log(100, count(measure) + 1), 1)
+                else sum(measure)
+            end as score
+          , avg(fractional_mp) mean_frac_mp
+          , avg(normalized_score) mean_normalized_score
+          , cast(sum(num_perfect_scores) as double precision) / nullif(sum(num_games), 0)
+                as perfect_score_rate
+        from measured_results
+        group by player_name, series_name
+    )
+    select
+        rank() over(partition by series_name order by score desc nulls last) rank
+      , player_name
+      , series_name
+      , score
+      , mean_frac_mp
+      , mean_normalized_score
+      , perfect_score_rate
+    from base_view
+);
+
+drop table if exists adjustments;
+
+select update_computed_competition_standings();
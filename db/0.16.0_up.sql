@@ -0,0 +1,337 @@
+-- Admin adjustments to a competition's standings, each for a player or a game and with a reason
+-- that's shown on the competition page: matchpoint penalties, scores awarded in place of a
+-- game's (e.g. after a server bug), and disqualifications, whose games don't count at all.
+create table if not exists adjustments (
+    id int primary key generated always as identity
+  , competition_id smallint not null references competitions(id) on delete cascade
+  , player_id int references players(id) on delete cascade
+  , game_id int references games(id) on delete cascade
+  , adjustment_type text not null check(adjustment_type in (
+        'penalty'
+      , 'adjusted_score'
+      , 'disqualification'
+    ))
+  , amount smallint
+  , reason text not null check(length(reason) > 0)
+  , created_at timestamptz not null default now()
+  , check ((player_id is null) <> (game_id is null))
+  , check (case adjustment_type
+        when 'penalty' then amount > 0
+        when 'adjusted_score' then game_id is not null and amount >= 0
+        else amount is null
+    end)
+);
+
+-- a game only has one score
+create unique index if not exists adjustments_adjusted_score_key
+    on adjustments (game_id)
+    where adjustment_type = 'adjusted_score';
+
+-- player-level penalties, which a team's nesting can't tell from its games' matchpoints
+alter table competition_standings
+    add column if not exists penalty_MP int;
+
+create or replace function compute_competition_standings(competition_ids smallint[])
+returns setof competition_standings
+as $$
+        with disqualified_games as (
+            select game_id
+            from adjustments
+            where adjustment_type = 'disqualification'
+                and game_id is not null
+                and competition_id = any(competition_ids)
+            union
+            -- every game a disqualified player played in the competition, on any of their accounts
+            select game_players.game_id
+            from adjustments
+            left join aliases disqualified_aliases on adjustments.player_id = disqualified_aliases.alias_id
+            join competition_seeds using(competition_id)
+            join games on games.seed_id = competition_seeds.id
+            join game_players on game_players.game_id = games.id
+            left join aliases on game_players.player_id = aliases.alias_id
+            where adjustment_type = 'disqualification'
+                and adjustments.competition_id = any(competition_ids)
+                and coalesce(aliases.primary_id, game_players.player_id)
+                    = coalesce(disqualified_aliases.primary_id, adjustments.player_id)
+        ),
+        player_penalties as (
+            select
+                competition_id
+              , coalesce(aliases.primary_id, adjustments.player_id) player_id
+              , cast(sum(amount) as int) penalty_MP
+            from adjustments
+            left join aliases on adjustments.player_id = aliases.alias_id
+            where adjustment_type = 'penalty'
+                and adjustments.player_id is not null
+                and competition_id = any(competition_ids)
+            group by competition_id, coalesce(aliases.primary_id, adjustments.player_id)
+        ),
+        base_cte as (
+            select
+                competitions.id competition_id
+              , competition_seeds.id seed_id
+              , competition_seeds.base_name base_seed_name
+              , games.id game_id
+                -- if we start allowing play on different sites, revisit this
+              , concat('https://hanab.live/replay/', games.site_game_id) replay_URL
+              , games.site_game_id
+                -- strikeouts and terminations are credited with the score reached before they
+                -- ended, rather than the site's 0, and either can be overridden by an admin
+              , coalesce(
+                    adjusted_scores.amount
+                  , games.pre_strikeout_score
+                  , games.score
+                ) score
+              , games.end_condition
+              , games.turns
+              , games.datetime_started datetime_game_started
+              , games.datetime_ended datetime_game_ended
+              , competitions.scoring_type
+              , games.team_id
+            from competitions
+            join competition_seeds on competition_seeds.competition_id = competitions.id
+            join games on competition_seeds.id = games.seed_id
+            left join adjustments adjusted_scores on
+                adjusted_scores.game_id = games.id
+                and adjusted_scores.adjustment_type = 'adjusted_score'
+            where
+                games.datetime_ended < competitions.end_datetime
+                -- games played on a seed before the competition was announced don't count
+                and (
+                    competitions.start_datetime is null
+                    or games.datetime_started >= competitions.start_datetime
+                )
+                -- games of unregistered teams only count if the competition allows them
+                and (not competitions.registration_required or games.team_id is not null)
+                and competitions.id = any(competition_ids)
+                and games.id not in (select game_id from disqualified_games)
+        ),
+        game_participation as (
+            select
+                seed_id
+              , game_id
+              , datetime_game_started
+              , coalesce(primary_accounts.id, actual_accounts.id) player_id
+              , case 
+                    when whitelisted_games.game_id is not null
+                        then 1
+                    else 0
+                end as is_whitelisted_game
+            from base_cte
+            join game_players using(game_id)
+            join players actual_accounts on game_players.player_id = actual_accounts.id
+            left join aliases on actual_accounts.id = aliases.alias_id
+            left join players primary_accounts on aliases.primary_id = primary_accounts.id
+            left join whitelisted_games using(game_id)
+            where not exists (
+                select b.game_id
+                from blacklisted_games b
+                where b.game_id = base_cte.game_id
+            )
+        ),
+        prioritized_games as (
+            select
+                game_id
+              , row_number() over(
+                    partition by seed_id, player_id
+                    order by is_whitelisted_game desc, datetime_game_started
+                ) priority
+            from game_participation
+        ),
+        selected_game_ids as (
+            select distinct game_id
+            from prioritized_games
+            where priority = 1
+        ),
+        games_selected as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , team_id
+              , cast(case
+                    when scoring_type = 'speedrun'
+                        then rank() over(partition by seed_id order by
+                            score desc,
+                            datetime_game_ended - datetime_game_started
+                        )
+                    else  -- standard
+                        rank() over(partition by seed_id order by score desc, turns)
+                end as int) as seed_rank
+              , cast(count(*) over(partition by seed_id) as int) num_seed_participants
+              , cast(count(*) over(partition by competition_id) as int) num_comp_participants
+            from base_cte
+            join selected_game_ids using(game_id)
+        ),
+        competition_num_unique_seeds as (
+            select competitions.id, count(distinct competition_seeds.id) num_seeds
+            from competitions
+            join competition_seeds on competition_seeds.competition_id = competitions.id
+            where competitions.id = any(competition_ids)
+            group by competitions.id
+        ),
+        computed_mp as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , (
+                    2 * num_seed_participants
+                    - (cast(count(*) over(partition by seed_id, seed_rank) as int) - 1)
+                    - 2 * seed_rank
+                    - coalesce((
+                        select cast(sum(amount) as int)
+                        from adjustments
+                        where adjustments.game_id = games_selected.game_id
+                            and adjustment_type = 'penalty'
+                    ), 0)
+                ) as seed_matchpoints
+              , 2 * (num_comp_participants - num_seeds) as max_MP
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , team_id
+            from games_selected
+            join competition_num_unique_seeds on competition_id = competition_num_unique_seeds.id
+        ),
+        computed_mp_with_primary_player_ids as (
+            select
+                competition_id
+              , seed_id
+              , base_seed_name
+              , seed_matchpoints
+              , max_MP
+              , game_id
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , coalesce(primary_accounts.id, actual_accounts.id) player_id
+              , coalesce(primary_accounts.name, actual_accounts.name) player_name
+              , game_players.character_id
+              , team_id
+            from computed_mp
+            join game_players using(game_id)
+            join players actual_accounts on game_players.player_id = actual_accounts.id
+            left join aliases on actual_accounts.id = aliases.alias_id
+            left join players primary_accounts on aliases.primary_id = primary_accounts.id
+        ),
+        mp_agg as (
+            select
+                competition_id
+              , cast(sum(seed_matchpoints) over(partition by competition_id, player_id) as int)
+                    - coalesce(penalty_MP, 0) as sum_MP
+              , player_id
+              , player_name
+              , seed_id
+              , base_seed_name
+              , seed_matchpoints
+              , max_MP
+              , replay_URL
+              , site_game_id
+              , score
+              , end_condition
+              , turns
+              , datetime_game_started
+              , datetime_game_ended
+              , character_id
+              , team_id
+              , coalesce(penalty_MP, 0) penalty_MP
+            from computed_mp_with_primary_player_ids
+            left join player_penalties using(competition_id, player_id)
+        ),
+        competition_player_sum_MP as (
+            select distinct
+                competition_id
+              , player_id
+              , sum_MP
+            from mp_agg
+        ),
+        competition_player_ranks as (
+            select
+                competition_id
+              , player_id 
+              , rank() over(partition by competition_id order by sum_MP desc) final_rank
+            from competition_player_sum_MP
+        )
+    select
+        mp_agg.competition_id
+      , final_rank
+      , case
+            when max_MP = 0
+                then null
+            else
+                cast(sum_MP as real)/ max_MP
+        end as fractional_MP
+      , sum_MP
+      , player_name
+      , base_seed_name
+      , seed_matchpoints
+      , replay_URL
+      , site_game_id
+      , score
+      , turns
+      , datetime_game_started
+      , datetime_game_ended
+      , characters.name character_name
+      , end_condition
+      , teams.name team_name
+      , max_MP
+      , penalty_MP
+    from mp_agg
+    join competition_player_ranks cpr using(competition_id, player_id)
+    -- characters are dealt to each player of each game, rather than to seeds
+    left join characters on mp_agg.character_id = characters.id
+    left join teams on mp_agg.team_id = teams.id
+$$ language sql stable;
+
+
+create or replace view computed_competition_standings as (
+    select
+        competition_names.name competition_name
+      , final_rank
+      , fractional_MP
+      , sum_MP
+      , player_name
+      , base_seed_name
+      , seed_matchpoints
+      , replay_URL
+      , site_game_id
+      , score
+      , turns
+      , datetime_game_started
+      , datetime_game_ended
+      , character_name
+      , variants.max_score
+      , cast(score as double precision) / variants.max_score normalized_score
+      , score = variants.max_score is_perfect_score
+      , end_condition
+      , team_name
+      , max_MP
+      , penalty_MP
+    from competition_standings
+    join competition_names using(competition_id)
+    -- seeds may each have their own variant
+    join competition_seeds on
+        competition_standings.competition_id = competition_seeds.competition_id
+        and base_seed_name = competition_seeds.base_name
+    join variants on competition_seeds.variant_id = variants.id
+);
+
+select update_computed_competition_standings();
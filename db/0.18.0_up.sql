@@ -0,0 +1,3 @@
+-- Changes that failed are audited too, with what went wrong; null for changes that went through
+alter table audit_log
+    add column if not exists error text;
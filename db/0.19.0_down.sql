@@ -0,0 +1,4 @@
+-- Provisional teams become registered teams, though their games are only attributed to them the
+-- next time games are ingested or aliases change
+alter table teams
+    drop column if exists confirmed;
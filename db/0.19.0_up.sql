@@ -0,0 +1,6 @@
+-- Teams registered without credentials are provisional until an admin confirms them, so that a
+-- bogus roster can't take over players' games. Teams registered so far stay as they are.
+alter table teams
+    add column if not exists confirmed boolean not null default true;
+alter table teams
+    alter column confirmed drop default;
@@ -0,0 +1,28 @@
+-- API tokens for automation clients. Only hashes of the tokens are stored.
+create table if not exists api_tokens (
+    id serial primary key
+  , token_hash bytea not null unique
+  , owner text not null
+  , scopes text[] not null
+  , description text
+  , issued_by text not null
+  , created_at timestamptz not null default now()
+  , last_used_at timestamptz
+  , revoked_at timestamptz
+);
+
+-- The viewer role runs user-supplied SQL, so it mustn't be able to read the token hashes,
+-- despite the default privileges granting it select on every table
+do $$
+declare
+    grantee_name text;
+begin
+    for grantee_name in
+        select distinct grantee
+        from information_schema.role_table_grants
+        where table_name = 'api_tokens'
+            and grantee <> current_user
+    loop
+        execute format('revoke all on api_tokens from %I', grantee_name);
+    end loop;
+end $$;
@@ -0,0 +1,29 @@
+-- Every administrative change, recorded in the same transaction as the change itself
+create table if not exists audit_log (
+    id bigserial primary key
+  , recorded_at timestamptz not null default now()
+  , endpoint text not null
+  , user_id text not null
+  -- sha256 of the change's JSON payload
+  , payload_digest text not null
+  , affected_rows bigint not null
+  -- dry runs are rolled back, apart from their entry here
+  , dry_run boolean not null
+);
+
+create index if not exists audit_log_user_id_idx on audit_log (user_id);
+
+-- Like the API tokens, this isn't for the eyes of the viewer role, which runs user-supplied SQL
+do $$
+declare
+    grantee_name text;
+begin
+    for grantee_name in
+        select distinct grantee
+        from information_schema.role_table_grants
+        where table_name = 'audit_log'
+            and grantee <> current_user
+    loop
+        execute format('revoke all on audit_log from %I', grantee_name);
+    end loop;
+end $$;
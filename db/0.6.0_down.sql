@@ -0,0 +1,3 @@
+drop table if exists competition_schedule_series;
+drop table if exists competition_schedule_rotations;
+drop table if exists competition_schedules;
@@ -0,0 +1,50 @@
+-- Recurring competitions. Each schedule creates a round of competitions ahead of time, every
+-- cadence_days days; rounds are numbered from 0, and end on end_weekday at end_time (UTC).
+create table if not exists competition_schedules (
+    id smallint primary key generated always as identity
+  , name text not null unique check(length(name) > 0)
+  , enabled boolean not null default true
+  , cadence_days smallint not null check(cadence_days > 0)
+    -- ISO weekday; 1 is Monday
+  , end_weekday smallint not null check(end_weekday between 1 and 7)
+  , end_time time not null
+    -- round 0 ends on the first end_weekday on or after this date
+  , first_round_date date not null
+    -- how many days before a round starts its competitions are created
+  , lead_days smallint not null default 7 check(lead_days >= 0)
+  , num_seeds smallint not null default 4 check(num_seeds > 0)
+    -- {date} is replaced with the round's end date, {round} with its number, and {n} with
+    -- the seed's number, starting from 1
+  , seed_name_pattern text not null default 'hc-{date}-{n}' check(strpos(seed_name_pattern, '{n}') > 0)
+    -- how many consecutive entries of the rotation each round takes
+  , competitions_per_round smallint not null default 1 check(competitions_per_round > 0)
+    -- left null to use the same defaults as a competition posted without them
+  , deckplay_enabled boolean
+  , empty_clues_enabled boolean
+  , characters_enabled boolean
+  , scoring_type scoring_type
+  , base_time_seconds smallint
+  , turn_time_seconds smallint
+  , check (
+        (base_time_seconds is not null and turn_time_seconds is not null)
+        or (base_time_seconds is null and turn_time_seconds is null)
+    )
+  , additional_rules text
+    -- null until the first round is created
+  , last_created_round int
+);
+
+-- The variants and player counts the schedule's competitions cycle through
+create table if not exists competition_schedule_rotations (
+    schedule_id smallint not null references competition_schedules(id) on delete cascade
+  , position smallint not null
+  , num_players smallint not null check(num_players >= 2)
+  , variant_id int not null references variants(id)
+  , primary key (schedule_id, position)
+);
+
+create table if not exists competition_schedule_series (
+    schedule_id smallint not null references competition_schedules(id) on delete cascade
+  , series_id smallint not null references series(id) on delete cascade
+  , primary key (schedule_id, series_id)
+);
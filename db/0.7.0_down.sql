@@ -0,0 +1,14 @@
+alter table variants
+    drop constraint if exists variants_name_key
+  , add constraint variants_name_key unique (name);
+
+drop index if exists variants_site_variant_id_key;
+
+alter table variants
+    drop column if exists removed_at
+  , drop column if exists flags
+  , drop column if exists max_score
+  , drop column if exists special_rank
+  , drop column if exists clue_ranks
+  , drop column if exists clue_colors
+  , drop column if exists suits;
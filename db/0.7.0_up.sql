@@ -0,0 +1,20 @@
+-- Variant metadata, synced from hanab.live's variants.json
+alter table variants
+    add column if not exists suits text[] not null default '{}'
+    -- null means the default clue colors for the variant's suits
+  , add column if not exists clue_colors text[]
+  , add column if not exists clue_ranks smallint[] not null default '{1,2,3,4,5}'
+  , add column if not exists special_rank smallint check(special_rank between 1 and 5)
+  , add column if not exists max_score smallint check(max_score > 0)
+    -- the boolean properties set on the variant, e.g. upOrDown or throwItInAHole
+  , add column if not exists flags text[] not null default '{}'
+    -- set when a sync no longer finds the variant on the site; kept so that old
+    -- competitions still resolve
+  , add column if not exists removed_at timestamptz;
+
+create unique index if not exists variants_site_variant_id_key on variants (site_variant_id);
+
+-- a sync can swap the names of two variants, so only check names at commit
+alter table variants
+    drop constraint if exists variants_name_key
+  , add constraint variants_name_key unique (name) deferrable initially immediate;
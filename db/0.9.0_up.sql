@@ -0,0 +1,9 @@
+-- Full game exports from hanab.live (deck, actions, options, players), so that replays can be
+-- analysed without going back to the site. Keyed by the site's game ID rather than referencing
+-- `games`, since an export describes the game on the site, whichever seed it was ingested into.
+create table if not exists game_exports (
+    site_game_id bigint primary key
+    -- gzipped JSON
+  , export bytea not null
+  , stored_at timestamptz not null default now()
+);
@@ -0,0 +1,1073 @@
+// Command-line administration, as an alternative to hand-written requests to the admin API.
+// Works either directly against the db (using the same env vars as the server), or through a
+// running server's admin API, when `--server` is given.
+use std::{fs, io::{self, BufRead, Write}, path::{Path, PathBuf}};
+use anyhow::{anyhow, Result};
+use dotenv::dotenv;
+use serde::{Serialize, de::DeserializeOwned};
+use sqlx::PgPool;
+use structopt::StructOpt;
+use actix_web::client::Client;
+use hanabi_live_competitions::{
+    credentials::{self, Scope},
+    get_expected_env_var,
+    model::{
+        UtcDateTime,
+        adjustment::{self, Adjustment, AdjustmentRequest, AdjustmentType},
+        api_token::{self, ApiToken, ApiTokenRequest, IssuedApiToken},
+        audit::AuditContext,
+        character::{self, CharacterSyncReport},
+        competition::{
+            self,
+            CompetitionNestedResults,
+            PartiallySpecifiedCompetition,
+            TimeControl,
+        },
+        game::{self, CompetitionGames, GameList, GameListEntry},
+        player::{self, Alias},
+        replay::{self, GameReplay},
+        series::{self, Series},
+        team::{self, RegisteredTeam, TeamRegistration},
+        tie_break::TieBreak,
+        variant::{self, Variant, VariantSyncReport},
+    },
+    DbAdminPool,
+    DbViewerPool,
+};
+
+#[derive(StructOpt)]
+#[structopt(name = "hc-admin")]
+struct Opt {
+    /// Base URL of a running server to send requests to, e.g. http://127.0.0.1:5056;
+    /// if absent, the db is modified directly
+    #[structopt(long, env = "HC_ADMIN_SERVER")]
+    server: Option<String>,
+    /// Admin user for the server's API
+    #[structopt(long, env = "HC_ADMIN_USER")]
+    user: Option<String>,
+    /// Admin password for the server's API
+    #[structopt(long, env = "HC_ADMIN_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// API token for the server's API, instead of a user and password
+    #[structopt(long, env = "HC_ADMIN_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+    /// Check the changes for errors, then roll them back; the attempt is still audited
+    #[structopt(long)]
+    dry_run: bool,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Creates a competition from flags, or competitions from a TOML file
+    AddCompetitions(AddCompetitionsOpt),
+    /// Creates a series
+    AddSeries {
+        name: String,
+        /// Only count each player's first n competitions in the series
+        #[structopt(long)]
+        first_n: Option<i16>,
+        /// Only count each player's best n competitions in the series
+        #[structopt(long)]
+        top_n: Option<i16>,
+        /// Rank players by their fraction of each variant's max score, rather than by
+        /// fractional MP
+        #[structopt(long)]
+        normalized_scores: bool,
+        /// total_score, fewer_turns, seeds_won or earliest_completion; may be repeated, and
+        /// breaks ties on score in the order given
+        #[structopt(long = "tie-break")]
+        tie_breaks: Vec<TieBreak>,
+    },
+    /// Adds a variant, as named and numbered on hanab.live
+    AddVariant {
+        site_variant_id: i32,
+        name: String,
+    },
+    /// Adds, renames and marks removed variants to match hanab.live's variants.json, read from
+    /// a file or an http(s) URL
+    SyncVariants {
+        source: String,
+    },
+    /// Adds and renames detrimental characters to match hanab.live's characters.json, read from
+    /// a file or an http(s) URL
+    SyncCharacters {
+        source: String,
+    },
+    /// Imports games from a JSON file, in the format accepted by POST /games
+    ImportGames {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Replays every stored game export, and prints the games whose reported results disagree
+    ReplayGames,
+    /// Manages aliases, whose games are credited to a primary account
+    Aliases(AliasesCommand),
+    /// Manages the teams registered for a competition
+    Teams(TeamsCommand),
+    /// Manages a competition's penalties, adjusted scores and disqualifications
+    Adjustments(AdjustmentsCommand),
+    /// Manages whitelisted games, which take priority over a player's other games on a seed
+    Whitelist(GameListCommand),
+    /// Manages blacklisted games, which are excluded from the standings
+    Blacklist(GameListCommand),
+    /// Recomputes competition names and all standings
+    Refresh,
+    /// Prints the standings of a competition
+    Standings {
+        competition_name: String,
+    },
+    /// Manages API tokens for automation clients
+    Tokens(TokensCommand),
+    /// Edits the server's admin credentials file, which a running server rereads on its own
+    Credentials(CredentialsOpt),
+}
+
+#[derive(StructOpt)]
+enum TokensCommand {
+    /// Prints a new token, which can't be retrieved again afterwards
+    Issue {
+        /// Who or what the token is for
+        owner: String,
+        /// competitions:write, games:write, players:write or admin; may be repeated
+        #[structopt(long = "scope", required = true)]
+        scopes: Vec<Scope>,
+        #[structopt(long)]
+        description: Option<String>,
+    },
+    List,
+    Revoke {
+        id: i32,
+    },
+}
+
+#[derive(StructOpt)]
+struct CredentialsOpt {
+    #[structopt(long, env = "ACCEPTED_API_CREDENTIALS", parse(from_os_str))]
+    file: PathBuf,
+    #[structopt(subcommand)]
+    command: CredentialsCommand,
+}
+
+#[derive(StructOpt)]
+enum CredentialsCommand {
+    /// Adds an admin, or replaces their password and scopes; the password is read from stdin
+    Set {
+        user_id: String,
+        /// admin, competitions:write, games:write or players:write; may be repeated, and
+        /// defaults to admin, which allows everything
+        #[structopt(long = "scope")]
+        scopes: Vec<Scope>,
+    },
+    Remove {
+        user_id: String,
+    },
+}
+
+#[derive(StructOpt)]
+struct AddCompetitionsOpt {
+    /// TOML file with a [[competitions]] table per competition, with the same fields as the
+    /// JSON accepted by POST /competitions; when given, the other flags are ignored
+    #[structopt(long, parse(from_os_str))]
+    file: Option<PathBuf>,
+    #[structopt(long, required_unless = "file")]
+    num_players: Option<i16>,
+    #[structopt(long, required_unless = "file")]
+    variant: Option<String>,
+    /// e.g. 2020-08-24T13:00:00Z; games started before it don't count, and the seeds' links
+    /// aren't shown until then
+    #[structopt(long)]
+    start: Option<UtcDateTime>,
+    /// e.g. 2020-09-07T13:00:00Z; defaults to the second Monday from now, 13:00 UTC
+    #[structopt(long)]
+    end: Option<UtcDateTime>,
+    #[structopt(long)]
+    deckplay: Option<bool>,
+    #[structopt(long)]
+    empty_clues: Option<bool>,
+    #[structopt(long)]
+    characters: Option<bool>,
+    /// Only count the games of teams that registered their roster
+    #[structopt(long)]
+    registration_required: bool,
+    #[structopt(long)]
+    speedrun: bool,
+    #[structopt(long, requires = "turn-time-seconds")]
+    base_time_seconds: Option<i16>,
+    #[structopt(long, requires = "base-time-seconds")]
+    turn_time_seconds: Option<i16>,
+    #[structopt(long)]
+    additional_rules: Option<String>,
+    /// Base seed name; may be repeated, and defaults to four seeds named after the end date
+    #[structopt(long = "seed")]
+    seeds: Vec<String>,
+    /// Series to add the competition to; may be repeated
+    #[structopt(long = "series")]
+    series: Vec<String>,
+    /// total_score, fewer_turns, seeds_won or earliest_completion; may be repeated, and
+    /// breaks ties on matchpoints in the order given
+    #[structopt(long = "tie-break")]
+    tie_breaks: Vec<TieBreak>,
+}
+
+#[derive(serde::Deserialize)]
+struct CompetitionsFile {
+    competitions: Vec<PartiallySpecifiedCompetition>,
+}
+
+impl AddCompetitionsOpt {
+    fn into_competitions(self) -> Result<Vec<PartiallySpecifiedCompetition>> {
+        if let Some(file) = self.file {
+            let competitions_file: CompetitionsFile = toml::from_str(&fs::read_to_string(file)?)?;
+            return Ok(competitions_file.competitions);
+        }
+        Ok(vec![PartiallySpecifiedCompetition {
+            num_players: self.num_players.unwrap(),
+            variant_name: self.variant,
+            title: None,
+            start_datetime: self.start,
+            end_datetime: self.end,
+            deckplay_enabled: self.deckplay,
+            empty_clues_enabled: self.empty_clues,
+            characters_enabled: self.characters,
+            scoring_type: if self.speedrun { Some("speedrun".to_owned()) } else { None },
+            time_control: match (self.base_time_seconds, self.turn_time_seconds) {
+                (Some(base_time_seconds), Some(turn_time_seconds)) => Some(TimeControl {
+                    base_time_seconds,
+                    turn_time_seconds,
+                }),
+                _ => None,
+            },
+            additional_rules: self.additional_rules,
+            base_seed_names: if self.seeds.is_empty() { None } else { Some(self.seeds) },
+            seeds: None,
+            series_names: Some(self.series),
+            registration_required: Some(self.registration_required),
+            tie_breaks: Some(self.tie_breaks),
+        }])
+    }
+}
+
+#[derive(StructOpt)]
+enum AliasesCommand {
+    /// Credits the games of `alias` to `primary`
+    Add {
+        alias: String,
+        primary: String,
+    },
+    Remove {
+        alias: String,
+    },
+    List,
+}
+
+#[derive(StructOpt)]
+enum TeamsCommand {
+    Register {
+        competition_name: String,
+        team_name: String,
+        #[structopt(long = "player", required = true)]
+        players: Vec<String>,
+        #[structopt(long = "substitute")]
+        substitutes: Vec<String>,
+    },
+    /// Counts the games of a team that was registered without credentials
+    Confirm {
+        competition_name: String,
+        team_name: String,
+    },
+    Remove {
+        competition_name: String,
+        team_name: String,
+    },
+    List {
+        competition_name: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum AdjustmentsCommand {
+    /// Adjusts the standings for either `--player` or `--game`
+    Add {
+        competition_name: String,
+        /// penalty, adjusted_score or disqualification
+        adjustment_type: AdjustmentType,
+        #[structopt(long)]
+        player: Option<String>,
+        /// Site game ID
+        #[structopt(long)]
+        game: Option<i64>,
+        /// Matchpoints for a penalty, or the score for an adjusted score
+        #[structopt(long)]
+        amount: Option<i16>,
+        #[structopt(long)]
+        reason: String,
+    },
+    Remove {
+        competition_name: String,
+        id: i32,
+    },
+    List {
+        competition_name: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum GameListCommand {
+    Add {
+        site_game_id: i64,
+        #[structopt(long)]
+        reason: Option<String>,
+    },
+    Remove {
+        site_game_id: i64,
+    },
+    List,
+}
+
+enum Backend {
+    Db {
+        admin_pool: DbAdminPool,
+        viewer_pool: DbViewerPool,
+        // recorded in the audit log as the user making the changes
+        user_id: String,
+        dry_run: bool,
+    },
+    Api {
+        client: Client,
+        server: String,
+        auth: ApiAuth,
+        dry_run: bool,
+    },
+}
+
+enum ApiAuth {
+    Basic {
+        user: String,
+        password: String,
+    },
+    Bearer(String),
+}
+
+impl Backend {
+    async fn from_opt(opt: &Opt) -> Result<Self> {
+        match &opt.server {
+            None => Ok(Backend::Db {
+                admin_pool: DbAdminPool(
+                    PgPool::new(&get_expected_env_var("DATABASE_ADMIN_URL")).await?),
+                viewer_pool: DbViewerPool(
+                    PgPool::new(&get_expected_env_var("DATABASE_VIEWER_URL")).await?),
+                user_id: format!(
+                    "{} (hc-admin)",
+                    std::env::var("USER").unwrap_or_else(|_| "unknown".to_owned()),
+                ),
+                dry_run: opt.dry_run,
+            }),
+            Some(server) => Ok(Backend::Api {
+                client: Client::default(),
+                server: server.trim_end_matches('/').to_owned(),
+                auth: match &opt.token {
+                    Some(token) => ApiAuth::Bearer(token.clone()),
+                    None => ApiAuth::Basic {
+                        user: opt.user.clone()
+                            .ok_or_else(|| anyhow!("--user or --token is required with --server"))?,
+                        password: opt.password.clone()
+                            .ok_or_else(|| anyhow!("--password is required with --user"))?,
+                    },
+                },
+                dry_run: opt.dry_run,
+            }),
+        }
+    }
+
+    // Only to be called on the API backend
+    async fn send<T: Serialize>(
+        &self,
+        method: actix_web::http::Method,
+        path: &str,
+        payload: Option<&T>,
+    ) -> Result<String> {
+        let (client, server, auth, dry_run) = match self {
+            Backend::Api { client, server, auth, dry_run } => (client, server, auth, *dry_run),
+            Backend::Db { .. } => unreachable!("requests are only sent to a server"),
+        };
+        let path = if dry_run && method != actix_web::http::Method::GET {
+            format!("{}?dry_run=true", path)
+        } else {
+            path.to_owned()
+        };
+        let request = client.request(method, format!("{}{}", server, path));
+        let request = match auth {
+            ApiAuth::Basic { user, password } => request.basic_auth(user, Some(password)),
+            ApiAuth::Bearer(token) => request.bearer_auth(token),
+        };
+        let mut response = match payload {
+            Some(payload) => request.send_json(payload).await,
+            None => request.send().await,
+        }.map_err(|err| anyhow!("{}", err))?;
+        let body = response.body().limit(1 << 24).await.map_err(|err| anyhow!("{}", err))?;
+        let body = String::from_utf8_lossy(&body).into_owned();
+        if !response.status().is_success() {
+            return Err(anyhow!("{}: {}", response.status(), body));
+        }
+        Ok(body)
+    }
+
+    async fn post<T: Serialize>(&self, path: &str, payload: &T) -> Result<String> {
+        self.send(actix_web::http::Method::POST, path, Some(payload)).await
+    }
+
+    async fn delete<T: Serialize>(&self, path: &str, payload: &T) -> Result<String> {
+        self.send(actix_web::http::Method::DELETE, path, Some(payload)).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let body = self.send::<()>(actix_web::http::Method::GET, path, None).await?;
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    // Only to be called on the db backend
+    fn audit<T: Serialize + ?Sized>(&self, command: &str, payload: &T) -> AuditContext {
+        match self {
+            Backend::Db { user_id, dry_run, .. } => {
+                AuditContext::new(&format!("hc-admin {}", command), user_id, payload, *dry_run)
+            },
+            Backend::Api { .. } => unreachable!("the server audits API requests itself"),
+        }
+    }
+
+    fn is_dry_run(&self) -> bool {
+        match self {
+            Backend::Db { dry_run, .. } | Backend::Api { dry_run, .. } => *dry_run,
+        }
+    }
+
+    fn report_success(&self, message: &str) {
+        if self.is_dry_run() {
+            println!("Dry run, nothing was committed: {}", message);
+        } else {
+            println!("{}", message);
+        }
+    }
+}
+
+#[actix_rt::main]
+async fn main() -> Result<()> {
+    dotenv().ok();
+    env_logger::init();
+    let opt = Opt::from_args();
+    // doesn't need a backend
+    if let Command::Credentials(credentials_opt) = opt.command {
+        return run_credentials_command(credentials_opt);
+    }
+    let backend = Backend::from_opt(&opt).await?;
+    match opt.command {
+        Command::AddCompetitions(add_competitions_opt) => {
+            let competitions = add_competitions_opt.into_competitions()?;
+            match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("add-competitions", &competitions);
+                    competition::add_competitions(admin_pool, competitions, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post("/competitions", &competitions).await?; },
+            }
+            backend.report_success("Competitions and seeds were successfully inserted.");
+        },
+        Command::AddSeries { name, first_n, top_n, normalized_scores, tie_breaks } => {
+            let series = vec![Series { name, first_n, top_n, normalized_scores, tie_breaks }];
+            match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("add-series", &series);
+                    series::add_series(admin_pool, series, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post("/series", &series).await?; },
+            }
+            backend.report_success("Series were successfully inserted.");
+        },
+        Command::AddVariant { site_variant_id, name } => {
+            let variants = vec![Variant { id: site_variant_id, name }];
+            match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("add-variant", &variants);
+                    variant::add_variants(admin_pool, &variants, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post("/variants", &variants).await?; },
+            }
+            backend.report_success("Variants were successfully inserted.");
+        },
+        Command::SyncVariants { source } => {
+            let catalog: serde_json::Value = serde_json::from_str(&read_catalog(&source).await?)?;
+            let report = match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let variants = variant::parse_variant_catalog(catalog)?;
+                    let audit = backend.audit("sync-variants", &variants);
+                    variant::sync_variants(admin_pool, &variants, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    serde_json::from_str(&backend.post("/variants/sync", &catalog).await?)?
+                },
+            };
+            print_variant_sync_report(&report);
+            backend.report_success("Variants were successfully synced.");
+        },
+        Command::SyncCharacters { source } => {
+            let catalog: serde_json::Value = serde_json::from_str(&read_catalog(&source).await?)?;
+            let report = match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let characters = character::parse_character_catalog(catalog)?;
+                    let audit = backend.audit("sync-characters", &characters);
+                    character::sync_characters(admin_pool, &characters, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    serde_json::from_str(&backend.post("/characters/sync", &catalog).await?)?
+                },
+            };
+            print_character_sync_report(&report);
+            backend.report_success("Characters were successfully synced.");
+        },
+        Command::ImportGames { file } => {
+            let competitions_games: Vec<CompetitionGames> =
+                serde_json::from_str(&fs::read_to_string(file)?)?;
+            for competition_games in &competitions_games {
+                competition_games.validate()?;
+            }
+            match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("import-games", &competitions_games);
+                    let ingest_report =
+                        game::add_competitions_games(admin_pool, &competitions_games, &audit).await?;
+                    backend.report_success(&format!(
+                        "Games were successfully inserted.{}",
+                        ingest_report.summarize(),
+                    ));
+                },
+                // the server's message already says whether it was a dry run
+                Backend::Api { .. } => println!("{}", backend.post("/games", &competitions_games).await?),
+            }
+        },
+        Command::ReplayGames => {
+            let flagged_replays: Vec<GameReplay> = match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("replay-games", &());
+                    replay::replay_all_games(admin_pool, &audit).await?
+                },
+                Backend::Api { .. } => serde_json::from_str(&backend.post("/replays", &()).await?)?,
+            };
+            print_flagged_replays(&flagged_replays);
+            backend.report_success("Games were successfully replayed.");
+        },
+        Command::Aliases(aliases_command) => run_aliases_command(&backend, aliases_command).await?,
+        Command::Teams(teams_command) => run_teams_command(&backend, teams_command).await?,
+        Command::Adjustments(adjustments_command) => {
+            run_adjustments_command(&backend, adjustments_command).await?
+        },
+        Command::Whitelist(game_list_command) => {
+            run_game_list_command(&backend, GameList::Whitelist, game_list_command).await?
+        },
+        Command::Blacklist(game_list_command) => {
+            run_game_list_command(&backend, GameList::Blacklist, game_list_command).await?
+        },
+        Command::Refresh => {
+            match &backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("refresh", &());
+                    competition::refresh_all_standings(admin_pool, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post("/refresh", &()).await?; },
+            }
+            backend.report_success("Standings were successfully refreshed.");
+        },
+        Command::Standings { competition_name } => {
+            let results = match &backend {
+                Backend::Db { viewer_pool, .. } => {
+                    competition::get_competition_and_nested_results(
+                        viewer_pool,
+                        &competition_name,
+                    ).await?
+                },
+                Backend::Api { .. } => {
+                    // the raw output is the results' JSON, encoded as a JSON string
+                    let results_json: String = backend.get(&format!(
+                        "/competitions/{}?raw=true",
+                        urlencoding::encode(&competition_name),
+                    )).await?;
+                    serde_json::from_str(&results_json)?
+                },
+            };
+            print_standings(&results);
+        },
+        Command::Tokens(tokens_command) => run_tokens_command(&backend, tokens_command).await?,
+        Command::Credentials(_) => unreachable!("handled before connecting"),
+    }
+    Ok(())
+}
+
+async fn run_tokens_command(backend: &Backend, tokens_command: TokensCommand) -> Result<()> {
+    match tokens_command {
+        TokensCommand::Issue { owner, scopes, description } => {
+            let request = ApiTokenRequest { owner, scopes, description };
+            let issued_api_token: IssuedApiToken = match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("tokens issue", &request);
+                    api_token::issue_api_token(admin_pool, &request, &audit).await?
+                },
+                // a dry run's response is only the server's message, as there's no token to show
+                Backend::Api { .. } if backend.is_dry_run() => {
+                    println!("{}", backend.post("/api-tokens", &request).await?);
+                    return Ok(());
+                },
+                Backend::Api { .. } => {
+                    serde_json::from_str(&backend.post("/api-tokens", &request).await?)?
+                },
+            };
+            // a dry run's token was never stored, so it'd be no use to anyone
+            if backend.is_dry_run() {
+                backend.report_success("Token was successfully issued.");
+            } else {
+                backend.report_success(
+                    &format!("Issued token {}; it won't be shown again:", issued_api_token.id));
+                println!("{}", issued_api_token.token);
+            }
+        },
+        TokensCommand::List => {
+            let api_tokens: Vec<ApiToken> = match backend {
+                Backend::Db { admin_pool, .. } => api_token::get_api_tokens(admin_pool).await?,
+                Backend::Api { .. } => backend.get("/api-tokens").await?,
+            };
+            let format_datetime = |datetime: Option<UtcDateTime>| {
+                datetime.map(|datetime| datetime.to_rfc3339()).unwrap_or_default()
+            };
+            print_table(
+                &["id", "owner", "scopes", "description", "issued by", "last used", "revoked"],
+                api_tokens.into_iter().map(|api_token| vec![
+                    api_token.id.to_string(),
+                    api_token.owner,
+                    api_token.scopes.iter().map(Scope::as_str).collect::<Vec<&str>>().join(","),
+                    api_token.description.unwrap_or_default(),
+                    api_token.issued_by,
+                    format_datetime(api_token.last_used_at),
+                    format_datetime(api_token.revoked_at),
+                ]).collect(),
+            );
+        },
+        TokensCommand::Revoke { id } => {
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("tokens revoke", &id);
+                    api_token::revoke_api_token(admin_pool, id, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    backend.send::<()>(
+                        actix_web::http::Method::DELETE,
+                        &format!("/api-tokens/{}", id),
+                        None,
+                    ).await?;
+                },
+            }
+            backend.report_success("Token was successfully revoked.");
+        },
+    }
+    Ok(())
+}
+
+fn run_credentials_command(credentials_opt: CredentialsOpt) -> Result<()> {
+    let file = credentials_opt.file;
+    let file_contents = if file.exists() { fs::read_to_string(&file)? } else { String::new() };
+    match credentials_opt.command {
+        CredentialsCommand::Set { user_id, scopes } => {
+            if user_id.is_empty() || user_id.contains(':') {
+                return Err(anyhow!("User IDs must be nonempty and can't contain colons"));
+            }
+            eprint!("Password for {}: ", user_id);
+            io::stderr().flush()?;
+            let mut password = String::new();
+            io::stdin().lock().read_line(&mut password)?;
+            let password = password.trim_end_matches(&['\r', '\n'][..]);
+            if password.is_empty() {
+                return Err(anyhow!("The password can't be empty"));
+            }
+            let line = credentials::format_credentials_line(
+                &user_id,
+                &credentials::hash_password(password)?,
+                &scopes,
+            );
+            let new_contents = credentials::set_credentials_line(&file_contents, &user_id, &line);
+            write_credentials_file(&file, &new_contents)?;
+            println!("Credentials of {} were successfully set.", user_id);
+        },
+        CredentialsCommand::Remove { user_id } => {
+            let new_contents = credentials::remove_credentials_line(&file_contents, &user_id)
+                .ok_or_else(|| anyhow!("{} has no credentials", user_id))?;
+            write_credentials_file(&file, &new_contents)?;
+            println!("Credentials of {} were successfully removed.", user_id);
+        },
+    }
+    Ok(())
+}
+
+// Written to a temporary file first, so that the server never reads a partially written one
+fn write_credentials_file(file: &Path, contents: &str) -> Result<()> {
+    let tmp_file = file.with_extension("tmp");
+    fs::write(&tmp_file, contents)?;
+    if let Ok(metadata) = fs::metadata(file) {
+        fs::set_permissions(&tmp_file, metadata.permissions())?;
+    }
+    fs::rename(&tmp_file, file)?;
+    Ok(())
+}
+
+async fn run_aliases_command(backend: &Backend, aliases_command: AliasesCommand) -> Result<()> {
+    match aliases_command {
+        AliasesCommand::Add { alias, primary } => {
+            let aliases = vec![Alias { alias, primary }];
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("aliases add", &aliases);
+                    player::add_aliases(admin_pool, &aliases, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post("/aliases", &aliases).await?; },
+            }
+            backend.report_success("Aliases were successfully inserted.");
+        },
+        AliasesCommand::Remove { alias } => {
+            let alias_names = vec![alias];
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("aliases remove", &alias_names);
+                    player::remove_aliases(admin_pool, &alias_names, &audit).await?
+                },
+                Backend::Api { .. } => { backend.delete("/aliases", &alias_names).await?; },
+            }
+            backend.report_success("Aliases were successfully removed.");
+        },
+        AliasesCommand::List => {
+            let aliases: Vec<Alias> = match backend {
+                Backend::Db { viewer_pool, .. } => player::get_aliases(viewer_pool).await?,
+                Backend::Api { .. } => backend.get("/aliases").await?,
+            };
+            print_table(
+                &["alias", "primary"],
+                aliases.into_iter().map(|alias| vec![alias.alias, alias.primary]).collect(),
+            );
+        },
+    }
+    Ok(())
+}
+
+async fn run_teams_command(backend: &Backend, teams_command: TeamsCommand) -> Result<()> {
+    let teams_path = |competition_name: &str| {
+        format!("/competitions/{}/teams", urlencoding::encode(competition_name))
+    };
+    match teams_command {
+        TeamsCommand::Register { competition_name, team_name, players, substitutes } => {
+            let registration = TeamRegistration { team_name, players, substitutes };
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("teams register", &registration);
+                    team::register_team(admin_pool, &competition_name, &registration, true, &audit)
+                        .await?
+                },
+                Backend::Api { .. } => {
+                    backend.post(&teams_path(&competition_name), &registration).await?;
+                },
+            }
+            backend.report_success("Team was successfully registered.");
+        },
+        TeamsCommand::Confirm { competition_name, team_name } => {
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("teams confirm", &team_name);
+                    team::confirm_team(admin_pool, &competition_name, &team_name, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    backend.post(&format!(
+                        "{}/{}/confirm",
+                        teams_path(&competition_name),
+                        urlencoding::encode(&team_name),
+                    ), &()).await?;
+                },
+            }
+            backend.report_success("Team was successfully confirmed.");
+        },
+        TeamsCommand::Remove { competition_name, team_name } => {
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("teams remove", &team_name);
+                    team::remove_team(admin_pool, &competition_name, &team_name, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    backend.delete(&format!(
+                        "{}/{}",
+                        teams_path(&competition_name),
+                        urlencoding::encode(&team_name),
+                    ), &()).await?;
+                },
+            }
+            backend.report_success("Team was successfully removed.");
+        },
+        TeamsCommand::List { competition_name } => {
+            let registered_teams: Vec<RegisteredTeam> = match backend {
+                Backend::Db { viewer_pool, .. } => {
+                    team::get_registered_teams(viewer_pool, &competition_name).await?
+                },
+                Backend::Api { .. } => backend.get(&teams_path(&competition_name)).await?,
+            };
+            print_table(
+                &["team", "players", "substitutes", "registered at", "confirmed"],
+                registered_teams.into_iter().map(|registered_team| vec![
+                    registered_team.team_name,
+                    registered_team.players.join(", "),
+                    registered_team.substitutes.join(", "),
+                    registered_team.registered_at.to_rfc3339(),
+                    registered_team.confirmed.to_string(),
+                ]).collect(),
+            );
+        },
+    }
+    Ok(())
+}
+
+async fn run_adjustments_command(
+    backend: &Backend,
+    adjustments_command: AdjustmentsCommand,
+) -> Result<()> {
+    let adjustments_path = |competition_name: &str| {
+        format!("/competitions/{}/adjustments", urlencoding::encode(competition_name))
+    };
+    match adjustments_command {
+        AdjustmentsCommand::Add {
+            competition_name,
+            adjustment_type,
+            player,
+            game,
+            amount,
+            reason,
+        } => {
+            let request = AdjustmentRequest {
+                player_name: player,
+                site_game_id: game,
+                adjustment_type,
+                amount,
+                reason,
+            };
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("adjustments add", &request);
+                    let adjustment_id = adjustment::add_adjustment(
+                        admin_pool,
+                        &competition_name,
+                        &request,
+                        &audit,
+                    ).await?;
+                    backend.report_success(
+                        &format!("Adjustment {} was successfully added.", adjustment_id));
+                },
+                // the server's response has the new adjustment's id, and says if it was a dry run
+                Backend::Api { .. } => {
+                    println!("{}", backend.post(&adjustments_path(&competition_name), &request).await?);
+                },
+            }
+        },
+        AdjustmentsCommand::Remove { competition_name, id } => {
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit("adjustments remove", &id);
+                    adjustment::remove_adjustment(admin_pool, &competition_name, id, &audit).await?
+                },
+                Backend::Api { .. } => {
+                    backend.delete(
+                        &format!("{}/{}", adjustments_path(&competition_name), id),
+                        &(),
+                    ).await?;
+                },
+            }
+            backend.report_success("Adjustment was successfully removed.");
+        },
+        AdjustmentsCommand::List { competition_name } => {
+            let adjustments: Vec<Adjustment> = match backend {
+                Backend::Db { viewer_pool, .. } => {
+                    adjustment::get_adjustments(viewer_pool, &competition_name).await?
+                },
+                Backend::Api { .. } => backend.get(&adjustments_path(&competition_name)).await?,
+            };
+            print_table(
+                &["id", "player", "game", "type", "amount", "reason", "made"],
+                adjustments.into_iter().map(|adjustment| vec![
+                    adjustment.id.to_string(),
+                    adjustment.player_name.unwrap_or_default(),
+                    adjustment.site_game_id.map(|id| id.to_string()).unwrap_or_default(),
+                    adjustment.adjustment_type.to_string(),
+                    adjustment.amount.map(|amount| amount.to_string()).unwrap_or_default(),
+                    adjustment.reason,
+                    adjustment.created_at.to_rfc3339(),
+                ]).collect(),
+            );
+        },
+    }
+    Ok(())
+}
+
+async fn run_game_list_command(
+    backend: &Backend,
+    game_list: GameList,
+    game_list_command: GameListCommand,
+) -> Result<()> {
+    let game_list_name = serde_json::to_value(game_list)?.as_str().unwrap().to_owned();
+    let path = format!("/games/{}", game_list_name);
+    match game_list_command {
+        GameListCommand::Add { site_game_id, reason } => {
+            let entries = vec![GameListEntry { site_game_id, reason }];
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit(&format!("{} add", game_list_name), &entries);
+                    game::add_to_game_list(admin_pool, game_list, &entries, &audit).await?
+                },
+                Backend::Api { .. } => { backend.post(&path, &entries).await?; },
+            }
+            backend.report_success("Games were successfully listed.");
+        },
+        GameListCommand::Remove { site_game_id } => {
+            let site_game_ids = vec![site_game_id];
+            match backend {
+                Backend::Db { admin_pool, .. } => {
+                    let audit = backend.audit(&format!("{} remove", game_list_name), &site_game_ids);
+                    game::remove_from_game_list(admin_pool, game_list, &site_game_ids, &audit).await?
+                },
+                Backend::Api { .. } => { backend.delete(&path, &site_game_ids).await?; },
+            }
+            backend.report_success("Games were successfully unlisted.");
+        },
+        GameListCommand::List => {
+            let entries: Vec<GameListEntry> = match backend {
+                Backend::Db { viewer_pool, .. } => game::get_game_list(viewer_pool, game_list).await?,
+                Backend::Api { .. } => backend.get(&path).await?,
+            };
+            print_table(
+                &["game", "reason"],
+                entries.into_iter().map(|entry| vec![
+                    entry.site_game_id.to_string(),
+                    entry.reason.unwrap_or_default(),
+                ]).collect(),
+            );
+        },
+    }
+    Ok(())
+}
+
+async fn read_catalog(source: &str) -> Result<String> {
+    if !source.starts_with("http://") && !source.starts_with("https://") {
+        return Ok(fs::read_to_string(source)?);
+    }
+    let mut response = Client::default().get(source).send().await
+        .map_err(|err| anyhow!("{}", err))?;
+    if !response.status().is_success() {
+        return Err(anyhow!("{}: {}", source, response.status()));
+    }
+    let body = response.body().limit(1 << 24).await.map_err(|err| anyhow!("{}", err))?;
+    Ok(String::from_utf8(body.to_vec())?)
+}
+
+fn print_variant_sync_report(report: &VariantSyncReport) {
+    for name in &report.added {
+        println!("added: {}", name);
+    }
+    for renamed in &report.renamed {
+        println!("renamed: {} -> {}", renamed.old_name, renamed.new_name);
+    }
+    for name in &report.restored {
+        println!("restored: {}", name);
+    }
+    for name in &report.removed {
+        println!("removed: {}", name);
+    }
+    println!("{} unchanged", report.unchanged);
+}
+
+fn print_character_sync_report(report: &CharacterSyncReport) {
+    for name in &report.added {
+        println!("added: {}", name);
+    }
+    for renamed in &report.renamed {
+        println!("renamed: {} -> {}", renamed.old_name, renamed.new_name);
+    }
+    println!("{} unchanged", report.unchanged);
+}
+
+fn print_flagged_replays(flagged_replays: &[GameReplay]) {
+    let headers = ["game", "reported score", "reported turns", "score", "turns", "strikes", "end", "error"];
+    let rows = flagged_replays.iter().map(|game_replay| {
+        let outcome = game_replay.outcome.as_ref();
+        vec![
+            game_replay.site_game_id.to_string(),
+            game_replay.reported_score.to_string(),
+            game_replay.reported_turns.to_string(),
+            outcome.map_or_else(String::new, |outcome| outcome.score.to_string()),
+            outcome.map_or_else(String::new, |outcome| outcome.turns.to_string()),
+            outcome.map_or_else(String::new, |outcome| outcome.num_strikes.to_string()),
+            outcome.map_or_else(String::new, |outcome| outcome.end_condition.as_str().to_owned()),
+            game_replay.error.clone().unwrap_or_default(),
+        ]
+    }).collect();
+    print_table(&headers, rows);
+}
+
+fn print_standings(results: &CompetitionNestedResults) {
+    let competition = &results.competition_with_derived_quantities;
+    println!("{}", competition.competition_name);
+    let mut headers = vec!["rank", "players", "MP", "frac MP"];
+    headers.extend(competition.competition.base_seed_names.iter().map(String::as_str));
+    let rows = results.team_results.iter().map(|team_results| {
+        let mut players = team_results.players.iter().flatten().cloned()
+            .collect::<Vec<String>>().join(", ");
+        if !team_results.substitutes.is_empty() {
+            players = format!("{}; subs: {}", players, team_results.substitutes.join(", "));
+        }
+        let mut row = vec![
+            team_results.final_rank.to_string(),
+            match &team_results.team_name {
+                None => players,
+                Some(team_name) => format!("{} ({})", team_name, players),
+            },
+            team_results.sum_mp.to_string(),
+            format!("{:.3}", team_results.fractional_mp),
+        ];
+        row.extend(team_results.game_results.iter().map(|game_result| match game_result {
+            Some(game_result) => format!(
+                "{} in {} ({} MP)",
+                game_result.score,
+                game_result.turns,
+                game_result.seed_matchpoints,
+            ),
+            None => "".to_owned(),
+        }));
+        row
+    }).collect();
+    print_table(&headers, rows);
+    if !competition.formatted_tie_breaks.is_empty() {
+        println!("Ties on MP are broken by {}.", competition.formatted_tie_breaks);
+    }
+    for team_results in &results.team_results {
+        if let Some(tie_break_note) = &team_results.tie_break_note {
+            println!("#{}: {}", team_results.final_rank, tie_break_note);
+        }
+    }
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: Vec<&str>| cells.iter().zip(&widths)
+        .map(|(cell, &width)| format!("{:width$}", cell, width = width))
+        .collect::<Vec<String>>().join("  ").trim_end().to_owned();
+    println!("{}", format_row(headers.to_vec()));
+    println!("{}", format_row(widths.iter().map(|&width| "-".repeat(width)).collect::<Vec<String>>()
+        .iter().map(String::as_str).collect()));
+    for row in &rows {
+        println!("{}", format_row(row.iter().map(String::as_str).collect()));
+    }
+}
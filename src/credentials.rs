@@ -0,0 +1,291 @@
+// Admin credentials live in the file at ACCEPTED_API_CREDENTIALS, one admin per line:
+//     user_id:argon2 hash[:scope,scope,...]
+// Lines beginning with a # are ignored. An admin with no listed scopes may do everything.
+// The file is reread on SIGHUP, and whenever its modification time changes.
+use std::{
+    collections::HashMap,
+    fmt,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use serde::{Serialize, Deserialize};
+use rand::{RngCore, rngs::OsRng};
+use anyhow::Result;
+
+const FILE_POLL_PERIOD: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Scope {
+    // Everything, including the ones below
+    #[serde(rename = "admin")]
+    Admin,
+    // Competitions, series and variants
+    #[serde(rename = "competitions:write")]
+    CompetitionsWrite,
+    // Games, the white/blacklists, and adjustments to the standings
+    #[serde(rename = "games:write")]
+    GamesWrite,
+    // Aliases
+    #[serde(rename = "players:write")]
+    PlayersWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::Admin,
+        Scope::CompetitionsWrite,
+        Scope::GamesWrite,
+        Scope::PlayersWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Admin => "admin",
+            Scope::CompetitionsWrite => "competitions:write",
+            Scope::GamesWrite => "games:write",
+            Scope::PlayersWrite => "players:write",
+        }
+    }
+
+    pub fn is_granted_by(self, scopes: &[Scope]) -> bool {
+        scopes.contains(&Scope::Admin) || scopes.contains(&self)
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown scope: {0}")]
+pub struct UnknownScopeError(String);
+
+impl FromStr for Scope {
+    type Err = UnknownScopeError;
+
+    fn from_str(scope_str: &str) -> Result<Self, Self::Err> {
+        Scope::ALL.iter().find(|scope| scope.as_str() == scope_str).copied()
+            .ok_or_else(|| UnknownScopeError(scope_str.to_owned()))
+    }
+}
+
+#[derive(Clone)]
+pub struct Admin {
+    pub user_id: String,
+    password_hash: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl Admin {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        scope.is_granted_by(&self.scopes)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CredentialsFileError {
+    #[error("Line {0} of the credentials file isn't of the form user_id:hash[:scopes]")]
+    MalformedLine(usize),
+    #[error(
+        "Line {0} of the credentials file has a plaintext password; \
+        replace it with `hc-admin credentials set`"
+    )]
+    UnhashedPassword(usize),
+    #[error("Line {0} of the credentials file has an unknown scope: {1}")]
+    UnknownScope(usize, String),
+}
+
+// Shared by all workers, so that a reload applies to every one of them
+#[derive(Clone)]
+pub struct AdminCredentials(Arc<RwLock<CredentialsContents>>);
+
+struct CredentialsContents {
+    file_path: PathBuf,
+    file_modified: Option<SystemTime>,
+    admins: HashMap<String, Admin>,
+    // Verified against when the user doesn't exist, so that the response time doesn't give
+    // away which users do
+    dummy_hash: String,
+}
+
+impl AdminCredentials {
+    pub fn read_credentials_from_file(file_path: &str) -> Result<Self> {
+        let file_path = PathBuf::from(file_path);
+        let file_modified = fs::metadata(&file_path)?.modified().ok();
+        let admins = parse_credentials(&fs::read_to_string(&file_path)?)?;
+        Ok(Self(Arc::new(RwLock::new(CredentialsContents {
+            file_path,
+            file_modified,
+            admins,
+            dummy_hash: hash_password("")?,
+        }))))
+    }
+
+    // On error, the old credentials are kept
+    pub fn reload(&self) -> Result<()> {
+        let file_path = self.0.read().unwrap().file_path.clone();
+        let file_modified = fs::metadata(&file_path)?.modified().ok();
+        let admins = parse_credentials(&fs::read_to_string(&file_path)?)?;
+        let mut contents = self.0.write().unwrap();
+        contents.file_modified = file_modified;
+        contents.admins = admins;
+        Ok(())
+    }
+
+    // Returns whether the file was reloaded.
+    // A file that fails to parse isn't retried until it's modified again.
+    pub fn reload_if_modified(&self) -> Result<bool> {
+        let file_path = {
+            let mut contents = self.0.write().unwrap();
+            let file_modified = fs::metadata(&contents.file_path)?.modified().ok();
+            if file_modified == contents.file_modified {
+                return Ok(false);
+            }
+            contents.file_modified = file_modified;
+            contents.file_path.clone()
+        };
+        let admins = parse_credentials(&fs::read_to_string(&file_path)?)?;
+        self.0.write().unwrap().admins = admins;
+        Ok(true)
+    }
+
+    pub fn watch_for_changes(&self) {
+        let credentials = self.clone();
+        actix_rt::spawn(async move {
+            use actix_rt::signal::unix::{signal, SignalKind};
+            let mut hangups = match signal(SignalKind::hangup()) {
+                Ok(hangups) => hangups,
+                Err(err) => {
+                    error!("Couldn't listen for SIGHUP: {}", err);
+                    return;
+                },
+            };
+            while hangups.recv().await.is_some() {
+                match credentials.reload() {
+                    Ok(_) => info!("Reloaded admin credentials"),
+                    Err(err) => error!("Couldn't reload admin credentials, keeping the old ones: {}", err),
+                }
+            }
+        });
+        let credentials = self.clone();
+        actix_rt::spawn(async move {
+            let mut ticks = actix_rt::time::interval(FILE_POLL_PERIOD);
+            loop {
+                ticks.tick().await;
+                match credentials.reload_if_modified() {
+                    Ok(true) => info!("Reloaded modified admin credentials"),
+                    Ok(false) => (),
+                    Err(err) => error!("Couldn't reload admin credentials, keeping the old ones: {}", err),
+                }
+            }
+        });
+    }
+
+    // Returns the admin whose credentials these are, if any
+    pub fn verify(&self, user_id: &str, password: &str) -> Option<Admin> {
+        let contents = self.0.read().unwrap();
+        let admin = contents.admins.get(user_id);
+        let password_hash = admin.map_or(&contents.dummy_hash, |admin| &admin.password_hash);
+        // argon2 compares the hashes in constant time
+        let is_password_valid = argon2::verify_encoded(password_hash, password.as_bytes())
+            .unwrap_or(false);
+        match admin {
+            Some(admin) if is_password_valid => Some(admin.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn get_admin(&self, user_id: &str) -> Option<Admin> {
+        self.0.read().unwrap().admins.get(user_id).cloned()
+    }
+}
+
+pub fn hash_password(password: &str) -> Result<String> {
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let config = argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        ..argon2::Config::default()
+    };
+    Ok(argon2::hash_encoded(password.as_bytes(), &salt, &config)?)
+}
+
+pub fn format_credentials_line(user_id: &str, password_hash: &str, scopes: &[Scope]) -> String {
+    if scopes.is_empty() {
+        format!("{}:{}", user_id, password_hash)
+    } else {
+        let scope_strs: Vec<&str> = scopes.iter().map(Scope::as_str).collect();
+        format!("{}:{}:{}", user_id, password_hash, scope_strs.join(","))
+    }
+}
+
+// Replaces the user's line, or appends one if they don't have one yet.
+// Comments and other users' lines are left as they are.
+pub fn set_credentials_line(file_contents: &str, user_id: &str, new_line: &str) -> String {
+    let mut lines: Vec<&str> = file_contents.lines().collect();
+    match lines.iter().position(|line| line_user_id(line) == Some(user_id)) {
+        Some(idx) => lines[idx] = new_line,
+        None => lines.push(new_line),
+    }
+    lines.join("\n") + "\n"
+}
+
+// Returns None if the user has no line
+pub fn remove_credentials_line(file_contents: &str, user_id: &str) -> Option<String> {
+    let lines: Vec<&str> = file_contents.lines().collect();
+    let remaining_lines: Vec<&str> = lines.iter()
+        .filter(|line| line_user_id(line) != Some(user_id))
+        .copied().collect();
+    if remaining_lines.len() == lines.len() {
+        return None;
+    }
+    Some(remaining_lines.join("\n") + "\n")
+}
+
+fn line_user_id(line: &str) -> Option<&str> {
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    line.split(':').next()
+}
+
+fn parse_credentials(file_contents: &str) -> Result<HashMap<String, Admin>, CredentialsFileError> {
+    let mut admins = HashMap::new();
+    for (idx, line) in file_contents.lines().enumerate() {
+        let line_num = idx + 1;
+        // Ignore empty lines and lines beginning with a #
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // argon2 hashes don't contain colons, though scopes do
+        let tokens: Vec<&str> = line.splitn(3, ':').collect();
+        let (user_id, password_hash, scopes_str) = match tokens.as_slice() {
+            [user_id, password_hash] => (*user_id, *password_hash, ""),
+            [user_id, password_hash, scopes_str] => (*user_id, *password_hash, *scopes_str),
+            _ => return Err(CredentialsFileError::MalformedLine(line_num)),
+        };
+        if user_id.is_empty() {
+            return Err(CredentialsFileError::MalformedLine(line_num));
+        }
+        if !password_hash.starts_with("$argon2") {
+            return Err(CredentialsFileError::UnhashedPassword(line_num));
+        }
+        let scopes = scopes_str.split(',')
+            .filter(|scope_str| !scope_str.is_empty())
+            .map(|scope_str| scope_str.trim().parse()
+                .map_err(|_| CredentialsFileError::UnknownScope(line_num, scope_str.to_owned())))
+            .collect::<Result<Vec<Scope>, CredentialsFileError>>()?;
+        let scopes = if scopes.is_empty() { vec![Scope::Admin] } else { scopes };
+        admins.insert(user_id.to_owned(), Admin {
+            user_id: user_id.to_owned(),
+            password_hash: password_hash.to_owned(),
+            scopes,
+        });
+    }
+    Ok(admins)
+}
@@ -0,0 +1,628 @@
+// A Hanabi rules engine, for replaying the games exported from hanab.live and checking what was
+// reported for them. It only knows the rules of the common variants; games in any other variant
+// are reported as unsupported rather than guessed at.
+use serde::{Serialize, Deserialize};
+use crate::model::{game_export::GameExport, variant::VariantMetadata};
+
+const MAX_CLUE_TOKENS: i16 = 8;
+const MAX_STRIKES: i16 = 3;
+const MAX_RANK: i16 = 5;
+
+// The flags from variants.json that are accounted for below, or that don't change the rules
+const SUPPORTED_FLAGS: &[&str] = &[
+    "showSuitNames",
+    "specialAllClueColors",
+    "specialAllClueRanks",
+    "specialNoClueColors",
+    "specialNoClueRanks",
+    "colorCluesTouchNothing",
+    "rankCluesTouchNothing",
+    "clueStarved",
+    "throwItInAHole",
+];
+
+// Suits that are touched by a clue color of their own name
+const BASIC_SUITS: &[&str] = &[
+    "Red", "Yellow", "Green", "Blue", "Purple", "Teal", "Black", "Pink", "Brown",
+];
+const ALL_COLORS_SUITS: &[&str] = &[
+    "Rainbow", "Dark Rainbow", "Omni", "Dark Omni", "Muddy Rainbow", "Cocoa Rainbow",
+];
+const NO_COLORS_SUITS: &[&str] = &["White", "Gray", "Null", "Dark Null", "Light Pink", "Gray Pink"];
+const ALL_RANKS_SUITS: &[&str] = &["Pink", "Omni", "Dark Omni", "Light Pink", "Gray Pink"];
+const NO_RANKS_SUITS: &[&str] = &[
+    "Brown", "Null", "Dark Null", "Muddy Rainbow", "Cocoa Rainbow",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Touch {
+    Own,
+    All,
+    Nothing,
+}
+
+#[derive(Clone, Debug)]
+struct SuitRules {
+    // index into the variant's clue colors, for suits touched by their own color
+    color_index: Option<usize>,
+    color_touch: Touch,
+    rank_touch: Touch,
+    // played from 5 down to 1
+    reversed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct VariantRules {
+    suits: Vec<SuitRules>,
+    num_clue_colors: usize,
+    clue_ranks: Vec<i16>,
+    special_rank: Option<i16>,
+    special_color_touch: Touch,
+    special_rank_touch: Touch,
+    color_clues_touch_nothing: bool,
+    rank_clues_touch_nothing: bool,
+    clue_starved: bool,
+}
+
+impl VariantRules {
+    pub fn new(variant: &VariantMetadata) -> Result<Self, ReplayError> {
+        if variant.suits.is_empty() {
+            return Err(ReplayError::UnsupportedVariant(
+                "the variant's suits are unknown; sync the variants".to_owned()));
+        }
+        if let Some(flag) = variant.flags.iter()
+            .find(|flag| !SUPPORTED_FLAGS.contains(&flag.as_str()))
+        {
+            return Err(ReplayError::UnsupportedVariant(flag.clone()));
+        }
+        let base_names: Vec<(&str, bool)> = variant.suits.iter().map(|suit| {
+            match suit.strip_suffix(" Reversed") {
+                Some(base_name) => (base_name, true),
+                None => (suit.as_str(), false),
+            }
+        }).collect();
+        // the site derives the clue colors from the suits, unless the variant lists them
+        let clue_colors: Vec<String> = match &variant.clue_colors {
+            Some(clue_colors) => clue_colors.clone(),
+            None => {
+                let mut clue_colors: Vec<String> = Vec::new();
+                for (base_name, _) in &base_names {
+                    if BASIC_SUITS.contains(base_name) && !clue_colors.iter().any(|c| c == base_name) {
+                        clue_colors.push(base_name.to_string());
+                    }
+                }
+                clue_colors
+            },
+        };
+        let mut suits = Vec::new();
+        for (base_name, reversed) in base_names {
+            let color_touch = if ALL_COLORS_SUITS.contains(&base_name) {
+                Touch::All
+            } else if NO_COLORS_SUITS.contains(&base_name) {
+                Touch::Nothing
+            } else {
+                Touch::Own
+            };
+            let color_index = clue_colors.iter().position(|color| color == base_name);
+            if color_touch == Touch::Own
+                && (color_index.is_none() || !BASIC_SUITS.contains(&base_name))
+            {
+                return Err(ReplayError::UnsupportedVariant(format!("{} suit", base_name)));
+            }
+            let rank_touch = if ALL_RANKS_SUITS.contains(&base_name) {
+                Touch::All
+            } else if NO_RANKS_SUITS.contains(&base_name) {
+                Touch::Nothing
+            } else {
+                Touch::Own
+            };
+            suits.push(SuitRules { color_index, color_touch, rank_touch, reversed });
+        }
+        let has_flag = |name: &str| variant.flags.iter().any(|flag| flag == name);
+        Ok(Self {
+            suits,
+            num_clue_colors: clue_colors.len(),
+            clue_ranks: variant.clue_ranks.clone(),
+            special_rank: variant.special_rank,
+            special_color_touch: if has_flag("specialAllClueColors") {
+                Touch::All
+            } else if has_flag("specialNoClueColors") {
+                Touch::Nothing
+            } else {
+                Touch::Own
+            },
+            special_rank_touch: if has_flag("specialAllClueRanks") {
+                Touch::All
+            } else if has_flag("specialNoClueRanks") {
+                Touch::Nothing
+            } else {
+                Touch::Own
+            },
+            color_clues_touch_nothing: has_flag("colorCluesTouchNothing"),
+            rank_clues_touch_nothing: has_flag("rankCluesTouchNothing"),
+            clue_starved: has_flag("clueStarved"),
+        })
+    }
+
+    pub fn max_score(&self) -> i16 {
+        self.suits.len() as i16 * MAX_RANK
+    }
+
+    fn touches(&self, clue: Clue, card: Card) -> bool {
+        let suit = &self.suits[card.suit_index];
+        let is_special = self.special_rank == Some(card.rank);
+        match clue {
+            Clue::Color(color_index) => {
+                if self.color_clues_touch_nothing {
+                    return false;
+                }
+                let touch = if is_special && self.special_color_touch != Touch::Own {
+                    self.special_color_touch
+                } else {
+                    suit.color_touch
+                };
+                match touch {
+                    Touch::All => true,
+                    Touch::Nothing => false,
+                    Touch::Own => suit.color_index == Some(color_index),
+                }
+            },
+            Clue::Rank(rank) => {
+                if self.rank_clues_touch_nothing {
+                    return false;
+                }
+                let touch = if is_special && self.special_rank_touch != Touch::Own {
+                    self.special_rank_touch
+                } else {
+                    suit.rank_touch
+                };
+                match touch {
+                    Touch::All => true,
+                    Touch::Nothing => false,
+                    Touch::Own => card.rank == rank,
+                }
+            },
+        }
+    }
+}
+
+// The parts of a competition's ruleset that change what's legal
+#[derive(Clone, Copy, Debug)]
+pub struct Ruleset {
+    pub deck_plays: bool,
+    pub empty_clues: bool,
+}
+
+// Numbered as on hanab.live, whose game-over actions carry one of these
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EndCondition {
+    InProgress,
+    Normal,
+    Strikeout,
+    Timeout,
+    Terminated,
+    SpeedrunFail,
+    IdleTimeout,
+    CharacterSoftlock,
+    AllOrNothingFail,
+    AllOrNothingSoftlock,
+    TerminatedByVote,
+}
+
+// In the order of their numbers on the site
+const END_CONDITIONS: [EndCondition; 11] = [
+    EndCondition::InProgress,
+    EndCondition::Normal,
+    EndCondition::Strikeout,
+    EndCondition::Timeout,
+    EndCondition::Terminated,
+    EndCondition::SpeedrunFail,
+    EndCondition::IdleTimeout,
+    EndCondition::CharacterSoftlock,
+    EndCondition::AllOrNothingFail,
+    EndCondition::AllOrNothingSoftlock,
+    EndCondition::TerminatedByVote,
+];
+
+impl EndCondition {
+    fn from_site_value(value: i32) -> Option<Self> {
+        if value < 0 {
+            return None;
+        }
+        END_CONDITIONS.get(value as usize).copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndCondition::InProgress => "in_progress",
+            EndCondition::Normal => "normal",
+            EndCondition::Strikeout => "strikeout",
+            EndCondition::Timeout => "timeout",
+            EndCondition::Terminated => "terminated",
+            EndCondition::SpeedrunFail => "speedrun_fail",
+            EndCondition::IdleTimeout => "idle_timeout",
+            EndCondition::CharacterSoftlock => "character_softlock",
+            EndCondition::AllOrNothingFail => "all_or_nothing_fail",
+            EndCondition::AllOrNothingSoftlock => "all_or_nothing_softlock",
+            EndCondition::TerminatedByVote => "terminated_by_vote",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        END_CONDITIONS.iter().find(|end_condition| end_condition.as_str() == name).copied()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplayOutcome {
+    // as the site scores it, i.e. 0 unless the game ended normally
+    pub score: i16,
+    // the score reached before the game ended, however it ended, which is what the standings
+    // credit strikeouts and terminations with
+    pub pre_strikeout_score: i16,
+    pub turns: i16,
+    pub num_strikes: i16,
+    pub end_condition: EndCondition,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("Can't replay this variant: {0}")]
+    UnsupportedVariant(String),
+    #[error("Can't replay games with this option: {0}")]
+    UnsupportedOption(String),
+    #[error("Malformed export: {0}")]
+    MalformedExport(String),
+    #[error("Illegal action on turn {turn}: {reason}")]
+    IllegalAction {
+        turn: usize,
+        reason: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Card {
+    suit_index: usize,
+    rank: i16,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Clue {
+    Color(usize),
+    Rank(i16),
+}
+
+struct GameState<'a> {
+    variant: &'a VariantRules,
+    deck: Vec<Card>,
+    next_card: usize,
+    // cards by their order in the deck
+    hands: Vec<Vec<usize>>,
+    // how many cards of each suit have been played
+    stacks: Vec<i16>,
+    // in halves, since discards only give half a clue in clue-starved variants
+    half_clue_tokens: i16,
+    num_strikes: i16,
+    num_turns: i16,
+    // the turn count at which the final round is over, once the deck has run out
+    final_turn: Option<i16>,
+}
+
+impl<'a> GameState<'a> {
+    fn stacks_score(&self) -> i16 {
+        self.stacks.iter().sum()
+    }
+
+    fn draw(&mut self, player: usize) {
+        if self.next_card < self.deck.len() {
+            self.hands[player].push(self.next_card);
+            self.next_card += 1;
+        }
+    }
+
+    fn gain_clue(&mut self) {
+        let gain = if self.variant.clue_starved { 1 } else { 2 };
+        self.half_clue_tokens = (self.half_clue_tokens + gain).min(2 * MAX_CLUE_TOKENS);
+    }
+
+    fn is_playable(&self, card: Card) -> bool {
+        let num_played = self.stacks[card.suit_index];
+        let next_rank = if self.variant.suits[card.suit_index].reversed {
+            MAX_RANK - num_played
+        } else {
+            num_played + 1
+        };
+        num_played < MAX_RANK && card.rank == next_rank
+    }
+
+    fn play(&mut self, card_order: usize) {
+        let card = self.deck[card_order];
+        if self.is_playable(card) {
+            self.stacks[card.suit_index] += 1;
+            if self.stacks[card.suit_index] == MAX_RANK {
+                self.gain_clue();
+            }
+        } else {
+            self.num_strikes += 1;
+        }
+    }
+
+    // Takes the card out of the player's hand, or off the deck for a deck play
+    fn take_card(
+        &mut self,
+        player: usize,
+        card_order: usize,
+        deck_plays: bool,
+    ) -> Result<(), String> {
+        if let Some(idx) = self.hands[player].iter().position(|&order| order == card_order) {
+            self.hands[player].remove(idx);
+            self.draw(player);
+            Ok(())
+        } else if deck_plays && self.next_card + 1 == self.deck.len() && card_order == self.next_card {
+            self.next_card += 1;
+            Ok(())
+        } else {
+            Err(format!("card {} isn't in the player's hand", card_order))
+        }
+    }
+}
+
+pub fn replay(
+    export: &GameExport,
+    variant: &VariantRules,
+    ruleset: &Ruleset,
+) -> Result<ReplayOutcome, ReplayError> {
+    let num_players = export.players.len();
+    if !(2..=6).contains(&num_players) {
+        return Err(ReplayError::MalformedExport(format!("{} players", num_players)));
+    }
+    if export.options.all_or_nothing == Some(true) {
+        return Err(ReplayError::UnsupportedOption("allOrNothing".to_owned()));
+    }
+    let mut deck = Vec::new();
+    for card in &export.deck {
+        if card.suit_index < 0 || card.suit_index as usize >= variant.suits.len()
+            || card.rank < 1 || card.rank > MAX_RANK
+        {
+            return Err(ReplayError::MalformedExport(format!(
+                "no such card in the variant: suit {}, rank {}", card.suit_index, card.rank)));
+        }
+        deck.push(Card { suit_index: card.suit_index as usize, rank: card.rank });
+    }
+    let mut hand_size: usize = match num_players {
+        2 | 3 => 5,
+        4 | 5 => 4,
+        _ => 3,
+    };
+    if export.options.one_extra_card == Some(true) {
+        hand_size += 1;
+    }
+    if export.options.one_less_card == Some(true) {
+        hand_size -= 1;
+    }
+    if deck.len() < num_players * hand_size {
+        return Err(ReplayError::MalformedExport("not enough cards to deal".to_owned()));
+    }
+    let mut game = GameState {
+        variant,
+        deck,
+        next_card: 0,
+        hands: vec![Vec::new(); num_players],
+        stacks: vec![0; variant.suits.len()],
+        half_clue_tokens: 2 * MAX_CLUE_TOKENS,
+        num_strikes: 0,
+        num_turns: 0,
+        final_turn: None,
+    };
+    for player in 0..num_players {
+        for _ in 0..hand_size {
+            game.draw(player);
+        }
+    }
+    let mut player = export.options.starting_player.unwrap_or(0) as usize % num_players;
+    let mut end_condition = None;
+    for (turn, action) in export.actions.iter().enumerate() {
+        let illegal = |reason: String| ReplayError::IllegalAction { turn: turn + 1, reason };
+        if end_condition.is_some() {
+            // the site may or may not record a game-over action after a game ends on its own
+            if action.action_type == 4 {
+                continue;
+            }
+            return Err(illegal("the game was already over".to_owned()));
+        }
+        let target = action.target;
+        match action.action_type {
+            // play
+            0 => {
+                if target < 0 {
+                    return Err(illegal(format!("no card {}", target)));
+                }
+                game.take_card(player, target as usize, ruleset.deck_plays).map_err(illegal)?;
+                game.play(target as usize);
+            },
+            // discard
+            1 => {
+                if game.half_clue_tokens == 2 * MAX_CLUE_TOKENS {
+                    return Err(illegal("discarded at the maximum number of clues".to_owned()));
+                }
+                if target < 0 {
+                    return Err(illegal(format!("no card {}", target)));
+                }
+                game.take_card(player, target as usize, false).map_err(illegal)?;
+                game.gain_clue();
+            },
+            // color or rank clue
+            2 | 3 => {
+                if game.half_clue_tokens < 2 {
+                    return Err(illegal("clued without a clue token".to_owned()));
+                }
+                if target < 0 || target as usize >= num_players || target as usize == player {
+                    return Err(illegal(format!("can't clue player {}", target)));
+                }
+                let value = action.value.unwrap_or(-1);
+                let clue = if action.action_type == 2 {
+                    if value < 0 || value as usize >= variant.num_clue_colors {
+                        return Err(illegal(format!("no clue color {}", value)));
+                    }
+                    Clue::Color(value as usize)
+                } else {
+                    if !variant.clue_ranks.contains(&(value as i16)) {
+                        return Err(illegal(format!("no rank clue {}", value)));
+                    }
+                    Clue::Rank(value as i16)
+                };
+                let touches_nothing = match clue {
+                    Clue::Color(_) => variant.color_clues_touch_nothing,
+                    Clue::Rank(_) => variant.rank_clues_touch_nothing,
+                };
+                if !ruleset.empty_clues && !touches_nothing && !game.hands[target as usize].iter()
+                    .any(|&order| variant.touches(clue, game.deck[order]))
+                {
+                    return Err(illegal("the clue touches no cards".to_owned()));
+                }
+                game.half_clue_tokens -= 2;
+            },
+            // game over, e.g. a timeout or a termination
+            4 => {
+                end_condition = Some(EndCondition::from_site_value(action.value.unwrap_or(-1))
+                    .ok_or_else(|| ReplayError::MalformedExport(format!(
+                        "unknown end condition {:?}", action.value)))?);
+                continue;
+            },
+            other => {
+                return Err(ReplayError::MalformedExport(format!("unknown action type {}", other)));
+            },
+        }
+        game.num_turns += 1;
+        if game.final_turn.is_none() && game.next_card == game.deck.len() {
+            // everyone gets one more turn, starting with the next player
+            game.final_turn = Some(game.num_turns + num_players as i16);
+        }
+        if game.num_strikes == MAX_STRIKES {
+            end_condition = Some(EndCondition::Strikeout);
+        } else if game.stacks_score() == variant.max_score()
+            || game.final_turn == Some(game.num_turns)
+        {
+            end_condition = Some(EndCondition::Normal);
+        }
+        player = (player + 1) % num_players;
+    }
+    let end_condition = end_condition.unwrap_or(EndCondition::InProgress);
+    Ok(ReplayOutcome {
+        score: match end_condition {
+            EndCondition::Normal | EndCondition::InProgress => game.stacks_score(),
+            _ => 0,
+        },
+        // a play that earns a strike doesn't reach the stacks
+        pre_strikeout_score: game.stacks_score(),
+        turns: game.num_turns,
+        num_strikes: game.num_strikes,
+        end_condition,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    // Dealt in this order, so that playing through the first 25 cards in turn is a perfect game
+    const DECK: &str = "r1 g1 p1 y2 b2 y1 b1 r2 g2 p2 r3 y3 g3 b3 p3 r4 y4 g4 b4 p4 r5 y5 g5 b5 p5 \
+        r1 r1 r2 r3 r4 y1 y1 y2 y3 y4 g1 g1 g2 g3 g4 b1 b1 b2 b3 b4 p1 p1 p2 p3 p4";
+
+    fn no_variant() -> VariantRules {
+        VariantRules::new(&VariantMetadata {
+            site_variant_id: 0,
+            suits: ["Red", "Yellow", "Green", "Blue", "Purple"].iter()
+                .map(|suit| suit.to_string())
+                .collect(),
+            clue_colors: None,
+            clue_ranks: vec![1, 2, 3, 4, 5],
+            special_rank: None,
+            max_score: Some(25),
+            flags: Vec::new(),
+            removed: false,
+        }).unwrap()
+    }
+
+    // A 2-player export of the deck above, in the site's format
+    fn export(actions: serde_json::Value) -> GameExport {
+        let deck: Vec<serde_json::Value> = DECK.split_whitespace().map(|card| json!({
+            "suitIndex": "rygbp".find(&card[..1]).unwrap(),
+            "rank": card[1..].parse::<i16>().unwrap(),
+        })).collect();
+        serde_json::from_value(json!({
+            "id": 1,
+            "players": ["alice", "bob"],
+            "deck": deck,
+            "actions": actions,
+            "options": {"variant": "No Variant"},
+            "seed": "p2v0s1",
+        })).unwrap()
+    }
+
+    fn plays(card_orders: &[i32]) -> Vec<serde_json::Value> {
+        card_orders.iter().map(|card_order| json!({"type": 0, "target": card_order})).collect()
+    }
+
+    const RULESET: Ruleset = Ruleset { deck_plays: false, empty_clues: false };
+
+    #[test]
+    fn replays_export_to_its_recorded_outcome() {
+        // the 24 cards before the purple 5, two misplays of duplicate red 1s, then the purple 5
+        let mut card_orders = vec![0, 5, 1, 6, 2, 7, 3, 8, 4, 9];
+        card_orders.extend(10..24);
+        card_orders.extend(&[26, 25, 24]);
+        let outcome = replay(&export(json!(plays(&card_orders))), &no_variant(), &RULESET)
+            .unwrap();
+        assert_eq!(outcome.end_condition, EndCondition::Normal);
+        assert_eq!(outcome.score, 25);
+        assert_eq!(outcome.pre_strikeout_score, 25);
+        assert_eq!(outcome.turns, 27);
+        assert_eq!(outcome.num_strikes, 2);
+    }
+
+    #[test]
+    fn strikeout_scores_zero_but_keeps_pre_strikeout_score() {
+        let outcome = replay(&export(json!(plays(&[0, 9, 4, 8]))), &no_variant(), &RULESET).unwrap();
+        assert_eq!(outcome.end_condition, EndCondition::Strikeout);
+        assert_eq!(outcome.score, 0);
+        assert_eq!(outcome.pre_strikeout_score, 1);
+        assert_eq!(outcome.turns, 4);
+        assert_eq!(outcome.num_strikes, 3);
+    }
+
+    #[test]
+    fn termination_ends_game_without_taking_a_turn() {
+        let mut actions = plays(&[0, 5]);
+        actions.push(json!({"type": 4, "target": 0, "value": 4}));
+        let outcome = replay(&export(json!(actions)), &no_variant(), &RULESET).unwrap();
+        assert_eq!(outcome.end_condition, EndCondition::Terminated);
+        assert_eq!(outcome.score, 0);
+        assert_eq!(outcome.pre_strikeout_score, 2);
+        assert_eq!(outcome.turns, 2);
+        assert_eq!(outcome.num_strikes, 0);
+    }
+
+    #[test]
+    fn clue_without_tokens_is_illegal() {
+        // red clues back and forth use up all 8 tokens
+        let actions: Vec<serde_json::Value> = (0..9)
+            .map(|turn| json!({"type": 2, "target": (turn + 1) % 2, "value": 0}))
+            .collect();
+        match replay(&export(json!(actions)), &no_variant(), &RULESET) {
+            Err(ReplayError::IllegalAction { turn, .. }) => assert_eq!(turn, 9),
+            other => panic!("expected an illegal action, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn playing_card_not_in_hand_is_illegal() {
+        // card 5 was dealt to bob
+        match replay(&export(json!(plays(&[5]))), &no_variant(), &RULESET) {
+            Err(ReplayError::IllegalAction { turn, .. }) => assert_eq!(turn, 1),
+            other => panic!("expected an illegal action, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+#![recursion_limit="60"]
+#[macro_use]
+extern crate log;
+
+pub mod routes;
+pub mod model;
+pub mod page_cache;
+pub mod credentials;
+pub mod session;
+pub mod scheduler;
+pub mod hanabi;
+
+use sqlx::PgPool;
+use std::env;
+
+// These newtypes are a measure to guard against exposing the db admin role
+// to website viewers.
+#[derive(Clone)]
+pub struct DbViewerPool(pub PgPool);
+#[derive(Clone)]
+pub struct DbAdminPool(pub PgPool);
+
+// Convenient pattern for erroring out on missing env var
+pub fn get_expected_env_var(name: &str) -> String {
+    env::var(name).unwrap_or_else(|_| panic!("{} must be set (check `.env`)", name))
+}
@@ -1,28 +1,23 @@
-#![recursion_limit="60"]
 #[macro_use]
 extern crate log;
 
-mod routes;
-mod model;
-
 use dotenv::dotenv;
 use listenfd::ListenFd;
-use actix_web::{web, App, HttpResponse, HttpRequest, HttpServer, FromRequest, http::header};
+use actix_web::{web, App, HttpResponse, HttpRequest, HttpServer, http::header};
 use sqlx::PgPool;
 use std::env;
 use anyhow::Result;
-
-// These newtypes are a measure to guard against exposing the db admin role
-// to website viewers.
-#[derive(Clone)]
-pub struct DbViewerPool(pub PgPool);
-#[derive(Clone)]
-pub struct DbAdminPool(pub PgPool);
-
-// Convenient pattern for erroring out on missing env var
-fn get_expected_env_var(name: &str) -> String {
-    env::var(name).expect(&*format!("{} must be set (check `.env`)", name))
-}
+use hanabi_live_competitions::{
+    credentials,
+    get_expected_env_var,
+    model,
+    page_cache,
+    routes,
+    scheduler,
+    session,
+    DbAdminPool,
+    DbViewerPool,
+};
 
 #[actix_rt::main]
 async fn main() -> Result<()> {
@@ -33,31 +28,71 @@ async fn main() -> Result<()> {
     let database_admin_url = get_expected_env_var("DATABASE_ADMIN_URL");
     let db_viewer_pool = DbViewerPool(PgPool::new(&database_viewer_url).await?);
     let db_admin_pool = DbAdminPool(PgPool::new(&database_admin_url).await?);
+
+    // Schema management subcommands run instead of the server
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        [] => (),
+        ["migrate"] => {
+            for version in model::migration::migrate_to_latest(&db_admin_pool).await? {
+                info!("Applied schema version {}", version);
+            }
+            return Ok(());
+        },
+        ["rollback", version] => {
+            for version in model::migration::rollback_to(&db_admin_pool, version).await? {
+                info!("Rolled back schema version {}", version);
+            }
+            return Ok(());
+        },
+        ["baseline", version] => {
+            model::migration::baseline(&db_admin_pool, version).await?;
+            info!("Recorded schema version {} as applied", version);
+            return Ok(());
+        },
+        ["schema-version"] => {
+            match model::migration::get_schema_version(&db_admin_pool).await? {
+                Some(version) => println!("{}", version),
+                None => println!("no schema"),
+            }
+            return Ok(());
+        },
+        _ => {
+            eprintln!("usage: {} [migrate | rollback <version> | baseline <version> | schema-version]",
+                env!("CARGO_PKG_NAME"));
+            std::process::exit(2);
+        },
+    }
+    // Refuses to start against a schema newer than this build
+    for version in model::migration::migrate_to_latest(&db_admin_pool).await? {
+        info!("Applied schema version {}", version);
+    }
     let admin_credentials_file_path = get_expected_env_var("ACCEPTED_API_CREDENTIALS");
-    let admin_credentials = routes::AdminCredentials::read_credentials_from_file(
+    let admin_credentials = credentials::AdminCredentials::read_credentials_from_file(
         &admin_credentials_file_path)
-        .expect(&format!("No file found at path: {}", admin_credentials_file_path));
+        .unwrap_or_else(|err| panic!(
+            "Couldn't read admin credentials from {}: {}", admin_credentials_file_path, err));
+    admin_credentials.watch_for_changes();
     let mut handlebars = handlebars::Handlebars::new();
     handlebars
         .register_templates_directory(".html", "./static/templates")
         .unwrap();
     let handlebars_ref = web::Data::new(handlebars);
+    // shared by all workers, so that an admin write invalidates every worker's pages
+    let page_cache = page_cache::PageCache::new();
+    let admin_sessions = session::AdminSessions::default();
+    scheduler::create_scheduled_competitions_periodically(db_admin_pool.clone(), page_cache.clone());
 
     let mut server = HttpServer::new(move || {
         App::new()
             .data(db_viewer_pool.clone())
             .data(db_admin_pool.clone())
             .data(admin_credentials.clone())
-            .app_data(
-                // change json extractor configuration
-                web::Json::<Vec<model::variant::Variant>>::configure(|cfg| {
-                    cfg.limit(100000)
-            }))
-            .app_data(
-                // change json extractor configuration
-                web::Json::<Vec<model::game::CompetitionGames>>::configure(|cfg| {
-                    cfg.limit(100000)
-            }))
+            .data(page_cache.clone())
+            .data(admin_sessions.clone())
+            // there's one json extractor configuration per app or resource, whatever the
+            // payload's type, so routes that take larger payloads set their own in routes::init
+            .app_data(web::JsonConfig::default().limit(routes::DEFAULT_JSON_LIMIT))
             .app_data(handlebars_ref.clone())
             .configure(routes::init)
             // static route handling
@@ -1,8 +1,21 @@
 pub mod competition;
 pub mod game;
+pub mod game_export;
 pub mod variant;
 pub mod result;
 pub mod series;
+pub mod player;
+pub mod migration;
+pub mod api_token;
+pub mod audit;
+pub mod schedule;
+pub mod planner;
+pub mod replay;
+pub mod compliance;
+pub mod character;
+pub mod team;
+pub mod adjustment;
+pub mod tie_break;
 
 pub type UtcDateTime = chrono::DateTime<chrono::offset::Utc>;
 pub type Date = chrono::NaiveDate;
@@ -0,0 +1,285 @@
+// Admin adjustments to a competition's standings, for when deleting games isn't the right tool:
+// matchpoint penalties, scores awarded after a server bug, and disqualifications
+use std::{fmt, str::FromStr};
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use crate::{
+    DbAdminPool,
+    DbViewerPool,
+    model::{
+        Tx,
+        UtcDateTime,
+        audit::{commit_audited, AuditContext},
+        competition::update_competition_standings,
+    },
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AdjustmentType {
+    // deducts `amount` matchpoints from a player's total, or from a game's seed matchpoints
+    Penalty,
+    // `amount` counts as the game's score
+    AdjustedScore,
+    // the player's games, or the game, don't count
+    Disqualification,
+}
+
+impl AdjustmentType {
+    pub const ALL: [AdjustmentType; 3] = [
+        AdjustmentType::Penalty,
+        AdjustmentType::AdjustedScore,
+        AdjustmentType::Disqualification,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdjustmentType::Penalty => "penalty",
+            AdjustmentType::AdjustedScore => "adjusted_score",
+            AdjustmentType::Disqualification => "disqualification",
+        }
+    }
+}
+
+impl fmt::Display for AdjustmentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown adjustment type: {0}")]
+pub struct UnknownAdjustmentTypeError(String);
+
+impl FromStr for AdjustmentType {
+    type Err = UnknownAdjustmentTypeError;
+
+    fn from_str(adjustment_type_str: &str) -> Result<Self, Self::Err> {
+        AdjustmentType::ALL.iter()
+            .find(|adjustment_type| adjustment_type.as_str() == adjustment_type_str).copied()
+            .ok_or_else(|| UnknownAdjustmentTypeError(adjustment_type_str.to_owned()))
+    }
+}
+
+// Either a player or a game, by site game ID
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdjustmentRequest {
+    pub player_name: Option<String>,
+    pub site_game_id: Option<i64>,
+    pub adjustment_type: AdjustmentType,
+    pub amount: Option<i16>,
+    pub reason: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Adjustment {
+    pub id: i32,
+    pub player_name: Option<String>,
+    pub site_game_id: Option<i64>,
+    pub adjustment_type: AdjustmentType,
+    pub amount: Option<i16>,
+    pub reason: String,
+    pub created_at: UtcDateTime,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AdjustmentError {
+    #[error("No competition with that name was found")]
+    CompetitionNotFound,
+    #[error("An adjustment is for either a player or a game")]
+    Target,
+    #[error("A penalty needs a positive amount of matchpoints")]
+    PenaltyAmount,
+    #[error("An adjusted score is for a game, and needs a score")]
+    AdjustedScore,
+    #[error("A disqualification doesn't have an amount")]
+    DisqualificationAmount,
+    #[error("An adjustment needs a reason")]
+    MissingReason,
+    #[error("No player named {0} was found")]
+    PlayerNotFound(String),
+    #[error("Game {0} wasn't played on one of the competition's seeds")]
+    GameNotFound(i64),
+    #[error("Game {0}'s variant has a max score of {1}")]
+    AdjustedScoreAboveMax(i64, i16),
+    #[error("Game {0} already has an adjusted score")]
+    DuplicateAdjustedScore(i64),
+    #[error("No adjustment {0} was found for the competition")]
+    AdjustmentNotFound(i32),
+}
+
+impl AdjustmentRequest {
+    fn validate(&self) -> Result<(), AdjustmentError> {
+        if self.player_name.is_some() == self.site_game_id.is_some() {
+            return Err(AdjustmentError::Target);
+        }
+        if self.reason.trim().is_empty() {
+            return Err(AdjustmentError::MissingReason);
+        }
+        match self.adjustment_type {
+            AdjustmentType::Penalty if self.amount.is_none_or(|amount| amount <= 0) => {
+                Err(AdjustmentError::PenaltyAmount)
+            },
+            AdjustmentType::AdjustedScore
+                if self.site_game_id.is_none() || self.amount.is_none_or(|amount| amount < 0) =>
+            {
+                Err(AdjustmentError::AdjustedScore)
+            },
+            AdjustmentType::Disqualification if self.amount.is_some() => {
+                Err(AdjustmentError::DisqualificationAmount)
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+async fn get_competition_id(mut tx: Tx, competition_name: &str) -> Result<(Tx, i16)> {
+    match sqlx::query!(
+        "select competition_id
+        from competition_names
+        where name = $1",
+        competition_name,
+    ).fetch_optional(&mut tx).await? {
+        Some(record) => Ok((tx, record.competition_id.unwrap())),
+        None => Err(AdjustmentError::CompetitionNotFound.into()),
+    }
+}
+
+// Returns the new adjustment's id, for removing it later
+pub async fn add_adjustment(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    request: &AdjustmentRequest,
+    audit: &AuditContext,
+) -> Result<i32> {
+    request.validate()?;
+    let tx = pool.0.begin().await?;
+    let (mut tx, competition_id) = get_competition_id(tx, competition_name).await?;
+    let player_id = match &request.player_name {
+        None => None,
+        Some(player_name) => match sqlx::query!(
+            "select id
+            from players
+            where name = $1",
+            player_name,
+        ).fetch_optional(&mut tx).await? {
+            Some(record) => Some(record.id),
+            None => return Err(AdjustmentError::PlayerNotFound(player_name.clone()).into()),
+        },
+    };
+    let game_id = match request.site_game_id {
+        None => None,
+        Some(site_game_id) => match sqlx::query!(
+            "select games.id, variants.max_score
+            from games
+            join competition_seeds on games.seed_id = competition_seeds.id
+            join variants on competition_seeds.variant_id = variants.id
+            where games.site_game_id = $1 and competition_seeds.competition_id = $2",
+            site_game_id,
+            competition_id,
+        ).fetch_optional(&mut tx).await? {
+            Some(record) => {
+                // only checked when the seed's variant's max score is known
+                if let (AdjustmentType::AdjustedScore, Some(amount), Some(max_score)) =
+                    (request.adjustment_type, request.amount, record.max_score)
+                {
+                    if amount > max_score {
+                        return Err(AdjustmentError::AdjustedScoreAboveMax(
+                            site_game_id,
+                            max_score,
+                        ).into());
+                    }
+                }
+                Some(record.id)
+            },
+            None => return Err(AdjustmentError::GameNotFound(site_game_id).into()),
+        },
+    };
+    if request.adjustment_type == AdjustmentType::AdjustedScore && sqlx::query!(
+        "select id
+        from adjustments
+        where game_id = $1 and adjustment_type = 'adjusted_score'",
+        game_id,
+    ).fetch_optional(&mut tx).await?.is_some() {
+        // request.validate made sure there's a game
+        return Err(AdjustmentError::DuplicateAdjustedScore(request.site_game_id.unwrap()).into());
+    }
+    let adjustment_id = sqlx::query!(
+        "insert into adjustments (
+            competition_id
+          , player_id
+          , game_id
+          , adjustment_type
+          , amount
+          , reason
+        ) values (
+            $1
+          , $2
+          , $3
+          , $4
+          , $5
+          , $6
+        )
+        returning id",
+        competition_id,
+        player_id,
+        game_id,
+        request.adjustment_type.as_str(),
+        request.amount,
+        request.reason.trim(),
+    ).fetch_one(&mut tx).await?.id;
+    tx = update_competition_standings(tx, &[competition_id]).await?;
+    commit_audited(tx, audit, 1).await?;
+    Ok(adjustment_id)
+}
+
+pub async fn remove_adjustment(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    adjustment_id: i32,
+    audit: &AuditContext,
+) -> Result<()> {
+    let tx = pool.0.begin().await?;
+    let (mut tx, competition_id) = get_competition_id(tx, competition_name).await?;
+    let num_rows = sqlx::query!(
+        "delete from adjustments
+        where id = $1 and competition_id = $2",
+        adjustment_id,
+        competition_id,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        return Err(AdjustmentError::AdjustmentNotFound(adjustment_id).into());
+    }
+    tx = update_competition_standings(tx, &[competition_id]).await?;
+    commit_audited(tx, audit, num_rows).await
+}
+
+pub async fn get_adjustments(
+    pool: &DbViewerPool,
+    competition_name: &str,
+) -> Result<Vec<Adjustment>> {
+    sqlx::query!(
+        "select
+            adjustments.id
+          , (select name from players where players.id = adjustments.player_id) player_name
+          , (select site_game_id from games where games.id = adjustments.game_id) site_game_id
+          , adjustments.adjustment_type
+          , adjustments.amount
+          , adjustments.reason
+          , adjustments.created_at
+        from competition_names
+        join adjustments using(competition_id)
+        where competition_names.name = $1
+        order by adjustments.created_at, adjustments.id",
+        competition_name,
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| Ok(Adjustment {
+        id: record.id,
+        player_name: record.player_name,
+        site_game_id: record.site_game_id,
+        // enforced by a db constraint
+        adjustment_type: record.adjustment_type.parse()?,
+        amount: record.amount,
+        reason: record.reason,
+        created_at: record.created_at,
+    })).collect()
+}
@@ -0,0 +1,159 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use crate::{
+    DbAdminPool,
+    credentials::Scope,
+    model::{UtcDateTime, audit::{commit_audited, AuditContext}},
+    session::generate_token,
+};
+use anyhow::Result;
+
+// Distinguishes tokens from passwords, e.g. when they turn up in logs
+const TOKEN_PREFIX: &str = "hc_";
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: i32,
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    pub description: Option<String>,
+    pub issued_by: String,
+    pub created_at: UtcDateTime,
+    pub last_used_at: Option<UtcDateTime>,
+    pub revoked_at: Option<UtcDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiTokenRequest {
+    pub owner: String,
+    pub scopes: Vec<Scope>,
+    pub description: Option<String>,
+}
+
+// The token itself is only ever shown once, when it's issued
+#[derive(Serialize, Deserialize)]
+pub struct IssuedApiToken {
+    pub id: i32,
+    pub token: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiTokenError {
+    #[error("No unrevoked token with ID {0} was found")]
+    NotFound(i32),
+    #[error("Tokens must have at least one scope")]
+    NoScopes,
+}
+
+pub async fn issue_api_token(
+    pool: &DbAdminPool,
+    request: &ApiTokenRequest,
+    audit: &AuditContext,
+) -> Result<IssuedApiToken> {
+    if request.scopes.is_empty() {
+        return Err(ApiTokenError::NoScopes.into());
+    }
+    let mut tx = pool.0.begin().await?;
+    let token = format!("{}{}", TOKEN_PREFIX, generate_token());
+    let scope_strs: Vec<String> = request.scopes.iter()
+        .map(|scope| scope.as_str().to_owned()).collect();
+    let id = sqlx::query!(
+        "insert into api_tokens (
+            token_hash
+          , owner
+          , scopes
+          , description
+          , issued_by
+        ) values (
+            $1
+          , $2
+          , $3
+          , $4
+          , $5
+        ) returning id",
+        hash_token(&token),
+        request.owner,
+        &scope_strs,
+        request.description,
+        audit.user_id,
+    ).fetch_one(&mut tx).await?.id;
+    commit_audited(tx, audit, 1).await?;
+    Ok(IssuedApiToken { id, token })
+}
+
+pub async fn get_api_tokens(
+    pool: &DbAdminPool,
+) -> Result<Vec<ApiToken>> {
+    Ok(sqlx::query!(
+        "select
+            id
+          , owner
+          , scopes
+          , description
+          , issued_by
+          , created_at
+          , last_used_at
+          , revoked_at
+        from api_tokens
+        order by id",
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| ApiToken {
+        id: record.id,
+        owner: record.owner,
+        scopes: parse_scopes(&record.scopes),
+        description: record.description,
+        issued_by: record.issued_by,
+        created_at: record.created_at,
+        last_used_at: record.last_used_at,
+        revoked_at: record.revoked_at,
+    }).collect())
+}
+
+pub async fn revoke_api_token(
+    pool: &DbAdminPool,
+    id: i32,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_rows = sqlx::query!(
+        "update api_tokens
+        set revoked_at = now()
+        where id = $1
+            and revoked_at is null",
+        id,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        return Err(ApiTokenError::NotFound(id).into());
+    }
+    commit_audited(tx, audit, num_rows).await
+}
+
+// Returns the token's ID, owner and scopes if it's valid, and records that it was used
+pub async fn use_api_token(
+    pool: &DbAdminPool,
+    token: &str,
+) -> Result<Option<(i32, String, Vec<Scope>)>> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return Ok(None);
+    }
+    Ok(sqlx::query!(
+        "update api_tokens
+        set last_used_at = now()
+        where token_hash = $1
+            and revoked_at is null
+        returning id, owner, scopes",
+        hash_token(token),
+    ).fetch_optional(&pool.0).await?.map(|record| {
+        (record.id, record.owner, parse_scopes(&record.scopes))
+    }))
+}
+
+// Tokens are long and random, so unlike passwords, they don't need a slow, salted hash,
+// and can be looked up by their hash
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+// Scopes that this build doesn't know about grant nothing
+fn parse_scopes(scope_strs: &[String]) -> Vec<Scope> {
+    scope_strs.iter().filter_map(|scope_str| scope_str.parse().ok()).collect()
+}
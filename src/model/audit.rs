@@ -0,0 +1,161 @@
+use serde::{Serialize, Deserialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use crate::{DbAdminPool, model::{Tx, UtcDateTime}};
+use anyhow::Result;
+
+const MAX_ENTRIES_PER_PAGE: i64 = 500;
+
+// Who is making an administrative change, and through what.
+// Every function that changes the db takes one, and records it along with the change.
+pub struct AuditContext {
+    pub endpoint: String,
+    pub user_id: String,
+    pub payload_digest: String,
+    // The change is made, and then rolled back, so that its errors can be checked for
+    pub dry_run: bool,
+}
+
+impl AuditContext {
+    pub fn new<T: Serialize + ?Sized>(
+        endpoint: &str,
+        user_id: &str,
+        payload: &T,
+        dry_run: bool,
+    ) -> Self {
+        let payload_json = serde_json::to_vec(payload).unwrap_or_default();
+        let payload_digest = Sha256::digest(&payload_json).iter()
+            .map(|byte| format!("{:02x}", byte)).collect();
+        Self {
+            endpoint: endpoint.to_owned(),
+            user_id: user_id.to_owned(),
+            payload_digest,
+            dry_run,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub recorded_at: UtcDateTime,
+    pub endpoint: String,
+    pub user_id: String,
+    pub payload_digest: String,
+    pub affected_rows: i64,
+    pub dry_run: bool,
+    // why the change failed, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct AuditFilter {
+    pub user_id: Option<String>,
+    // matched as a substring
+    pub endpoint: Option<String>,
+    pub since: Option<UtcDateTime>,
+    pub until: Option<UtcDateTime>,
+    pub dry_run: Option<bool>,
+    pub failed: Option<bool>,
+    // starting from 1
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+// Commits the change along with its audit entry.
+// Dry runs are rolled back instead, and only their audit entry is kept.
+// `affected_rows` counts the rows the change inserted, updated or deleted, not including the
+// standings that were recomputed as a result.
+pub async fn commit_audited(tx: Tx, audit: &AuditContext, affected_rows: u64) -> Result<()> {
+    if audit.dry_run {
+        let mut conn = tx.rollback().await?;
+        insert_audit_entry(&mut conn, audit, affected_rows, None).await?;
+    } else {
+        let mut tx = tx;
+        insert_audit_entry(&mut tx, audit, affected_rows, None).await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+// For changes that failed, and so were never committed
+pub async fn record_failed_change(
+    pool: &DbAdminPool,
+    audit: &AuditContext,
+    error: &anyhow::Error,
+) -> Result<()> {
+    let mut conn = pool.0.acquire().await?;
+    insert_audit_entry(&mut conn, audit, 0, Some(&error.to_string())).await
+}
+
+// Newest first
+pub async fn get_audit_entries(
+    pool: &DbAdminPool,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEntry>> {
+    let per_page = filter.per_page.unwrap_or(50).clamp(1, MAX_ENTRIES_PER_PAGE);
+    // a page past the end is empty, however far past it is
+    let offset = (filter.page.unwrap_or(1).max(1) - 1).saturating_mul(per_page);
+    Ok(sqlx::query_as!(
+        AuditEntry,
+        "select
+            id
+          , recorded_at
+          , endpoint
+          , user_id
+          , payload_digest
+          , affected_rows
+          , dry_run
+          , error
+        from audit_log
+        where ($1::text is null or user_id = $1)
+            and ($2::text is null or strpos(endpoint, $2) > 0)
+            and ($3::timestamptz is null or recorded_at >= $3)
+            and ($4::timestamptz is null or recorded_at < $4)
+            and ($5::boolean is null or dry_run = $5)
+            and ($6::boolean is null or (error is not null) = $6)
+        order by id desc
+        limit $7
+        offset $8",
+        filter.user_id,
+        filter.endpoint,
+        filter.since,
+        filter.until,
+        filter.dry_run,
+        filter.failed,
+        per_page,
+        offset,
+    ).fetch_all(&pool.0).await?)
+}
+
+async fn insert_audit_entry(
+    conn: &mut PgConnection,
+    audit: &AuditContext,
+    affected_rows: u64,
+    error: Option<&str>,
+) -> Result<()> {
+    sqlx::query!(
+        "insert into audit_log (
+            endpoint
+          , user_id
+          , payload_digest
+          , affected_rows
+          , dry_run
+          , error
+        ) values (
+            $1
+          , $2
+          , $3
+          , $4
+          , $5
+          , $6
+        )",
+        audit.endpoint,
+        audit.user_id,
+        audit.payload_digest,
+        affected_rows as i64,
+        audit.dry_run,
+        error,
+    ).execute(conn).await?;
+    Ok(())
+}
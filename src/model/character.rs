@@ -0,0 +1,174 @@
+// Detrimental characters, as catalogued in hanab.live's characters.json, and the ones dealt to
+// each player of a game
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use crate::{DbAdminPool, model::{Tx, audit::{commit_audited, AuditContext}}};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CatalogCharacter {
+    pub id: i16,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emoji: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawCatalogCharacter {
+    id: i16,
+    name: Option<String>,
+    description: Option<String>,
+    emoji: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CharacterCatalogError {
+    #[error("Expected an array of characters, or an object of characters keyed by name")]
+    UnknownFormat,
+    #[error("Character with site id {0} has no name")]
+    MissingName(i16),
+    #[error("Character site id {0} appears more than once")]
+    DuplicateId(i16),
+    #[error("Character name {0} appears more than once")]
+    DuplicateName(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CharacterError {
+    #[error("Unknown character {0}; sync the characters catalog first")]
+    Unknown(String),
+}
+
+// Same two shapes as variants.json
+pub fn parse_character_catalog(json: serde_json::Value) -> Result<Vec<CatalogCharacter>> {
+    let raw_characters: Vec<(Option<String>, RawCatalogCharacter)> = match json {
+        serde_json::Value::Array(_) =>
+            serde_json::from_value::<Vec<RawCatalogCharacter>>(json)?
+                .into_iter().map(|raw_character| (None, raw_character)).collect(),
+        serde_json::Value::Object(_) =>
+            serde_json::from_value::<HashMap<String, RawCatalogCharacter>>(json)?
+                .into_iter().map(|(name, raw_character)| (Some(name), raw_character)).collect(),
+        _ => return Err(CharacterCatalogError::UnknownFormat.into()),
+    };
+    let mut characters = Vec::new();
+    for (key_name, raw_character) in raw_characters {
+        let name = match raw_character.name.or(key_name) {
+            Some(name) => name,
+            None => return Err(CharacterCatalogError::MissingName(raw_character.id).into()),
+        };
+        characters.push(CatalogCharacter {
+            id: raw_character.id,
+            name,
+            description: raw_character.description,
+            emoji: raw_character.emoji,
+        });
+    }
+    characters.sort_by_key(|character| character.id);
+    let mut names = std::collections::HashSet::new();
+    for (i, character) in characters.iter().enumerate() {
+        if i > 0 && characters[i - 1].id == character.id {
+            return Err(CharacterCatalogError::DuplicateId(character.id).into());
+        }
+        if !names.insert(&character.name) {
+            return Err(CharacterCatalogError::DuplicateName(character.name.clone()).into());
+        }
+    }
+    Ok(characters)
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RenamedCharacter {
+    pub id: i16,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct CharacterSyncReport {
+    pub added: Vec<String>,
+    pub renamed: Vec<RenamedCharacter>,
+    pub unchanged: usize,
+}
+
+// Upserts the catalog by site id. Characters missing from it are kept, since games may have
+// been played with them.
+pub async fn sync_characters(
+    pool: &DbAdminPool,
+    characters: &[CatalogCharacter],
+    audit: &AuditContext,
+) -> Result<CharacterSyncReport> {
+    let mut tx = pool.0.begin().await?;
+    sqlx::query("set constraints characters_name_key deferred").execute(&mut tx).await?;
+    let existing: HashMap<i16, String> = sqlx::query!(
+        "select id, name
+        from characters",
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| (record.id, record.name)).collect();
+    let mut report = CharacterSyncReport::default();
+    for character in characters {
+        match existing.get(&character.id) {
+            None => report.added.push(character.name.clone()),
+            Some(old_name) if old_name != &character.name => {
+                report.renamed.push(RenamedCharacter {
+                    id: character.id,
+                    old_name: old_name.clone(),
+                    new_name: character.name.clone(),
+                });
+            },
+            Some(_) => report.unchanged += 1,
+        }
+        sqlx::query!(
+            "insert into characters (
+                id
+              , name
+              , description
+              , emoji
+            ) values (
+                $1
+              , $2
+              , $3
+              , $4
+            )
+            on conflict (id) do update set
+                name = excluded.name
+              , description = excluded.description
+              , emoji = excluded.emoji",
+            character.id,
+            character.name,
+            character.description,
+            character.emoji,
+        ).execute(&mut tx).await?;
+    }
+    report.added.sort();
+    if !report.renamed.is_empty() {
+        // the standings carry character names
+        sqlx::query("select update_computed_competition_standings()").execute(&mut tx).await?;
+    }
+    let affected_rows = report.added.len() + report.renamed.len();
+    commit_audited(tx, audit, affected_rows as u64).await?;
+    Ok(report)
+}
+
+// Looks up the ids of the characters dealt to a game's players, by name
+pub(crate) async fn get_character_ids(
+    mut tx: Tx,
+    character_names: &[Option<String>],
+) -> Result<(Tx, Vec<Option<i16>>)> {
+    let mut character_ids = Vec::new();
+    for character_name in character_names {
+        character_ids.push(match character_name {
+            None => None,
+            Some(character_name) => match sqlx::query!(
+                "select id
+                from characters
+                where name = $1",
+                character_name,
+            ).fetch_optional(&mut tx).await? {
+                Some(record) => Some(record.id),
+                None => return Err(CharacterError::Unknown(character_name.clone()).into()),
+            },
+        });
+    }
+    Ok((tx, character_ids))
+}
@@ -1,13 +1,30 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use sqlx::{FromRow, Row};
 
 use serde::{Serialize, Deserialize};
 use chrono::{Weekday, Duration, Datelike};
-use crate::{DbViewerPool, DbAdminPool, model::{Tx, UtcDateTime}};
+use crate::{DbViewerPool, DbAdminPool, model::{
+    Tx,
+    UtcDateTime,
+    adjustment::{get_adjustments, Adjustment},
+    audit::{commit_audited, AuditContext},
+    team::{get_registered_teams, RegisteredTeam},
+    tie_break::{
+        compare_by_tie_breaks,
+        explain_tie_break,
+        format_tie_breaks,
+        parse_tie_breaks,
+        tie_break_strs,
+        validate_tie_breaks,
+        TieBreak,
+        TieBreakStats,
+    },
+    variant::{get_variant_metadata, VariantMetadata},
+}};
 use anyhow::Result;
 use sqlx::postgres::PgRow;
 
-//// This is a huge pain in the ass at the time I'm writing this code
+// This is a huge pain in the ass at the time I'm writing this code
 //#[derive(sqlx::Type, Serialize, Deserialize, Debug)]
 //#[sqlx(rename = "scoring_type", rename_all = "lowercase")]
 //enum ScoringType { Standard, Speedrun }
@@ -18,10 +35,14 @@ pub struct SeriesCompetitions {
     competitions: Vec<CompetitionWithDerivedQuantities>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct CompetitionNestedResults {
     pub competition_with_derived_quantities: CompetitionWithDerivedQuantities,
     pub team_results: Vec<TeamResults>,
+    pub registered_teams: Vec<RegisteredTeam>,
+    // teams can register until the competition ends
+    pub registration_open: bool,
+    pub adjustments: Vec<Adjustment>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -30,37 +51,43 @@ pub struct CompetitionWithDerivedQuantities {
     pub competition_name: String,
     pub create_table_urls: Vec<BaseSeedNameCreateTableUrlPair>,
     pub formatted_time_control: String,
+    // e.g. "total score, then fewer turns"; empty when ties are left as shared ranks
+    pub formatted_tie_breaks: String,
+    // None for variants that were added before their metadata was synced
+    pub variant: Option<VariantMetadata>,
+    // the seeds and their create-table links are withheld until the competition has started
+    pub started: bool,
 }
 
 impl CompetitionWithDerivedQuantities {
-    fn new(competition: Competition, competition_name: String) -> Self {
-        let seed_name_create_table_url_pairs = competition.generate_create_table_urls()
-            .into_iter().zip(competition.base_seed_names.iter())
-            .map(|(create_table_url, base_seed_name)| BaseSeedNameCreateTableUrlPair {
-                base_seed_name: base_seed_name.clone(),
-                create_table_url
-            }).collect();
+    fn new(
+        mut competition: Competition,
+        competition_name: String,
+        variant: Option<VariantMetadata>,
+    ) -> Self {
+        let started = competition.ruleset.start_datetime
+            .is_none_or(|start_datetime| start_datetime <= chrono::offset::Utc::now());
+        // a seed's name is its deal, so it's left out of the raw JSON too
+        let seed_name_create_table_url_pairs = if started {
+            competition.generate_create_table_url_pairs(&TableOptions::default())
+        } else {
+            competition.base_seed_names.clear();
+            competition.seeds.clear();
+            Vec::new()
+        };
         let formatted_time_control = match &competition.ruleset.time_control {
             None => "".to_owned(),
-            Some(time_control) => {
-                let base_time_duration =
-                    chrono::Duration::seconds(time_control.base_time_seconds as i64);
-                let turn_time_duration =
-                    chrono::Duration::seconds(time_control.turn_time_seconds as i64);
-                format!(
-                    "{}:{:0>2} + {}:{:0>2}",
-                    base_time_duration.num_minutes(), 
-                    base_time_duration.num_seconds() % 60, 
-                    turn_time_duration.num_minutes(), 
-                    turn_time_duration.num_seconds() % 60, 
-                )
-            }
+            Some(time_control) => time_control.format(),
         };
+        let formatted_tie_breaks = format_tie_breaks(&competition.ruleset.tie_breaks);
         Self {
             create_table_urls: seed_name_create_table_url_pairs,
+            started,
             competition,
             competition_name,
             formatted_time_control,
+            formatted_tie_breaks,
+            variant,
         }
     }
 }
@@ -74,12 +101,35 @@ impl CompetitionWithDerivedQuantities {
 pub struct BaseSeedNameCreateTableUrlPair {
     base_seed_name: String,
     create_table_url: String,
+    // only for seeds with their own variant
+    variant_name: Option<String>,
+    // what the seed plays differently from the rest of the competition, e.g. its time control
+    seed_rules: Vec<String>,
+}
+
+pub const DEFAULT_BASE_SEED_NAME_PATTERN: &str = "hc-{date}-{n}";
+pub const DEFAULT_NUM_SEEDS: i16 = 4;
+
+// {date} is replaced with the competition's end date, and {n} with the seed's number,
+// starting from 1
+pub fn generate_base_seed_names(
+    pattern: &str,
+    end_datetime: UtcDateTime,
+    num_seeds: i16,
+) -> Vec<String> {
+    let pattern = pattern.replace("{date}", &end_datetime.format("%Y-%m-%d").to_string());
+    (1..=num_seeds).map(|n| pattern.replace("{n}", &n.to_string())).collect()
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct PartiallySpecifiedCompetition {
     pub num_players: i16,
-    pub variant_name: String,
+    // None when the seeds each have their own
+    pub variant_name: Option<String>,
+    // named after this instead of a variant, when the seeds each have their own
+    pub title: Option<String>,
+    // games started before it don't count; None means open since creation
+    pub start_datetime: Option<UtcDateTime>,
     pub end_datetime: Option<UtcDateTime>,
     pub deckplay_enabled: Option<bool>,
     pub empty_clues_enabled: Option<bool>,
@@ -88,7 +138,35 @@ pub struct PartiallySpecifiedCompetition {
     pub time_control: Option<TimeControl>,
     pub additional_rules: Option<String>,
     pub base_seed_names: Option<Vec<String>>,
+    // instead of `base_seed_names`, for seeds with their own variant, time control or options
+    pub seeds: Option<Vec<CompetitionSeed>>,
     pub series_names: Option<Vec<String>>,
+    // only registered teams' games count
+    pub registration_required: Option<bool>,
+    // breaks ties on matchpoints, in order
+    pub tie_breaks: Option<Vec<TieBreak>>,
+}
+
+// A seed's own variant, time control and options; those left out are the competition's
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CompetitionSeed {
+    pub base_seed_name: String,
+    pub variant_name: Option<String>,
+    pub time_control: Option<TimeControl>,
+    pub deckplay_enabled: Option<bool>,
+    pub empty_clues_enabled: Option<bool>,
+}
+
+impl CompetitionSeed {
+    fn new(base_seed_name: String) -> Self {
+        CompetitionSeed {
+            base_seed_name,
+            variant_name: None,
+            time_control: None,
+            deckplay_enabled: None,
+            empty_clues_enabled: None,
+        }
+    }
 }
 
 impl PartiallySpecifiedCompetition {
@@ -111,28 +189,37 @@ impl PartiallySpecifiedCompetition {
         if self.deckplay_enabled.is_none() { self.deckplay_enabled = Some(true) }
         if self.empty_clues_enabled.is_none() { self.empty_clues_enabled = Some(false) }
         if self.characters_enabled.is_none() { self.characters_enabled = Some(false) }
+        if self.registration_required.is_none() { self.registration_required = Some(false) }
+        if self.tie_breaks.is_none() { self.tie_breaks = Some(Vec::new()) }
         if self.scoring_type.is_none() {
             self.scoring_type = Some("standard".to_owned());
             //self.scoring_type = Some(ScoringType::Standard);
         }
+        if let Some(seeds) = &self.seeds {
+            self.base_seed_names = Some(seeds.iter()
+                .map(|seed| seed.base_seed_name.clone()).collect());
+        }
         if self.base_seed_names.is_none() {
-            let base_seed_prefix = format!(
-                "hc-{}", self.end_datetime.unwrap().date().format("%Y-%m-%d")
-            );
-            self.base_seed_names = Some(vec![
-                format!("{}-1", base_seed_prefix),
-                format!("{}-2", base_seed_prefix),
-                format!("{}-3", base_seed_prefix),
-                format!("{}-4", base_seed_prefix),
-            ]);
+            self.base_seed_names = Some(generate_base_seed_names(
+                DEFAULT_BASE_SEED_NAME_PATTERN,
+                self.end_datetime.unwrap(),
+                DEFAULT_NUM_SEEDS,
+            ));
         }
         if self.series_names.is_none() {
             self.series_names = Some(Vec::new());
         }
+        let base_seed_names = self.base_seed_names.unwrap();
+        let seeds = match self.seeds {
+            Some(seeds) => seeds,
+            None => base_seed_names.iter().cloned().map(CompetitionSeed::new).collect(),
+        };
         Competition {
             ruleset: CompetitionRuleset {
                 num_players: self.num_players,
                 variant_name: self.variant_name,
+                title: self.title,
+                start_datetime: self.start_datetime,
                 end_datetime: self.end_datetime.unwrap(),
                 deckplay_enabled: self.deckplay_enabled.unwrap(),
                 empty_clues_enabled: self.empty_clues_enabled.unwrap(),
@@ -140,8 +227,11 @@ impl PartiallySpecifiedCompetition {
                 additional_rules: self.additional_rules,
                 scoring_type: self.scoring_type.unwrap(),
                 time_control: self.time_control,
+                registration_required: self.registration_required.unwrap(),
+                tie_breaks: self.tie_breaks.unwrap(),
             },
-            base_seed_names: self.base_seed_names.unwrap(),
+            base_seed_names,
+            seeds,
             series_names: self.series_names.unwrap(),
         }
     }
@@ -151,43 +241,120 @@ impl PartiallySpecifiedCompetition {
 pub struct Competition {
     pub ruleset: CompetitionRuleset,
     pub base_seed_names: Vec<String>,
+    // in the same order as `base_seed_names`
+    pub seeds: Vec<CompetitionSeed>,
     pub series_names: Vec<String>,
 }
 
+#[derive(thiserror::Error, Debug)]
+pub enum CompetitionSeedsError {
+    #[error("A competition needs a variant, or a title and a variant for each seed")]
+    MissingTitle,
+    #[error("Only competitions whose seeds each have their own variant have a title")]
+    UnexpectedTitle,
+    #[error("Seed {0} needs a variant, since the competition doesn't have one")]
+    MissingSeedVariant(String),
+    #[error("Seed {0} can't have its own variant in a competition with one")]
+    UnexpectedSeedVariant(String),
+}
+
+// What a team can add to its create-table links, which hanab.live takes as query parameters
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct TableOptions {
+    pub password: Option<String>,
+    #[serde(default)]
+    pub card_cycle: bool,
+}
+
 impl Competition {
-    fn generate_create_table_urls(&self) -> Vec<String> {
+    fn validate_seeds(&self) -> Result<(), CompetitionSeedsError> {
+        match (&self.ruleset.variant_name, &self.ruleset.title) {
+            (Some(_), Some(_)) => return Err(CompetitionSeedsError::UnexpectedTitle),
+            (None, None) => return Err(CompetitionSeedsError::MissingTitle),
+            _ => (),
+        }
+        for seed in &self.seeds {
+            match (&self.ruleset.variant_name, &seed.variant_name) {
+                (None, None) => return Err(
+                    CompetitionSeedsError::MissingSeedVariant(seed.base_seed_name.clone())),
+                (Some(_), Some(_)) => return Err(
+                    CompetitionSeedsError::UnexpectedSeedVariant(seed.base_seed_name.clone())),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn generate_create_table_url_pairs(
+        &self,
+        table_options: &TableOptions,
+    ) -> Vec<BaseSeedNameCreateTableUrlPair> {
+        self.generate_team_create_table_urls(table_options).into_iter().zip(self.seeds.iter())
+            .map(|(create_table_url, seed)| {
+                let mut seed_rules = Vec::new();
+                if let Some(time_control) = &seed.time_control {
+                    seed_rules.push(format!("time control {}", time_control.format()));
+                }
+                if let Some(deckplay_enabled) = seed.deckplay_enabled {
+                    seed_rules.push(format!("deckplay enabled: {}", deckplay_enabled));
+                }
+                if let Some(empty_clues_enabled) = seed.empty_clues_enabled {
+                    seed_rules.push(format!("empty clues enabled: {}", empty_clues_enabled));
+                }
+                BaseSeedNameCreateTableUrlPair {
+                    base_seed_name: seed.base_seed_name.clone(),
+                    create_table_url,
+                    variant_name: seed.variant_name.clone(),
+                    seed_rules,
+                }
+            }).collect()
+    }
+
+    pub fn generate_team_create_table_urls(&self, table_options: &TableOptions) -> Vec<String> {
         let mut create_table_urls = Vec::new();
         let ruleset = &self.ruleset;
-        let time_control_query_parameters_str = match &self.ruleset.time_control {
-            None => "".to_owned(),
-            Some(time_control) => format!("\
-                &timed=true\
-                &timeBase={}\
-                &timePerTurn={}\
-                ",
-                time_control.base_time_seconds,
-                time_control.turn_time_seconds,
-            ),
+        let mut team_query_parameters_str = match &table_options.password {
+            Some(password) if !password.is_empty() => {
+                format!("&password={}", urlencoding::encode(password))
+            },
+            _ => "".to_owned(),
         };
-        for base_seed_name in &self.base_seed_names {
+        if table_options.card_cycle {
+            team_query_parameters_str.push_str("&cardCycle=true");
+        }
+        for seed in &self.seeds {
+            // validated to have one or the other
+            let variant_name = seed.variant_name.as_ref().or(ruleset.variant_name.as_ref())
+                .map(String::as_str).unwrap_or_default();
+            let time_control_query_parameters_str =
+                match seed.time_control.as_ref().or(ruleset.time_control.as_ref()) {
+                    None => "".to_owned(),
+                    Some(time_control) => format!("\
+                        &timed=true\
+                        &timeBase={}\
+                        &timePerTurn={}\
+                        ",
+                        time_control.base_time_seconds,
+                        time_control.turn_time_seconds,
+                    ),
+                };
             create_table_urls.push(format!("https://hanab.live/create-table?\
                 name=!seed%20{}\
                 &variantName={}\
                 &deckPlays={}\
                 &emptyClues={}\
                 &detrimentalCharacters={}\
-                {}
-                ",
-                //&speedrun={}\
-                urlencoding::encode(&base_seed_name),
-                urlencoding::encode(&ruleset.variant_name),
-                ruleset.deckplay_enabled,
-                ruleset.empty_clues_enabled,
-                // TODO: enable whatever feature we get for speedruns that don't end
-                // when a critical card is lost
-                // ruleset.scoring_type == "speedrun",
+                &speedrun={}\
+                {}\
+                {}",
+                urlencoding::encode(&seed.base_seed_name),
+                urlencoding::encode(variant_name),
+                seed.deckplay_enabled.unwrap_or(ruleset.deckplay_enabled),
+                seed.empty_clues_enabled.unwrap_or(ruleset.empty_clues_enabled),
                 ruleset.characters_enabled,
+                ruleset.scoring_type == "speedrun",
                 time_control_query_parameters_str,
+                team_query_parameters_str,
             ));
         }
         create_table_urls
@@ -197,7 +364,10 @@ impl Competition {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CompetitionRuleset {
     pub num_players: i16,
-    pub variant_name: String,
+    // None when the seeds each have their own, in which case there's a title instead
+    pub variant_name: Option<String>,
+    pub title: Option<String>,
+    pub start_datetime: Option<UtcDateTime>,
     pub end_datetime: UtcDateTime,
     pub deckplay_enabled: bool,
     pub empty_clues_enabled: bool,
@@ -205,31 +375,75 @@ pub struct CompetitionRuleset {
     pub scoring_type: String,
     pub time_control: Option<TimeControl>,
     pub additional_rules: Option<String>,
+    pub registration_required: bool,
+    // picked when the competition is created, since changing it would reorder the standings
+    #[serde(default)]
+    pub tie_breaks: Vec<TieBreak>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct TimeControl {
     pub base_time_seconds: i16,
     pub turn_time_seconds: i16,
 }
 
-#[derive(Serialize, Debug)]
+impl TimeControl {
+    // e.g. 2:00 + 0:20
+    fn format(&self) -> String {
+        let base_time_duration = chrono::Duration::seconds(self.base_time_seconds as i64);
+        let turn_time_duration = chrono::Duration::seconds(self.turn_time_seconds as i64);
+        format!(
+            "{}:{:0>2} + {}:{:0>2}",
+            base_time_duration.num_minutes(),
+            base_time_duration.num_seconds() % 60,
+            turn_time_duration.num_minutes(),
+            turn_time_duration.num_seconds() % 60,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TeamResults {
+    // only for registered teams
+    pub team_name: Option<String>,
     pub players: Vec<Option<String>>,
+    pub substitutes: Vec<String>,
     pub final_rank: i64,
     pub fractional_mp: f64,
+    // net of penalties
     pub sum_mp: i64,
+    // deducted from the team's total for its players' penalties
+    pub penalty_mp: i64,
+    // mean over the team's games
+    pub normalized_score: Option<f64>,
     pub game_results: Vec<Option<GameResult>>,
+    pub tie_break_stats: TieBreakStats,
+    // why the team is ahead of the next one, when they're tied on matchpoints
+    pub tie_break_note: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct GameResult {
     pub seed_matchpoints: i32,
     pub score: i16,
+    pub normalized_score: Option<f64>,
+    pub is_perfect_score: Option<bool>,
     pub turns: i16,
     pub site_game_id: i64,
     pub replay_url: String,
     pub game_duration_seconds: i32,
+    // shown as a badge when the game didn't end normally; null when unknown
+    pub end_condition: Option<String>,
+    // empty unless the game was played with detrimental characters
+    pub characters: Vec<PlayerCharacter>,
+    // its score or matchpoints were adjusted by an admin
+    pub adjusted: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PlayerCharacter {
+    pub player_name: String,
+    pub character_name: String,
 }
 
 // This is essentially an intermediate record that gets passed around so we can join it to the
@@ -237,9 +451,12 @@ pub struct GameResult {
 #[derive(FromRow)]
 pub struct CompetitionRulesetWithIds {
     pub competition_id: i16,
-    pub variant_id: i32,
+    // None when the seeds each have their own
+    pub variant_id: Option<i32>,
     pub num_players: i16,
-    pub variant_name: String,
+    pub variant_name: Option<String>,
+    pub title: Option<String>,
+    pub start_datetime: Option<UtcDateTime>,
     pub end_datetime: UtcDateTime,
     pub deckplay_enabled: bool,
     pub empty_clues_enabled: bool,
@@ -248,12 +465,16 @@ pub struct CompetitionRulesetWithIds {
     pub base_time_seconds: Option<i16>,
     pub turn_time_seconds: Option<i16>,
     pub additional_rules: Option<String>,
+    pub registration_required: bool,
+    pub tie_breaks: Vec<String>,
 }
 
 #[derive(thiserror::Error, Debug)]
 enum GetCompetitionError {
     #[error("No competition with that name was found")]
     NotFound,
+    #[error("The competition hasn't started yet")]
+    NotStarted,
 }
 
 // This is quite similar to model::result::CombinedResult, but this one is tailored to be a good
@@ -270,7 +491,17 @@ struct CompetitionFlatResult {
     pub site_game_id: i64,
     pub replay_url: String,
     pub score: i16,
+    pub normalized_score: Option<f64>,
+    pub is_perfect_score: Option<bool>,
+    pub end_condition: Option<String>,
+    pub character_name: Option<String>,
+    pub team_name: Option<String>,
+    pub max_mp: Option<i32>,
+    pub penalty_mp: Option<i32>,
+    // 1 when it's the best game on its seed
+    pub seed_rank: Option<i32>,
     pub turns: i16,
+    pub datetime_game_ended: UtcDateTime,
     // support for the INTERVAL type landed literally less than a week ago, so look out for a
     // release: https://github.com/launchbadge/sqlx/pull/271
     pub game_duration_seconds: i32,
@@ -279,17 +510,129 @@ struct CompetitionFlatResult {
 pub async fn add_competitions(
     pool: &DbAdminPool,
     partially_specified_competitions: Vec<PartiallySpecifiedCompetition>,
+    audit: &AuditContext,
 ) -> Result<()> {
     // if a single competition causes an error, don't commit any
-    let mut tx = pool.0.begin().await?;
+    let tx = pool.0.begin().await?;
+    let num_competitions = partially_specified_competitions.len();
+    let tx = add_competitions_in_tx(tx, partially_specified_competitions).await?;
+    commit_audited(tx, audit, num_competitions as u64).await
+}
+
+// For callers that make other changes in the same transaction
+pub(crate) async fn add_competitions_in_tx(
+    mut tx: Tx,
+    partially_specified_competitions: Vec<PartiallySpecifiedCompetition>,
+) -> Result<Tx> {
     for competition in partially_specified_competitions {
         // I'd prefer to use references throughout, but I don't know a better pattern that would
         // allow me to pass the same mutable borrow to multiple functions.
         tx = add_competition(tx, competition).await?;
     }
     sqlx::query("select update_competition_names()").execute(&mut tx).await?;
-    tx.commit().await?;
-    Ok(())
+    Ok(tx)
+}
+
+// The parts of a competition's ruleset that can be changed after it's been created.
+// Its seeds belong to a variant and a number of players, so those two can't be.
+#[derive(Serialize, Deserialize)]
+pub struct CompetitionRulesetEdit {
+    pub start_datetime: Option<UtcDateTime>,
+    pub end_datetime: UtcDateTime,
+    pub deckplay_enabled: bool,
+    pub empty_clues_enabled: bool,
+    pub characters_enabled: bool,
+    pub scoring_type: String,
+    pub time_control: Option<TimeControl>,
+    pub additional_rules: Option<String>,
+    #[serde(default)]
+    pub registration_required: bool,
+}
+
+// Returns the competition's new name, which changes along with its end date and scoring
+pub async fn update_competition(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    edit: &CompetitionRulesetEdit,
+    audit: &AuditContext,
+) -> Result<String> {
+    let mut tx = pool.0.begin().await?;
+    let competition_id = match sqlx::query!(
+        "select competition_id
+        from competition_names
+        where name = $1",
+        competition_name,
+    ).fetch_optional(&mut tx).await? {
+        Some(record) => record.competition_id.unwrap(),
+        None => return Err(GetCompetitionError::NotFound.into()),
+    };
+    let (base_time_seconds, turn_time_seconds) = match &edit.time_control {
+        None => (None, None),
+        Some(time_control) => (
+            Some(time_control.base_time_seconds),
+            Some(time_control.turn_time_seconds),
+        ),
+    };
+    sqlx::query(
+        "update competitions set
+            end_datetime = $2
+          , deckplay_enabled = $3
+          , empty_clues_enabled = $4
+          , characters_enabled = $5
+          , scoring_type = cast($6 as scoring_type)
+          , base_time_seconds = $7
+          , turn_time_seconds = $8
+          , additional_rules = $9
+          , start_datetime = $10
+          , registration_required = $11
+        where id = $1")
+        .bind(competition_id)
+        .bind(edit.end_datetime)
+        .bind(edit.deckplay_enabled)
+        .bind(edit.empty_clues_enabled)
+        .bind(edit.characters_enabled)
+        .bind(&edit.scoring_type)
+        .bind(base_time_seconds)
+        .bind(turn_time_seconds)
+        .bind(&edit.additional_rules)
+        .bind(edit.start_datetime)
+        .bind(edit.registration_required)
+        .execute(&mut tx).await?;
+    sqlx::query("select update_competition_names()").execute(&mut tx).await?;
+    // the scoring type affects the ranks, and the start and end dates and registration which
+    // games count
+    tx = update_competition_standings(tx, &[competition_id]).await?;
+    let new_competition_name = sqlx::query!(
+        "select name
+        from competition_names
+        where competition_id = $1",
+        competition_id,
+    ).fetch_one(&mut tx).await?.name.unwrap();
+    commit_audited(tx, audit, 1).await?;
+    Ok(new_competition_name)
+}
+
+// Recomputes the standings of only the given competitions
+pub async fn update_competition_standings(
+    mut tx: Tx,
+    competition_ids: &[i16],
+) -> Result<Tx> {
+    sqlx::query("select update_competition_standings($1)")
+        .bind(competition_ids)
+        .execute(&mut tx).await?;
+    Ok(tx)
+}
+
+// Recomputes competition names and the standings of every competition, for when something has
+// changed outside of the admin endpoints
+pub async fn refresh_all_standings(
+    pool: &DbAdminPool,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    sqlx::query("select update_competition_names()").execute(&mut tx).await?;
+    sqlx::query("select update_computed_competition_standings()").execute(&mut tx).await?;
+    commit_audited(tx, audit, 0).await
 }
 
 pub async fn get_competition_names(
@@ -323,32 +666,73 @@ pub async fn get_competition_and_nested_results(
         pool,
         competition_name
     ).await?;
-    let mut nested_results = nest_competition_results(
+    let registered_teams = get_registered_teams(pool, competition_name).await?;
+    let adjustments = get_adjustments(pool, competition_name).await?;
+    // ranked and in order of rank
+    Ok(nest_competition_results(
         competition_with_derived_quantities,
-        competition_flat_results
-    );
-    nested_results.team_results.sort_unstable_by_key(|record| record.final_rank);
-    Ok(nested_results)
+        competition_flat_results,
+        registered_teams,
+        adjustments,
+    ))
+}
+
+pub async fn get_competition_with_derived_quantities(
+    pool: &DbViewerPool,
+    competition_name: &str
+) -> Result<CompetitionWithDerivedQuantities> {
+    let competition_id = get_competition_id(pool, competition_name).await?;
+    if competition_id.is_none() {
+        return Err(GetCompetitionError::NotFound.into());
+    }
+    let competition_ruleset_with_ids = get_competition_with_ids(pool, competition_id.unwrap()).await?;
+    competition_with_derived_quantities_from_ruleset_with_ids(
+        pool,
+        competition_ruleset_with_ids,
+    ).await
 }
 
 pub async fn get_active_competitions(
     pool: &DbViewerPool,
 ) -> Result<Vec<CompetitionWithDerivedQuantities>> {
     let active_competition_ids = get_active_competition_ids(pool).await?;
-    let mut active_competitions_rulesets_with_ids = Vec::new();
-    for id in active_competition_ids.into_iter() {
-        active_competitions_rulesets_with_ids.push(get_competition_with_ids(pool, id).await?);
-    }
-    let mut competitions_with_derived_quantities = Vec::new();
-    for ruleset_with_ids in active_competitions_rulesets_with_ids {
-        competitions_with_derived_quantities.push(
-            competition_with_derived_quantities_from_ruleset_with_ids(
-                pool,
-                ruleset_with_ids,
-            ).await?
-        );
+    get_competitions_with_derived_quantities(pool, &active_competition_ids).await
+}
+
+// A team's own create-table links, with its table password and options
+pub async fn get_team_create_table_urls(
+    pool: &DbViewerPool,
+    competition_name: &str,
+    table_options: &TableOptions,
+) -> Result<Vec<BaseSeedNameCreateTableUrlPair>> {
+    let competition = get_competition_with_derived_quantities(pool, competition_name).await?;
+    if !competition.started {
+        return Err(GetCompetitionError::NotStarted.into());
     }
-    Ok(competitions_with_derived_quantities)
+    Ok(competition.competition.generate_create_table_url_pairs(table_options))
+}
+
+// Announced, but not started yet
+pub async fn get_upcoming_competitions(
+    pool: &DbViewerPool,
+) -> Result<Vec<CompetitionWithDerivedQuantities>> {
+    let upcoming_competition_ids: Vec<i16> = sqlx::query!(
+        "select competitions.id
+        from competitions
+        where start_datetime > now()
+        order by start_datetime, end_datetime",
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| record.id).collect();
+    get_competitions_with_derived_quantities(pool, &upcoming_competition_ids).await
+}
+
+// Loads many competitions in a fixed number of queries, rather than a handful per competition.
+// The output is in the same order as `competition_ids`.
+pub async fn get_competitions_with_derived_quantities(
+    pool: &DbViewerPool,
+    competition_ids: &[i16],
+) -> Result<Vec<CompetitionWithDerivedQuantities>> {
+    let rulesets_with_ids = get_competitions_with_ids(pool, competition_ids).await?;
+    competitions_with_derived_quantities_from_rulesets_with_ids(pool, rulesets_with_ids).await
 }
 
 async fn get_competition_id(
@@ -373,7 +757,9 @@ pub async fn get_competition_with_ids(
             competitions.id competition_id
           , variant_id
           , num_players
-          , variants.name variant_name
+          , (select name from variants where variants.id = variant_id) variant_name
+          , title
+          , start_datetime
           , end_datetime
           , deckplay_enabled
           , empty_clues_enabled
@@ -382,86 +768,203 @@ pub async fn get_competition_with_ids(
           , base_time_seconds
           , turn_time_seconds
           , additional_rules
+          , registration_required
+          , tie_breaks
         from competitions
-        join variants on variant_id = variants.id
         where competitions.id = $1"#,
         // , scoring_type as "scoring_type: String"
         competition_id
     ).fetch_one(&pool.0).await?)
 }
 
+pub async fn get_competitions_with_ids(
+    pool: &DbViewerPool,
+    competition_ids: &[i16],
+) -> Result<Vec<CompetitionRulesetWithIds>> {
+    let mut rulesets_with_ids = sqlx::query_as!(
+        CompetitionRulesetWithIds,
+        r#"select
+            competitions.id competition_id
+          , variant_id
+          , num_players
+          , (select name from variants where variants.id = variant_id) variant_name
+          , title
+          , start_datetime
+          , end_datetime
+          , deckplay_enabled
+          , empty_clues_enabled
+          , characters_enabled
+          , scoring_type::text
+          , base_time_seconds
+          , turn_time_seconds
+          , additional_rules
+          , registration_required
+          , tie_breaks
+        from competitions
+        where competitions.id = any($1)"#,
+        competition_ids,
+    ).fetch_all(&pool.0).await?;
+    let positions: HashMap<i16, usize> = competition_ids.iter().enumerate()
+        .map(|(position, &id)| (id, position)).collect();
+    rulesets_with_ids.sort_by_key(|ruleset_with_ids| positions[&ruleset_with_ids.competition_id]);
+    Ok(rulesets_with_ids)
+}
+
 async fn get_active_competition_ids(
     pool: &DbViewerPool,
 ) -> Result<Vec<i16>> {
     Ok(sqlx::query!(
         r#"select competitions.id
         from competitions
-        where end_datetime > now()"#
+        where end_datetime > now()
+            and (start_datetime is null or start_datetime <= now())"#
         // , scoring_type as "scoring_type: String"
     ).fetch_all(&pool.0).await?.into_iter().map(|record| record.id).collect())
 }
 
-// TODO: Most of the functions below could use a refactor
 pub async fn competition_with_derived_quantities_from_ruleset_with_ids(
     pool: &DbViewerPool,
     competition_ruleset_with_ids: CompetitionRulesetWithIds,
 ) -> Result<CompetitionWithDerivedQuantities> {
-    // logically guaranteed there will be a record
-    let competition_name = sqlx::query!(
-        "select name from competition_names where competition_id = $1",
-        competition_ruleset_with_ids.competition_id
-    ).fetch_one(&pool.0).await?.name.unwrap();
-    let series_names = sqlx::query!(
-        "select name
+    // logically guaranteed there will be exactly one
+    Ok(competitions_with_derived_quantities_from_rulesets_with_ids(
+        pool,
+        vec![competition_ruleset_with_ids],
+    ).await?.pop().unwrap())
+}
+
+// TODO: Most of the functions below could use a refactor
+pub async fn competitions_with_derived_quantities_from_rulesets_with_ids(
+    pool: &DbViewerPool,
+    competition_rulesets_with_ids: Vec<CompetitionRulesetWithIds>,
+) -> Result<Vec<CompetitionWithDerivedQuantities>> {
+    let competition_ids: Vec<i16> = competition_rulesets_with_ids.iter()
+        .map(|ruleset_with_ids| ruleset_with_ids.competition_id).collect();
+    let mut competition_names: HashMap<i16, String> = sqlx::query!(
+        "select competition_id, name
+        from competition_names
+        where competition_id = any($1)",
+        &competition_ids,
+    ).fetch_all(&pool.0).await?.into_iter().map(|record|
+        (record.competition_id.unwrap(), record.name.unwrap())).collect();
+    let mut series_names: HashMap<i16, Vec<String>> = HashMap::new();
+    for record in sqlx::query!(
+        "select competition_id, name
         from series_competitions
         join series on series_competitions.series_id = series.id
-        where competition_id = $1",
-        competition_ruleset_with_ids.competition_id
-    ).fetch_all(&pool.0).await?.into_iter().map(|record| record.name).collect();
-    let base_seed_name_records = sqlx::query!(
-        "select base_name
-        from competition_seeds
-        where
-            competition_id = $1
-            and variant_id = $2
-            and num_players = $3
-        ",
-        competition_ruleset_with_ids.competition_id,
-        competition_ruleset_with_ids.variant_id,
-        competition_ruleset_with_ids.num_players,
-    ).fetch_all(&pool.0).await?;
-    let competition = Competition {
-        ruleset: CompetitionRuleset {
-            num_players: competition_ruleset_with_ids.num_players,
-            variant_name: competition_ruleset_with_ids.variant_name,
-            end_datetime: competition_ruleset_with_ids.end_datetime,
-            deckplay_enabled: competition_ruleset_with_ids.deckplay_enabled,
-            empty_clues_enabled: competition_ruleset_with_ids.empty_clues_enabled,
-            characters_enabled: competition_ruleset_with_ids.characters_enabled,
-            scoring_type: competition_ruleset_with_ids.scoring_type,
-            time_control: match (
-                competition_ruleset_with_ids.base_time_seconds,
-                competition_ruleset_with_ids.turn_time_seconds,
-            ) {
-                (None, None) => None,
-                (Some(base_time_seconds), Some(turn_time_seconds)) => {
-                    Some(TimeControl {
-                        base_time_seconds,
-                        turn_time_seconds,
-                    })
+        where competition_id = any($1)",
+        &competition_ids,
+    ).fetch_all(&pool.0).await? {
+        series_names.entry(record.competition_id).or_default().push(record.name);
+    }
+    let mut seeds = get_competitions_seeds(pool, &competition_ids).await?;
+    let variant_ids: Vec<i32> = competition_rulesets_with_ids.iter()
+        .filter_map(|ruleset_with_ids| ruleset_with_ids.variant_id).collect();
+    let variants = get_variant_metadata(pool, &variant_ids).await?;
+    Ok(competition_rulesets_with_ids.into_iter().map(|competition_ruleset_with_ids| {
+        let competition_id = competition_ruleset_with_ids.competition_id;
+        let competition_seeds = seeds.remove(&competition_id).unwrap_or_default();
+        let competition = Competition {
+            ruleset: CompetitionRuleset {
+                num_players: competition_ruleset_with_ids.num_players,
+                variant_name: competition_ruleset_with_ids.variant_name,
+                title: competition_ruleset_with_ids.title,
+                start_datetime: competition_ruleset_with_ids.start_datetime,
+                end_datetime: competition_ruleset_with_ids.end_datetime,
+                deckplay_enabled: competition_ruleset_with_ids.deckplay_enabled,
+                empty_clues_enabled: competition_ruleset_with_ids.empty_clues_enabled,
+                characters_enabled: competition_ruleset_with_ids.characters_enabled,
+                scoring_type: competition_ruleset_with_ids.scoring_type,
+                time_control: match (
+                    competition_ruleset_with_ids.base_time_seconds,
+                    competition_ruleset_with_ids.turn_time_seconds,
+                ) {
+                    (None, None) => None,
+                    (Some(base_time_seconds), Some(turn_time_seconds)) => {
+                        Some(TimeControl {
+                            base_time_seconds,
+                            turn_time_seconds,
+                        })
+                    },
+                    _ => unreachable!(
+                        "Base time and turn time have composite nullability, \
+                        enforced by a db constraint"
+                    ),
                 },
-                _ => unreachable!(
-                    "Base time and turn time have composite nullability, \
-                    enforced by a db constraint"
-                ),
+                additional_rules: competition_ruleset_with_ids.additional_rules,
+                registration_required: competition_ruleset_with_ids.registration_required,
+                tie_breaks: parse_tie_breaks(&competition_ruleset_with_ids.tie_breaks),
             },
-            additional_rules: competition_ruleset_with_ids.additional_rules,
-        },
-        base_seed_names: base_seed_name_records.into_iter().map(|record|
-            record.base_name).collect(),
-        series_names,
+            base_seed_names: competition_seeds.iter()
+                .map(|seed| seed.base_seed_name.clone()).collect(),
+            seeds: competition_seeds,
+            series_names: series_names.remove(&competition_id).unwrap_or_default(),
+        };
+        // logically guaranteed there will be a record
+        let competition_name = competition_names.remove(&competition_id).unwrap();
+        // variants synced from the site always have suits
+        let variant = competition_ruleset_with_ids.variant_id
+            .and_then(|variant_id| variants.get(&variant_id))
+            .filter(|variant| !variant.suits.is_empty())
+            .cloned();
+        CompetitionWithDerivedQuantities::new(competition, competition_name, variant)
+    }).collect())
+}
+
+// Keyed by competition, in the order they were added
+async fn get_competitions_seeds(
+    pool: &DbViewerPool,
+    competition_ids: &[i16],
+) -> Result<HashMap<i16, Vec<CompetitionSeed>>> {
+    let mut seeds: HashMap<i16, Vec<CompetitionSeed>> = HashMap::new();
+    for record in sqlx::query!(
+
+        "select
+            competition_id
+          , base_name
+          , case
+                when competitions.variant_id is null
+                    then variants.name
+            end seed_variant_name
+          , competition_seeds.deckplay_enabled
+          , competition_seeds.empty_clues_enabled
+          , competition_seeds.base_time_seconds
+          , competition_seeds.turn_time_seconds
+        from competition_seeds
+        join competitions on competition_id = competitions.id
+        join variants on competition_seeds.variant_id = variants.id
+        where competition_id = any($1)
+        order by competition_seeds.id",
+        competition_ids,
+    ).fetch_all(&pool.0).await? {
+        seeds.entry(record.competition_id).or_default().push(CompetitionSeed {
+            base_seed_name: record.base_name,
+            variant_name: record.seed_variant_name,
+            time_control: match (record.base_time_seconds, record.turn_time_seconds) {
+                (Some(base_time_seconds), Some(turn_time_seconds)) => Some(TimeControl {
+                    base_time_seconds,
+                    turn_time_seconds,
+                }),
+                _ => None,
+            },
+            deckplay_enabled: record.deckplay_enabled,
+            empty_clues_enabled: record.empty_clues_enabled,
+        });
+    }
+    Ok(seeds)
+}
+
+// For admins, who see the seeds before the competition starts
+pub async fn get_competition_seeds(
+    pool: &DbViewerPool,
+    competition_name: &str,
+) -> Result<Vec<CompetitionSeed>> {
+    let competition_id = match get_competition_id(pool, competition_name).await? {
+        Some(competition_id) => competition_id,
+        None => return Err(GetCompetitionError::NotFound.into()),
     };
-    Ok(CompetitionWithDerivedQuantities::new(competition, competition_name))
+    Ok(get_competitions_seeds(pool, &[competition_id]).await?
+        .remove(&competition_id).unwrap_or_default())
 }
 
 async fn get_competition_flat_results(
@@ -472,7 +975,8 @@ async fn get_competition_flat_results(
         CompetitionFlatResult,
         "select
             final_rank
-          , fractional_MP
+            -- null when there's no one to be matched against
+          , coalesce(fractional_MP, 0) fractional_MP
           , sum_MP
           , player_name
           , base_seed_name
@@ -480,7 +984,16 @@ async fn get_competition_flat_results(
           , replay_URL
           , site_game_id
           , score
+          , normalized_score
+          , is_perfect_score
+          , end_condition
+          , character_name
+          , team_name
+          , max_MP
+          , penalty_MP
+          , seed_rank
           , turns
+          , datetime_game_ended
           , cast(round(extract(epoch from (datetime_game_ended - datetime_game_started))) as int)
                 as game_duration_seconds
         from computed_competition_standings
@@ -493,82 +1006,335 @@ async fn get_competition_flat_results(
 fn nest_competition_results(
     competition: CompetitionWithDerivedQuantities,
     flat_results: Vec<CompetitionFlatResult>,
+    registered_teams: Vec<RegisteredTeam>,
+    adjustments: Vec<Adjustment>,
 ) -> CompetitionNestedResults {
-    let mut player_indexed_results = HashMap::new(); // ::<String, (Vec<i64>, Vec<CompetitionFlatResult>)>
-    for result in flat_results.into_iter() {
-        match player_indexed_results.get_mut(&result.player_name) {
-            None => {
-                player_indexed_results.insert(
-                    result.player_name.clone(),
-                    (
-                        vec![result.site_game_id],
-                        vec![Some(result)]
-                    )
-                );
-            },
-            Some((games, results)) => {
-                games.push(result.site_game_id);
-                results.push(Some(result));
+    // every player's character is needed on their teammates' results too
+    let mut game_indexed_characters: HashMap<i64, Vec<PlayerCharacter>> = HashMap::new();
+    for result in &flat_results {
+        if let Some(character_name) = &result.character_name {
+            game_indexed_characters.entry(result.site_game_id).or_default().push(PlayerCharacter {
+                player_name: result.player_name.clone(),
+                character_name: character_name.clone(),
+            });
+        }
+    }
+    for characters in game_indexed_characters.values_mut() {
+        characters.sort_unstable_by(|c1, c2| c1.player_name.cmp(&c2.player_name));
+    }
+    let game_penalties = charge_penalties(&flat_results);
+    let adjusted_games: HashSet<i64> = adjustments.iter()
+        .filter_map(|adjustment| adjustment.site_game_id).collect();
+    let game_details = GameDetails {
+        characters: &game_indexed_characters,
+        adjusted_games: &adjusted_games,
+    };
+    let num_players = competition.competition.ruleset.num_players;
+    // registered teams' games are grouped by team, whoever played them
+    let (team_flat_results, flat_results): (Vec<_>, Vec<_>) = flat_results.into_iter()
+        .partition(|result| result.team_name.is_some());
+    let mut teams = reconstruct_teams(flat_results, num_players);
+    let mut team_indexed_results: HashMap<String, Vec<CompetitionFlatResult>> = HashMap::new();
+    for result in team_flat_results {
+        // partitioned on this above
+        team_indexed_results.entry(result.team_name.clone().unwrap()).or_default().push(result);
+    }
+    for (team_name, results) in team_indexed_results.into_iter() {
+        let (players, substitutes) = match registered_teams.iter()
+            .find(|registered_team| registered_team.team_name == team_name)
+        {
+            Some(registered_team) => (
+                registered_team.players.clone(),
+                registered_team.substitutes.clone(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        teams.push(ReconstructedTeam { team_name: Some(team_name), players, substitutes, results });
+    }
+    let registration_open = competition.competition.ruleset.end_datetime > chrono::offset::Utc::now();
+    let mut team_results: Vec<TeamResults> = teams.into_iter()
+        .map(|team| nest_team_results(
+            team,
+            &competition.competition,
+            &game_penalties,
+            &game_details,
+        ))
+        .collect();
+    rank_teams(&mut team_results, &competition.competition.ruleset.tie_breaks);
+    CompetitionNestedResults {
+        competition_with_derived_quantities: competition,
+        team_results,
+        registered_teams,
+        registration_open,
+        adjustments,
+    }
+}
+
+// The standings rank players rather than teams, which differ once someone has subbed in, so the
+// teams are ranked again here with the same tie-breaks. Teams that are still tied share a rank,
+// and are listed by name so the page doesn't reshuffle them between loads.
+fn rank_teams(team_results: &mut [TeamResults], tie_breaks: &[TieBreak]) {
+    team_results.sort_by(|team1, team2| team2.sum_mp.cmp(&team1.sum_mp)
+        .then_with(|| compare_by_tie_breaks(
+            tie_breaks,
+            &team1.tie_break_stats,
+            &team2.tie_break_stats,
+        ))
+        .then_with(|| team1.format_team().cmp(&team2.format_team())));
+    for idx in 0..team_results.len() {
+        team_results[idx].final_rank = idx as i64 + 1;
+        if idx == 0 || team_results[idx - 1].sum_mp != team_results[idx].sum_mp {
+            continue;
+        }
+        let (ahead, behind) = (&team_results[idx - 1], &team_results[idx]);
+        match explain_tie_break(tie_breaks, &ahead.tie_break_stats, &behind.tie_break_stats) {
+            // still tied after the whole chain
+            None => team_results[idx].final_rank = team_results[idx - 1].final_rank,
+            Some(explanation) => {
+                let note = format!("Ahead of {} {}", behind.format_team(), explanation);
+                team_results[idx - 1].tie_break_note = Some(note);
             },
         }
     }
-    let mut game_combination_indexed_results = HashMap::new(); // ::<String, TeamResults>
-    for (player_name, (mut games, results)) in player_indexed_results.into_iter() {
-        games.sort_unstable();
-        let games_key = games.into_iter().map(|site_game_id| site_game_id.to_string())
-            .collect::<Vec<String>>().join("-");
-        match game_combination_indexed_results.get_mut(&games_key) {
+}
+
+impl TeamResults {
+    // e.g. "Team (alice, bob)", or "alice, bob" for an unregistered team
+    fn format_team(&self) -> String {
+        let players = self.players.iter().flatten().cloned().collect::<Vec<String>>().join(", ");
+        match &self.team_name {
+            Some(team_name) => format!("{} ({})", team_name, players),
+            None => players,
+        }
+    }
+}
+
+// A team's players and the standings rows of its games, one row per game, before being laid out
+// by seed
+struct ReconstructedTeam {
+    team_name: Option<String>,
+    players: Vec<String>,
+    substitutes: Vec<String>,
+    results: Vec<CompetitionFlatResult>,
+}
+
+// A game, with whichever of its players have a row for it in the standings
+struct GameLineup {
+    player_names: Vec<String>,
+    result: CompetitionFlatResult,
+}
+
+// Unregistered teams are pieced together from who played with whom. Two games that share at
+// least half their players are the same team, as long as it hasn't already played either's seed,
+// and games sharing the most players are joined first. That way a substitute's game joins the
+// team they subbed for, and someone who played a seed with two different teams ends up on both
+// rather than merging them. 2-player games have to share both players, since one in common would
+// chain everyone who rotated partners into a single team; a 2-player sub has to register.
+fn reconstruct_teams(
+    flat_results: Vec<CompetitionFlatResult>,
+    num_players: i16,
+) -> Vec<ReconstructedTeam> {
+    let mut game_indexed_lineups: BTreeMap<i64, GameLineup> = BTreeMap::new();
+    for result in flat_results {
+        match game_indexed_lineups.get_mut(&result.site_game_id) {
+            Some(lineup) => lineup.player_names.push(result.player_name),
             None => {
-                game_combination_indexed_results.insert(
-                    games_key,
-                    (vec![Some(player_name)], results)
-                );
-            }
-            Some((players, _)) => {
-                players.push(Some(player_name));
+                game_indexed_lineups.insert(result.site_game_id, GameLineup {
+                    player_names: vec![result.player_name.clone()],
+                    result,
+                });
+            },
+        }
+    }
+    // in order of site game ID, so that ties go the same way every time
+    let lineups: Vec<GameLineup> = game_indexed_lineups.into_values().collect();
+    let mut player_indexed_games: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, lineup) in lineups.iter().enumerate() {
+        for player_name in &lineup.player_names {
+            player_indexed_games.entry(player_name).or_default().push(idx);
+        }
+    }
+    let mut num_shared_players: HashMap<(usize, usize), usize> = HashMap::new();
+    for games in player_indexed_games.values() {
+        for (i, &game1) in games.iter().enumerate() {
+            for &game2 in &games[i + 1..] {
+                *num_shared_players.entry((game1, game2)).or_default() += 1;
             }
         }
     }
-    let mut competition_nested_results = CompetitionNestedResults {
-        competition_with_derived_quantities: competition, 
-        team_results: Vec::new(),
+    let min_shared_players = match num_players {
+        2 => 2,
+        _ => (num_players.max(1) as usize).div_ceil(2),
     };
-    for (_, (mut players, mut results)) in game_combination_indexed_results.into_iter() {
-        players.sort_unstable();
-        let competition =
-            &competition_nested_results.competition_with_derived_quantities.competition;
-        while players.len() < competition.ruleset.num_players as usize {
-            players.push(None);
+    let mut game_pairs: Vec<((usize, usize), usize)> = num_shared_players.into_iter()
+        .filter(|&(_, num_shared)| num_shared >= min_shared_players)
+        .collect();
+    game_pairs.sort_unstable_by(|(pair1, num_shared1), (pair2, num_shared2)|
+        num_shared2.cmp(num_shared1).then(pair1.cmp(pair2)));
+    // a union-find over games, keeping track of the seeds each team has played
+    let mut parents: Vec<usize> = (0..lineups.len()).collect();
+    let mut team_seeds: Vec<HashSet<&str>> = lineups.iter()
+        .map(|lineup| HashSet::from([lineup.result.base_seed_name.as_str()]))
+        .collect();
+    for ((game1, game2), _) in game_pairs {
+        let team1 = find_team(&mut parents, game1);
+        let team2 = find_team(&mut parents, game2);
+        if team1 == team2 || !team_seeds[team1].is_disjoint(&team_seeds[team2]) {
+            continue;
         }
-        results.sort_unstable_by(|r1,  r2|
-            r1.as_ref().unwrap().base_seed_name.cmp(&r2.as_ref().unwrap().base_seed_name));
-        for (idx, base_seed_name) in competition.base_seed_names.iter().enumerate() {
-            if results.iter().find(
-                |&r| r.is_some() && &r.as_ref().unwrap().base_seed_name == base_seed_name
-            ).is_none() {
-                results.insert(idx, None);
+        let seeds = std::mem::take(&mut team_seeds[team2]);
+        team_seeds[team1].extend(seeds);
+        parents[team2] = team1;
+    }
+    let teams: Vec<usize> = (0..lineups.len()).map(|idx| find_team(&mut parents, idx)).collect();
+    let mut team_indexed_lineups: BTreeMap<usize, Vec<GameLineup>> = BTreeMap::new();
+    for (team, lineup) in teams.into_iter().zip(lineups) {
+        team_indexed_lineups.entry(team).or_default().push(lineup);
+    }
+    team_indexed_lineups.into_values().map(|lineups| {
+        // whoever played the most of the team's games is on it; anyone else subbed in
+        let mut num_games_played: HashMap<String, usize> = HashMap::new();
+        for lineup in &lineups {
+            for player_name in &lineup.player_names {
+                *num_games_played.entry(player_name.clone()).or_default() += 1;
             }
         }
-        let first_result = results.iter().find(|&r| r.is_some()).unwrap().as_ref().unwrap();
-        competition_nested_results.team_results.push(TeamResults{
+        let mut player_names: Vec<(String, usize)> = num_games_played.into_iter().collect();
+        player_names.sort_unstable_by(|(name1, num_games1), (name2, num_games2)|
+            num_games2.cmp(num_games1).then(name1.cmp(name2)));
+        let mut players: Vec<String> = player_names.into_iter().map(|(name, _)| name).collect();
+        let mut substitutes = players.split_off((num_players as usize).min(players.len()));
+        players.sort_unstable();
+        substitutes.sort_unstable();
+        ReconstructedTeam {
+            team_name: None,
             players,
-            final_rank: first_result.final_rank, 
-            fractional_mp: first_result.fractional_mp, 
-            sum_mp: first_result.sum_mp, 
-            game_results: results.into_iter().map(|optional_result| match optional_result {
-                Some(result) => Some(GameResult{
-                    seed_matchpoints: result.seed_matchpoints,
-                    score: result.score,
-                    turns: result.turns,
-                    site_game_id: result.site_game_id,
-                    replay_url: result.replay_url,
-                    game_duration_seconds: result.game_duration_seconds,
-                }),
-                None => None,
-            }).collect(),
-        })
+            substitutes,
+            results: lineups.into_iter().map(|lineup| lineup.result).collect(),
+        }
+    }).collect()
+}
+
+fn find_team(parents: &mut [usize], mut idx: usize) -> usize {
+    while parents[idx] != idx {
+        parents[idx] = parents[parents[idx]];
+        idx = parents[idx];
     }
-    competition_nested_results
+    idx
+}
+
+// A team is docked its players' penalties, which aren't in its games' matchpoints. The standings
+// take a penalty off the player's own total once, so a player on two teams is only charged to
+// the one holding their earliest game. Keyed by that game's site game ID, since each game belongs
+// to a single team.
+fn charge_penalties(flat_results: &[CompetitionFlatResult]) -> HashMap<i64, i64> {
+    let mut player_first_games: HashMap<&str, (i64, i64)> = HashMap::new();
+    for result in flat_results {
+        let penalty_mp = match result.penalty_mp {
+            Some(penalty_mp) if penalty_mp != 0 => penalty_mp as i64,
+            _ => continue,
+        };
+        let first_game = player_first_games.entry(&result.player_name)
+            .or_insert((result.site_game_id, penalty_mp));
+        first_game.0 = first_game.0.min(result.site_game_id);
+    }
+    let mut game_penalties: HashMap<i64, i64> = HashMap::new();
+    for (site_game_id, penalty_mp) in player_first_games.into_values() {
+        *game_penalties.entry(site_game_id).or_default() += penalty_mp;
+    }
+    game_penalties
+}
+
+// A registered team's results have a row per player of each game
+fn team_penalty_mp(results: &[CompetitionFlatResult], game_penalties: &HashMap<i64, i64>) -> i64 {
+    let site_game_ids: HashSet<i64> = results.iter().map(|result| result.site_game_id).collect();
+    site_game_ids.iter().filter_map(|site_game_id| game_penalties.get(site_game_id)).sum()
+}
+
+// What's shown alongside each game's result
+struct GameDetails<'a> {
+    characters: &'a HashMap<i64, Vec<PlayerCharacter>>,
+    adjusted_games: &'a HashSet<i64>,
+}
+
+fn nest_team_results(
+    mut team: ReconstructedTeam,
+    competition: &Competition,
+    game_penalties: &HashMap<i64, i64>,
+    game_details: &GameDetails,
+) -> TeamResults {
+    // a registered team can have several games on a seed when its players split up; its first
+    // one counts
+    team.results.sort_unstable_by_key(|result| result.site_game_id);
+    let max_mp = team.results.first().and_then(|result| result.max_mp).unwrap_or(0);
+    let results: Vec<Option<CompetitionFlatResult>> = competition.base_seed_names.iter()
+        .map(|base_seed_name| team.results.iter()
+            .find(|result| &result.base_seed_name == base_seed_name)
+            .cloned())
+        .collect();
+    let penalty_mp = team_penalty_mp(&team.results, game_penalties);
+    let sum_mp: i64 = results.iter().flatten()
+        .map(|result| result.seed_matchpoints as i64).sum::<i64>() - penalty_mp;
+    let tie_break_stats = TieBreakStats {
+        total_score: results.iter().flatten().map(|result| result.score as i64).sum(),
+        total_turns: results.iter().flatten().map(|result| result.turns as i64).sum(),
+        seeds_won: results.iter().flatten()
+            .filter(|result| result.seed_rank == Some(1)).count() as i64,
+        completed_at: results.iter().flatten().map(|result| result.datetime_game_ended).max(),
+    };
+    let mut players: Vec<Option<String>> = team.players.into_iter().map(Some).collect();
+    while players.len() < competition.ruleset.num_players as usize {
+        players.push(None);
+    }
+    TeamResults {
+        team_name: team.team_name,
+        players,
+        substitutes: team.substitutes,
+        // ranked once every team's been nested
+        final_rank: 0,
+        fractional_mp: if max_mp == 0 { 0.0 } else { sum_mp as f64 / max_mp as f64 },
+        sum_mp,
+        penalty_mp,
+        normalized_score: mean_normalized_score(&results),
+        game_results: results.into_iter()
+            .map(|optional_result| optional_result
+                .map(|result| game_result(result, game_details)))
+            .collect(),
+        tie_break_stats,
+        // noted once every team's been ranked
+        tie_break_note: None,
+    }
+}
+
+fn game_result(
+    result: CompetitionFlatResult,
+    game_details: &GameDetails,
+) -> GameResult {
+    GameResult {
+        seed_matchpoints: result.seed_matchpoints,
+        score: result.score,
+        normalized_score: result.normalized_score,
+        is_perfect_score: result.is_perfect_score,
+        turns: result.turns,
+        site_game_id: result.site_game_id,
+        replay_url: result.replay_url,
+        game_duration_seconds: result.game_duration_seconds,
+        end_condition: result.end_condition,
+        characters: game_details.characters.get(&result.site_game_id)
+            .cloned().unwrap_or_default(),
+        adjusted: game_details.adjusted_games.contains(&result.site_game_id),
+    }
+}
+
+// None if any of the games' variants has an unknown max score
+fn mean_normalized_score(results: &[Option<CompetitionFlatResult>]) -> Option<f64> {
+    let normalized_scores = results.iter().flatten()
+        .map(|result| result.normalized_score)
+        .collect::<Option<Vec<f64>>>()?;
+    if normalized_scores.is_empty() {
+        return None;
+    }
+    Some(normalized_scores.iter().sum::<f64>() / normalized_scores.len() as f64)
 }
 
 async fn add_competition(
@@ -576,11 +1342,17 @@ async fn add_competition(
     partially_specified_competition: PartiallySpecifiedCompetition,
 ) -> Result<Tx> {
     let competition = partially_specified_competition.fill_missing_values_with_defaults();
+    competition.validate_seeds()?;
+    validate_tie_breaks(&competition.ruleset.tie_breaks)?;
     let ruleset = &competition.ruleset;
-    let variant_id = sqlx::query!(
-        "SELECT id from variants WHERE name = $1",
-        ruleset.variant_name
-    ).fetch_one(&mut tx).await?.id;
+    let variant_id = match &ruleset.variant_name {
+        None => None,
+        Some(variant_name) => {
+            let (next_tx, variant_id) = select_variant_id(tx, variant_name).await?;
+            tx = next_tx;
+            Some(variant_id)
+        },
+    };
 
     let (base_time_seconds, turn_time_seconds) = match &ruleset.time_control {
         None => (None, None),
@@ -602,6 +1374,10 @@ async fn add_competition(
           , base_time_seconds
           , turn_time_seconds
           , additional_rules
+          , start_datetime
+          , title
+          , registration_required
+          , tie_breaks
         ) VALUES (
             $1
           , $2
@@ -613,6 +1389,10 @@ async fn add_competition(
           , $8
           , $9
           , $10
+          , $11
+          , $12
+          , $13
+          , $14
         ) RETURNING id"#)
         .bind(ruleset.end_datetime)
         .bind(ruleset.num_players)
@@ -624,6 +1404,10 @@ async fn add_competition(
         .bind(base_time_seconds)
         .bind(turn_time_seconds)
         .bind(&ruleset.additional_rules)
+        .bind(ruleset.start_datetime)
+        .bind(&ruleset.title)
+        .bind(ruleset.registration_required)
+        .bind(tie_break_strs(&ruleset.tie_breaks))
         .map(|row: PgRow| row.get(0))
         .fetch_one(&mut tx).await?;
 
@@ -647,24 +1431,220 @@ async fn add_competition(
         ).execute(&mut tx).await?;
     }
 
-    for base_seed_name in competition.base_seed_names {
+    for seed in &competition.seeds {
+        let seed_variant_id = match (&seed.variant_name, variant_id) {
+            (Some(variant_name), _) => {
+                let (next_tx, seed_variant_id) = select_variant_id(tx, variant_name).await?;
+                tx = next_tx;
+                seed_variant_id
+            },
+            // validated to have one or the other
+            (None, variant_id) => variant_id.unwrap(),
+        };
+        let (base_time_seconds, turn_time_seconds) = match &seed.time_control {
+            None => (None, None),
+            Some(time_control) => (
+                Some(time_control.base_time_seconds),
+                Some(time_control.turn_time_seconds),
+            ),
+        };
         sqlx::query!(
             "INSERT INTO competition_seeds (
                 competition_id
               , num_players
               , variant_id
               , base_name
+              , deckplay_enabled
+              , empty_clues_enabled
+              , base_time_seconds
+              , turn_time_seconds
             ) VALUES (
                 $1
               , $2
               , $3
               , $4
+              , $5
+              , $6
+              , $7
+              , $8
             )",
             competition_id,
             ruleset.num_players,
-            variant_id,
-            base_seed_name,
+            seed_variant_id,
+            seed.base_seed_name,
+            seed.deckplay_enabled,
+            seed.empty_clues_enabled,
+            base_time_seconds,
+            turn_time_seconds,
         ).execute(&mut tx).await?;
     }
     Ok(tx)
 }
+
+// Removed variants can't be picked for new competitions
+async fn select_variant_id(mut tx: Tx, variant_name: &str) -> Result<(Tx, i32)> {
+    let variant_id = sqlx::query!(
+        "SELECT id from variants WHERE name = $1 and removed_at is null",
+        variant_name,
+    ).fetch_one(&mut tx).await?.id;
+    Ok((tx, variant_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use super::*;
+
+    // One standings row per player of the game
+    fn game(
+        site_game_id: i64,
+        base_seed_name: &str,
+        player_names: &[&str],
+    ) -> Vec<CompetitionFlatResult> {
+        player_names.iter().map(|player_name| CompetitionFlatResult {
+            final_rank: 1,
+            fractional_mp: 0.0,
+            sum_mp: 0,
+            player_name: player_name.to_string(),
+            base_seed_name: base_seed_name.to_owned(),
+            seed_matchpoints: 0,
+            site_game_id,
+            replay_url: String::new(),
+            score: 25,
+            normalized_score: None,
+            is_perfect_score: None,
+            end_condition: None,
+            character_name: None,
+            team_name: None,
+            max_mp: None,
+            penalty_mp: None,
+            seed_rank: None,
+            turns: 60,
+            datetime_game_ended: Utc::now(),
+            game_duration_seconds: 600,
+        }).collect()
+    }
+
+    // Each team's players, substitutes and site game IDs, in a stable order
+    fn rosters(teams: Vec<ReconstructedTeam>) -> Vec<(Vec<String>, Vec<String>, Vec<i64>)> {
+        let mut rosters: Vec<_> = teams.into_iter().map(|team| {
+            let mut site_game_ids: Vec<i64> = team.results.iter()
+                .map(|result| result.site_game_id).collect();
+            site_game_ids.sort_unstable();
+            (team.players, team.substitutes, site_game_ids)
+        }).collect();
+        rosters.sort();
+        rosters
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    fn team(players: &[&str], sum_mp: i64, total_score: i64) -> TeamResults {
+        TeamResults {
+            team_name: None,
+            players: players.iter().map(|player| Some(player.to_string())).collect(),
+            substitutes: Vec::new(),
+            final_rank: 0,
+            fractional_mp: 0.0,
+            sum_mp,
+            penalty_mp: 0,
+            normalized_score: None,
+            game_results: Vec::new(),
+            tie_break_stats: TieBreakStats { total_score, ..TieBreakStats::default() },
+            tie_break_note: None,
+        }
+    }
+
+    #[test]
+    fn teams_tied_on_matchpoints_go_by_tie_breaks_then_name() {
+        let mut team_results = vec![
+            team(&["erin", "frank"], 4, 40),
+            team(&["carol", "dave"], 4, 40),
+            team(&["gina", "hank"], 2, 50),
+            team(&["alice", "bob"], 4, 45),
+        ];
+        rank_teams(&mut team_results, &[TieBreak::TotalScore]);
+        let ranks: Vec<(String, i64)> = team_results.iter()
+            .map(|team| (team.format_team(), team.final_rank))
+            .collect();
+        assert_eq!(ranks, vec![
+            ("alice, bob".to_owned(), 1),
+            ("carol, dave".to_owned(), 2),
+            ("erin, frank".to_owned(), 2),
+            ("gina, hank".to_owned(), 4),
+        ]);
+        assert_eq!(
+            team_results[0].tie_break_note.as_deref(),
+            Some("Ahead of carol, dave on total score (45 vs 40)"),
+        );
+        assert_eq!(team_results[1].tie_break_note, None);
+    }
+
+    #[test]
+    fn substitute_joins_the_team_they_subbed_for() {
+        let flat_results = [
+            game(1, "s1", &["alice", "bob", "carol"]),
+            game(2, "s2", &["alice", "bob", "carol"]),
+            game(3, "s3", &["alice", "bob", "dave"]),
+        ].concat();
+        assert_eq!(rosters(reconstruct_teams(flat_results, 3)), vec![
+            (names(&["alice", "bob", "carol"]), names(&["dave"]), vec![1, 2, 3]),
+        ]);
+    }
+
+    #[test]
+    fn player_on_two_teams_on_one_seed_is_on_both() {
+        let flat_results = [
+            game(1, "s1", &["alice", "bob", "carol"]),
+            game(2, "s2", &["alice", "bob", "carol"]),
+            game(3, "s1", &["alice", "bob", "dave"]),
+            game(4, "s2", &["alice", "bob", "dave"]),
+        ].concat();
+        assert_eq!(rosters(reconstruct_teams(flat_results, 3)), vec![
+            (names(&["alice", "bob", "carol"]), Vec::new(), vec![1, 2]),
+            (names(&["alice", "bob", "dave"]), Vec::new(), vec![3, 4]),
+        ]);
+    }
+
+    #[test]
+    fn rotating_partners_in_2p_stay_separate_teams() {
+        let flat_results = [
+            game(1, "s1", &["alice", "bob"]),
+            game(2, "s2", &["alice", "bob"]),
+            game(3, "s1", &["alice", "carol"]),
+            game(4, "s2", &["bob", "carol"]),
+        ].concat();
+        assert_eq!(rosters(reconstruct_teams(flat_results, 2)), vec![
+            (names(&["alice", "bob"]), Vec::new(), vec![1, 2]),
+            (names(&["alice", "carol"]), Vec::new(), vec![3]),
+            (names(&["bob", "carol"]), Vec::new(), vec![4]),
+        ]);
+    }
+
+    #[test]
+    fn penalty_of_player_on_two_teams_is_charged_once() {
+        let mut flat_results = [
+            game(1, "s1", &["alice", "bob", "carol"]),
+            game(2, "s2", &["alice", "bob", "carol"]),
+            game(3, "s1", &["alice", "bob", "dave"]),
+            game(4, "s2", &["alice", "bob", "dave"]),
+        ].concat();
+        for result in &mut flat_results {
+            if result.player_name == "alice" {
+                result.penalty_mp = Some(3);
+            }
+        }
+        let game_penalties = charge_penalties(&flat_results);
+        let mut team_penalties: Vec<(Vec<String>, i64)> = reconstruct_teams(flat_results, 3)
+            .into_iter()
+            .map(|team| (team.players, team_penalty_mp(&team.results, &game_penalties)))
+            .collect();
+        team_penalties.sort();
+        assert_eq!(team_penalties, vec![
+            (names(&["alice", "bob", "carol"]), 3),
+            (names(&["alice", "bob", "dave"]), 0),
+        ]);
+    }
+}
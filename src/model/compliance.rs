@@ -0,0 +1,222 @@
+// Checks that ingested games were played with their competition's options, from the game's
+// export or the options posted along with it
+use std::fmt;
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use crate::model::{
+    Tx,
+    game::Game,
+    game_export::GameOptions,
+};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ComplianceReason {
+    Seed,
+    DeckPlays,
+    EmptyClues,
+    DetrimentalCharacters,
+    TimeControl,
+}
+
+impl ComplianceReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComplianceReason::Seed => "seed",
+            ComplianceReason::DeckPlays => "deck_plays",
+            ComplianceReason::EmptyClues => "empty_clues",
+            ComplianceReason::DetrimentalCharacters => "detrimental_characters",
+            ComplianceReason::TimeControl => "time_control",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ComplianceViolation {
+    pub reason: ComplianceReason,
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct NoncompliantGame {
+    pub site_game_id: i64,
+    pub violations: Vec<ComplianceViolation>,
+}
+
+impl NoncompliantGame {
+    // Stored as the blacklist reason, e.g. "compliance:deck_plays,time_control"
+    pub fn blacklist_reason(&self) -> String {
+        format!(
+            "compliance:{}",
+            self.violations.iter().map(|violation| violation.reason.as_str())
+                .collect::<Vec<&str>>().join(","),
+        )
+    }
+}
+
+// What a seed's games have to have been played with
+pub(crate) struct SeedRuleset {
+    pub seed: String,
+    pub deck_plays: bool,
+    pub empty_clues: bool,
+    pub detrimental_characters: bool,
+    // base and per-turn seconds
+    pub time_control: Option<(i32, i32)>,
+}
+
+pub(crate) async fn get_seed_ruleset(mut tx: Tx, seed_id: i16) -> Result<(Tx, SeedRuleset)> {
+    let record = sqlx::query!(
+        "select
+            competition_seeds.base_name
+          , competition_seeds.num_players
+          , variants.site_variant_id
+            -- seeds may have their own options and time control
+          , coalesce(competition_seeds.deckplay_enabled, competitions.deckplay_enabled)
+                deckplay_enabled
+          , coalesce(competition_seeds.empty_clues_enabled, competitions.empty_clues_enabled)
+                empty_clues_enabled
+          , competitions.characters_enabled
+          , coalesce(competition_seeds.base_time_seconds, competitions.base_time_seconds)
+                base_time_seconds
+          , coalesce(competition_seeds.turn_time_seconds, competitions.turn_time_seconds)
+                turn_time_seconds
+        from competition_seeds
+        join competitions on competition_seeds.competition_id = competitions.id
+        join variants on competition_seeds.variant_id = variants.id
+        where competition_seeds.id = $1",
+        seed_id,
+    ).fetch_one(&mut tx).await?;
+    let seed_ruleset = SeedRuleset {
+        // as the site names the seed of a table created with `!seed <base seed name>`
+        seed: format!("p{}v{}s{}", record.num_players, record.site_variant_id, record.base_name),
+        deck_plays: record.deckplay_enabled.unwrap(),
+        empty_clues: record.empty_clues_enabled.unwrap(),
+        detrimental_characters: record.characters_enabled,
+        time_control: match (record.base_time_seconds, record.turn_time_seconds) {
+            (Some(base_time_seconds), Some(turn_time_seconds)) =>
+                Some((base_time_seconds as i32, turn_time_seconds as i32)),
+            _ => None,
+        },
+    };
+    Ok((tx, seed_ruleset))
+}
+
+struct DisplayTimeControl(Option<(i32, i32)>);
+
+impl fmt::Display for DisplayTimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "untimed"),
+            Some((base_time_seconds, turn_time_seconds)) =>
+                write!(f, "{}s + {}s/turn", base_time_seconds, turn_time_seconds),
+        }
+    }
+}
+
+// None if the game carries neither an export nor options, so there's nothing to check
+pub(crate) fn check_game(
+    seed_ruleset: &SeedRuleset,
+    game: &Game,
+) -> Option<Vec<ComplianceViolation>> {
+    let (options, seed): (&GameOptions, Option<&String>) = match (&game.export, &game.options) {
+        (Some(export), _) => (&export.options, export.seed.as_ref().or(game.seed.as_ref())),
+        (None, Some(options)) => (options, game.seed.as_ref()),
+        (None, None) => return None,
+    };
+    let mut violations = Vec::new();
+    if let Some(seed) = seed {
+        if seed != &seed_ruleset.seed {
+            violations.push(ComplianceViolation {
+                reason: ComplianceReason::Seed,
+                expected: seed_ruleset.seed.clone(),
+                actual: seed.clone(),
+            });
+        }
+    }
+    // the site leaves options that are off out of its exports
+    let flags = [
+        (ComplianceReason::DeckPlays, seed_ruleset.deck_plays, options.deck_plays),
+        (ComplianceReason::EmptyClues, seed_ruleset.empty_clues, options.empty_clues),
+        (
+            ComplianceReason::DetrimentalCharacters,
+            seed_ruleset.detrimental_characters,
+            options.detrimental_characters,
+        ),
+    ];
+    for &(reason, expected, actual) in &flags {
+        let actual = actual.unwrap_or(false);
+        if actual != expected {
+            violations.push(ComplianceViolation {
+                reason,
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            });
+        }
+    }
+    let time_control = if options.timed.unwrap_or(false) {
+        Some((options.time_base.unwrap_or(0), options.time_per_turn.unwrap_or(0)))
+    } else {
+        None
+    };
+    if time_control != seed_ruleset.time_control {
+        violations.push(ComplianceViolation {
+            reason: ComplianceReason::TimeControl,
+            expected: DisplayTimeControl(seed_ruleset.time_control).to_string(),
+            actual: DisplayTimeControl(time_control).to_string(),
+        });
+    }
+    Some(violations)
+}
+
+// Excluded from the standings like any other blacklisted game, so an admin can reinstate a game
+// by taking it off the blacklist. Games an admin whitelisted, or already blacklisted with their
+// own reason, are left alone; returns whether the game was whitelisted.
+pub(crate) async fn blacklist_noncompliant_game(
+    mut tx: Tx,
+    game_id: i32,
+    noncompliant_game: &NoncompliantGame,
+) -> Result<(Tx, bool)> {
+    // listed by site ID, like the game lists are, so it holds when the game is posted again
+    let whitelisted = sqlx::query!(
+        "select exists(
+            select 1
+            from whitelisted_games
+                join games on games.id = whitelisted_games.game_id
+            where games.site_game_id = $1
+        ) as whitelisted",
+        noncompliant_game.site_game_id,
+    ).fetch_one(&mut tx).await?.whitelisted.unwrap_or(false);
+    if whitelisted {
+        return Ok((tx, true));
+    }
+    sqlx::query!(
+        "insert into blacklisted_games (game_id, reason)
+        values ($1, $2)
+        on conflict (game_id) do nothing",
+        game_id,
+        noncompliant_game.blacklist_reason(),
+    ).execute(&mut tx).await?;
+    Ok((tx, false))
+}
+
+// Appended to the responses of the endpoints that ingest games
+pub fn summarize_noncompliant_games(noncompliant_games: &[NoncompliantGame]) -> String {
+    if noncompliant_games.is_empty() {
+        return "".to_owned();
+    }
+    format!(
+        " {} of them were blacklisted for breaking the competition's ruleset: {}.",
+        noncompliant_games.len(),
+        noncompliant_games.iter().map(|noncompliant_game| format!(
+            "{} ({})",
+            noncompliant_game.site_game_id,
+            noncompliant_game.violations.iter().map(|violation| format!(
+                "{}: expected {}, got {}",
+                violation.reason.as_str(),
+                violation.expected,
+                violation.actual,
+            )).collect::<Vec<String>>().join("; "),
+        )).collect::<Vec<String>>().join(", "),
+    )
+}
@@ -1,6 +1,28 @@
 use anyhow::Result;
+use sqlx::postgres::PgQueryAs;
 use serde::{Serialize, Deserialize};
-use crate::model::{UtcDateTime, Date, Tx};
+use crate::{
+    DbAdminPool,
+    hanabi::EndCondition,
+    model::{
+        UtcDateTime,
+        Date,
+        Tx,
+        audit::{commit_audited, AuditContext},
+        character::get_character_ids,
+        competition::update_competition_standings,
+        compliance::{
+            blacklist_noncompliant_game,
+            check_game,
+            get_seed_ruleset,
+            summarize_noncompliant_games,
+            NoncompliantGame,
+        },
+        game_export::{store_game_export, GameExport, GameOptions},
+        replay::{replay_games, summarize_flagged_replays, GameReplay},
+        team::attribute_games_to_teams,
+    },
+};
 
 #[derive(thiserror::Error, Debug)]
 pub enum CompetitionGamesError {
@@ -12,8 +34,9 @@ pub enum CompetitionGamesError {
 pub struct CompetitionGames {
     pub num_players: i16,
     // need the variant ID at the point of retrieving games anyway, since it constitutes part
-    // of the full seed name
-    pub variant_id: i32,
+    // of the full seed name; seeds with their own variant give it in `SeedGames` instead
+    #[serde(default)]
+    pub variant_id: Option<i32>,
     pub end_date: Date,
     pub seeds_games: Vec<SeedGames>
 }
@@ -22,6 +45,11 @@ impl CompetitionGames {
     pub fn validate(&self) -> Result<(), CompetitionGamesError> {
         let num_players = self.num_players;
         for seed_games in &self.seeds_games {
+            if seed_games.variant_id.or(self.variant_id).is_none() {
+                return Err(CompetitionGamesError::Consistency(format!(
+                    "no variant_id for seed {}", &seed_games.base_seed_name
+                )));
+            }
             for game_results in &seed_games.games {
                 if game_results.players.len() != num_players as usize
                 {
@@ -29,6 +57,19 @@ impl CompetitionGames {
                         "num_players = {}; players = {:?}", num_players, &game_results.players
                     )));
                 }
+                if let Some(export) = &game_results.export {
+                    export.validate(game_results.game_id, &game_results.players)
+                        .map_err(|err| CompetitionGamesError::Consistency(err.to_string()))?;
+                }
+                if let Some(characters) = &game_results.characters {
+                    if characters.len() != game_results.players.len() {
+                        return Err(CompetitionGamesError::Consistency(format!(
+                            "players = {:?}; characters = {:?}",
+                            &game_results.players,
+                            characters,
+                        )));
+                    }
+                }
             }
         }
         Ok(())
@@ -38,6 +79,9 @@ impl CompetitionGames {
 #[derive(Serialize, Deserialize)]
 pub struct SeedGames {
     pub base_seed_name: String,
+    // the seed's site variant ID, for competitions whose seeds each have their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant_id: Option<i32>,
     pub games: Vec<Game>,
 }
 
@@ -49,22 +93,213 @@ pub struct Game {
     pub turns: i16,
     pub datetime_started: UtcDateTime,
     pub datetime_ended: UtcDateTime,
+    // derived from the export when there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_condition: Option<EndCondition>,
+    // the score reached before a strikeout or termination, which the standings use instead of
+    // the site's 0
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pre_strikeout_score: Option<i16>,
+    // the game's full export from hanab.live, if available
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<GameExport>,
+    // what the game was played with, for games without an export; checked against the
+    // competition's ruleset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub options: Option<GameOptions>,
+    // e.g. p2v0sa-1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    // the detrimental characters dealt to `players`, in the same order, for games without an
+    // export
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub characters: Option<Vec<String>>,
+}
+
+impl Game {
+    // In the order of `players`, preferring the export's, which are in seat order
+    fn character_names(&self) -> Vec<Option<String>> {
+        let export_characters = self.export.as_ref().and_then(|export|
+            export.characters.as_ref().map(|characters| (&export.players, characters)));
+        match (export_characters, &self.characters) {
+            (Some((seated_players, characters)), _) => self.players.iter().map(|player|
+                seated_players.iter().position(|seated_player| seated_player == player)
+                    .and_then(|seat| characters.get(seat))
+                    .map(|character| character.name.clone())
+            ).collect(),
+            (None, Some(characters)) => characters.iter().cloned().map(Some).collect(),
+            (None, None) => vec![None; self.players.len()],
+        }
+    }
+}
+
+// What ingestion found wrong with the games, if anything
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct IngestReport {
+    pub flagged_replays: Vec<GameReplay>,
+    pub noncompliant_games: Vec<NoncompliantGame>,
+    // the games were checked, then rolled back
+    pub dry_run: bool,
+}
+
+impl IngestReport {
+    // Appended to the responses of the endpoints that ingest games
+    pub fn summarize(&self) -> String {
+        format!(
+            "{}{}",
+            summarize_noncompliant_games(&self.noncompliant_games),
+            summarize_flagged_replays(&self.flagged_replays),
+        )
+    }
 }
 
 pub async fn add_competitions_games(
-    pool: &super::super::DbAdminPool,
+    pool: &DbAdminPool,
     competitions_games: &Vec<CompetitionGames>,
-) -> Result<()> {
+    audit: &AuditContext,
+) -> Result<IngestReport> {
     // if a single competition causes an error, don't commit any
     let mut tx = pool.0.begin().await?;
+    let mut affected_competition_ids = Vec::new();
+    let mut noncompliant_games = Vec::new();
     for games in competitions_games {
         // I'd prefer to use references throughout, but I don't know a better pattern that would
         // allow me to pass the same mutable borrow to multiple functions.
-        tx = add_competition_games(tx, games).await?;
+        let (next_tx, competition_ids, competition_noncompliant_games) =
+            add_competition_games(tx, games).await?;
+        tx = next_tx;
+        affected_competition_ids.extend(competition_ids);
+        noncompliant_games.extend(competition_noncompliant_games);
     }
-    sqlx::query("select update_computed_competition_standings()").execute(&mut tx).await?;
-    tx.commit().await?;
-    Ok(())
+    affected_competition_ids.sort_unstable();
+    affected_competition_ids.dedup();
+    let games: Vec<&Game> = competitions_games.iter()
+        .flat_map(|games| &games.seeds_games)
+        .flat_map(|seed_games| &seed_games.games)
+        .collect();
+    let exported_site_game_ids: Vec<i64> = games.iter()
+        .filter(|game| game.export.is_some())
+        .map(|game| game.game_id)
+        .collect();
+    let tx = attribute_games_to_teams(tx, &affected_competition_ids).await?;
+    // before the standings, since replays fill in end conditions and pre-strikeout scores
+    let (tx, flagged_replays) = replay_games(tx, &exported_site_game_ids).await?;
+    // Only recompute the standings of competitions that received games, rather than all of them
+    let tx = update_competition_standings(tx, &affected_competition_ids).await?;
+    commit_audited(tx, audit, games.len() as u64).await?;
+    Ok(IngestReport { flagged_replays, noncompliant_games, dry_run: audit.dry_run })
+}
+
+// Whitelisted games take priority over a player's other games on the same seed, and
+// blacklisted games are excluded from the standings entirely.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum GameList {
+    Whitelist,
+    Blacklist,
+}
+
+impl GameList {
+    fn table_name(&self) -> &'static str {
+        match self {
+            GameList::Whitelist => "whitelisted_games",
+            GameList::Blacklist => "blacklisted_games",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameListEntry {
+    pub site_game_id: i64,
+    pub reason: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GameListError {
+    #[error("No game with site ID {0} was found")]
+    NotFound(i64),
+}
+
+pub async fn add_to_game_list(
+    pool: &DbAdminPool,
+    game_list: GameList,
+    entries: &[GameListEntry],
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    for entry in entries {
+        let num_rows = sqlx::query(&format!(
+            "insert into {} (game_id, reason)
+            select id, $2
+            from games
+            where site_game_id = $1
+            on conflict (game_id) do update set reason = excluded.reason",
+            game_list.table_name(),
+        ))
+            .bind(entry.site_game_id)
+            .bind(&entry.reason)
+            .execute(&mut tx).await?;
+        if num_rows == 0 {
+            return Err(GameListError::NotFound(entry.site_game_id).into());
+        }
+    }
+    let site_game_ids: Vec<i64> = entries.iter().map(|entry| entry.site_game_id).collect();
+    tx = update_standings_of_site_games(tx, &site_game_ids).await?;
+    commit_audited(tx, audit, entries.len() as u64).await
+}
+
+pub async fn remove_from_game_list(
+    pool: &DbAdminPool,
+    game_list: GameList,
+    site_game_ids: &[i64],
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_rows = sqlx::query(&format!(
+        "delete from {}
+        where game_id in (select id from games where site_game_id = any($1))",
+        game_list.table_name(),
+    ))
+        .bind(site_game_ids)
+        .execute(&mut tx).await?;
+    tx = update_standings_of_site_games(tx, site_game_ids).await?;
+    commit_audited(tx, audit, num_rows).await
+}
+
+pub async fn get_game_list(
+    pool: &crate::DbViewerPool,
+    game_list: GameList,
+) -> Result<Vec<GameListEntry>> {
+    Ok(sqlx::query_as::<sqlx::Postgres, GameListEntryRecord>(&format!(
+        "select site_game_id, reason
+        from {}
+        join games on game_id = games.id
+        order by site_game_id",
+        game_list.table_name(),
+    )).fetch_all(&pool.0).await?.into_iter().map(|record| GameListEntry {
+        site_game_id: record.site_game_id,
+        reason: record.reason,
+    }).collect())
+}
+
+#[derive(sqlx::FromRow)]
+struct GameListEntryRecord {
+    site_game_id: i64,
+    reason: Option<String>,
+}
+
+pub(crate) async fn update_standings_of_site_games(
+    mut tx: Tx,
+    site_game_ids: &[i64],
+) -> Result<Tx> {
+    let competition_ids: Vec<i16> = sqlx::query!(
+        "select distinct competition_id
+        from games
+        join competition_seeds on seed_id = competition_seeds.id
+        where site_game_id = any($1)",
+        site_game_ids,
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| record.competition_id).collect();
+    update_competition_standings(tx, &competition_ids).await
 }
 
 pub async fn select_seed_id(
@@ -123,6 +358,8 @@ pub async fn insert_game(
           , turns
           , datetime_started
           , datetime_ended
+          , end_condition
+          , pre_strikeout_score
         ) VALUES (
             $1
           , $2
@@ -130,6 +367,8 @@ pub async fn insert_game(
           , $4
           , $5
           , $6
+          , $7
+          , $8
         ) returning id",
         game.game_id,
         seed_id,
@@ -137,46 +376,61 @@ pub async fn insert_game(
         game.turns,
         game.datetime_started,
         game.datetime_ended,
+        game.end_condition.map(|end_condition| end_condition.as_str()),
+        game.pre_strikeout_score,
     ).fetch_one(&mut tx).await?.id;
     Ok((tx, game_id))
 }
 
+// `character_ids` are in the same order as `player_ids`
 pub async fn insert_game_players(
     mut tx: Tx,
     game_id: i32,
-    player_ids: &Vec<i32>,
+    player_ids: &[i32],
+    character_ids: &[Option<i16>],
 ) -> Result<Tx> {
-    for player_id in player_ids {
+    for (player_id, character_id) in player_ids.iter().zip(character_ids) {
         sqlx::query!(
             "INSERT INTO game_players (
                 game_id
               , player_id
+              , character_id
             ) VALUES (
                 $1
               , $2
+              , $3
             )",
             game_id,
             *player_id,
+            *character_id,
         ).execute(&mut tx).await?;
     }
     Ok(tx)
 }
 
+// Returns the IDs of the competitions whose seeds received games, and the games that were
+// blacklisted for breaking their competition's ruleset
 pub async fn add_competition_games(
     mut tx: Tx,
     competition_games: &CompetitionGames,
-) -> Result<Tx> {
+) -> Result<(Tx, Vec<i16>, Vec<NoncompliantGame>)> {
+    let mut seed_ids = Vec::new();
+    let mut noncompliant_games = Vec::new();
     for seed_games in &competition_games.seeds_games {
         // This pattern is not the most ergonomic; revisit it if this RFC lands:
         // https://github.com/rust-lang/rfcs/pull/2909
         let tx_and_seed_id = select_seed_id(
             tx,
             &seed_games.base_seed_name,
-            competition_games.variant_id,
+            // validated to have one or the other
+            seed_games.variant_id.or(competition_games.variant_id).unwrap(),
             competition_games.num_players,
         ).await?;
         tx = tx_and_seed_id.0;
         let seed_id = tx_and_seed_id.1;
+        seed_ids.push(seed_id);
+        let (next_tx, seed_ruleset) = get_seed_ruleset(tx, seed_id).await?;
+        tx = next_tx;
 
         for game in &seed_games.games {
             let tx_and_player_ids = upsert_players(tx, &game.players).await?;
@@ -184,18 +438,43 @@ pub async fn add_competition_games(
             let player_ids = tx_and_player_ids.1;
             let tx_and_game_id = insert_game(
                 tx,
-                &game,
+                game,
                 seed_id,
             ).await?;
             tx = tx_and_game_id.0;
             let game_id = tx_and_game_id.1;
 
+            let (next_tx, character_ids) = get_character_ids(tx, &game.character_names()).await?;
             tx = insert_game_players(
-                tx,
+                next_tx,
                 game_id,
-                &player_ids
+                &player_ids,
+                &character_ids,
             ).await?;
+            if let Some(export) = &game.export {
+                tx = store_game_export(tx, game.game_id, export).await?;
+            }
+            if let Some(violations) = check_game(&seed_ruleset, game) {
+                if !violations.is_empty() {
+                    let noncompliant_game = NoncompliantGame {
+                        site_game_id: game.game_id,
+                        violations,
+                    };
+                    let (next_tx, whitelisted) =
+                        blacklist_noncompliant_game(tx, game_id, &noncompliant_game).await?;
+                    tx = next_tx;
+                    if !whitelisted {
+                        noncompliant_games.push(noncompliant_game);
+                    }
+                }
+            }
         }
     }
-    Ok(tx)
+    let competition_ids = sqlx::query!(
+        "select distinct competition_id
+        from competition_seeds
+        where id = any($1)",
+        &seed_ids,
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| record.competition_id).collect();
+    Ok((tx, competition_ids, noncompliant_games))
 }
@@ -0,0 +1,191 @@
+// Full game exports, in hanab.live's export format, stored gzipped so that replays can be
+// analysed later without going back to the site
+use std::{collections::HashMap, io::{Read, Write}};
+use anyhow::Result;
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde::{Serialize, Deserialize};
+use sqlx::PgConnection;
+use crate::model::Tx;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GameExport {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    // in seat order
+    pub players: Vec<String>,
+    pub deck: Vec<CardIdentity>,
+    pub actions: Vec<GameAction>,
+    #[serde(default)]
+    pub options: GameOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    // in seat order, for games played with detrimental characters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub characters: Option<Vec<CharacterAssignment>>,
+    // notes and whatever else the site adds, kept as is
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CardIdentity {
+    pub suit_index: i16,
+    pub rank: i16,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CharacterAssignment {
+    pub name: String,
+    // e.g. the suit or rank the character is about, for characters that have one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<i32>,
+}
+
+// `action_type` is 0 for a play, 1 for a discard, 2 for a color clue, 3 for a rank clue and
+// 4 for the end of the game. `target` is a card's order in the deck for plays and discards,
+// and a player's seat for clues.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct GameAction {
+    #[serde(rename = "type")]
+    pub action_type: i16,
+    pub target: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GameOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_players: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starting_player: Option<i16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_base: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_per_turn: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub speedrun: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub card_cycle: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deck_plays: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub empty_clues: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_extra_card: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub one_less_card: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub all_or_nothing: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detrimental_characters: Option<bool>,
+    #[serde(flatten)]
+    pub other: HashMap<String, serde_json::Value>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GameExportError {
+    #[error("No export of game {0} was stored")]
+    NotFound(i64),
+    #[error("The export of game {0} is of game {1}")]
+    MismatchedId(i64, i64),
+    #[error("The export of game {0} has players {1:?}, but the game has {2:?}")]
+    MismatchedPlayers(i64, Vec<String>, Vec<String>),
+}
+
+impl GameExport {
+    // Checks the export against what was reported for the game
+    pub fn validate(&self, site_game_id: i64, players: &[String]) -> Result<(), GameExportError> {
+        if let Some(id) = self.id {
+            if id != site_game_id {
+                return Err(GameExportError::MismatchedId(site_game_id, id));
+            }
+        }
+        let mut export_players = self.players.clone();
+        let mut game_players = players.to_vec();
+        export_players.sort_unstable();
+        game_players.sort_unstable();
+        if export_players != game_players {
+            return Err(GameExportError::MismatchedPlayers(
+                site_game_id,
+                self.players.clone(),
+                players.to_vec(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn compress(export: &GameExport) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&serde_json::to_vec(export)?)?;
+    Ok(encoder.finish()?)
+}
+
+fn decompress(compressed_export: &[u8]) -> Result<String> {
+    let mut export_json = String::new();
+    GzDecoder::new(compressed_export).read_to_string(&mut export_json)?;
+    Ok(export_json)
+}
+
+// Replaces any export already stored for the game
+pub async fn store_game_export(
+    mut tx: Tx,
+    site_game_id: i64,
+    export: &GameExport,
+) -> Result<Tx> {
+    sqlx::query!(
+        "insert into game_exports (
+            site_game_id
+          , export
+        ) values (
+            $1
+          , $2
+        )
+        on conflict (site_game_id) do update set
+            export = excluded.export
+          , stored_at = now()",
+        site_game_id,
+        compress(export)?,
+    ).execute(&mut tx).await?;
+    Ok(tx)
+}
+
+// The export's JSON, as stored
+pub async fn get_game_export_json(
+    pool: &crate::DbViewerPool,
+    site_game_id: i64,
+) -> Result<String> {
+    match sqlx::query!(
+        "select export
+        from game_exports
+        where site_game_id = $1",
+        site_game_id,
+    ).fetch_optional(&pool.0).await? {
+        Some(record) => decompress(&record.export),
+        None => Err(GameExportError::NotFound(site_game_id).into()),
+    }
+}
+
+// Games without a stored export are left out
+pub async fn get_game_exports(
+    conn: &mut PgConnection,
+    site_game_ids: &[i64],
+) -> Result<HashMap<i64, GameExport>> {
+    let mut exports = HashMap::new();
+    for record in sqlx::query!(
+        "select site_game_id, export
+        from game_exports
+        where site_game_id = any($1)",
+        site_game_ids,
+    ).fetch_all(conn).await? {
+        exports.insert(record.site_game_id, serde_json::from_str(&decompress(&record.export)?)?);
+    }
+    Ok(exports)
+}
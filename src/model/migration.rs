@@ -0,0 +1,230 @@
+use sqlx::{Executor, Row, postgres::PgRow};
+use crate::DbAdminPool;
+use anyhow::Result;
+
+pub struct Migration {
+    pub version: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+// Must be kept in the order in which they're to be applied
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: "0.1.0",
+        up: include_str!("../../db/0.1.0_up.sql"),
+        down: include_str!("../../db/0.1.0_down.sql"),
+    },
+    Migration {
+        version: "0.2.0",
+        up: include_str!("../../db/0.2.0_up.sql"),
+        down: include_str!("../../db/0.2.0_down.sql"),
+    },
+    Migration {
+        version: "0.3.0",
+        up: include_str!("../../db/0.3.0_up.sql"),
+        down: include_str!("../../db/0.3.0_down.sql"),
+    },
+    Migration {
+        version: "0.4.0",
+        up: include_str!("../../db/0.4.0_up.sql"),
+        down: include_str!("../../db/0.4.0_down.sql"),
+    },
+    Migration {
+        version: "0.5.0",
+        up: include_str!("../../db/0.5.0_up.sql"),
+        down: include_str!("../../db/0.5.0_down.sql"),
+    },
+    Migration {
+        version: "0.6.0",
+        up: include_str!("../../db/0.6.0_up.sql"),
+        down: include_str!("../../db/0.6.0_down.sql"),
+    },
+    Migration {
+        version: "0.7.0",
+        up: include_str!("../../db/0.7.0_up.sql"),
+        down: include_str!("../../db/0.7.0_down.sql"),
+    },
+    Migration {
+        version: "0.8.0",
+        up: include_str!("../../db/0.8.0_up.sql"),
+        down: include_str!("../../db/0.8.0_down.sql"),
+    },
+    Migration {
+        version: "0.9.0",
+        up: include_str!("../../db/0.9.0_up.sql"),
+        down: include_str!("../../db/0.9.0_down.sql"),
+    },
+    Migration {
+        version: "0.10.0",
+        up: include_str!("../../db/0.10.0_up.sql"),
+        down: include_str!("../../db/0.10.0_down.sql"),
+    },
+    Migration {
+        version: "0.11.0",
+        up: include_str!("../../db/0.11.0_up.sql"),
+        down: include_str!("../../db/0.11.0_down.sql"),
+    },
+    Migration {
+        version: "0.12.0",
+        up: include_str!("../../db/0.12.0_up.sql"),
+        down: include_str!("../../db/0.12.0_down.sql"),
+    },
+    Migration {
+        version: "0.13.0",
+        up: include_str!("../../db/0.13.0_up.sql"),
+        down: include_str!("../../db/0.13.0_down.sql"),
+    },
+    Migration {
+        version: "0.14.0",
+        up: include_str!("../../db/0.14.0_up.sql"),
+        down: include_str!("../../db/0.14.0_down.sql"),
+    },
+    Migration {
+        version: "0.15.0",
+        up: include_str!("../../db/0.15.0_up.sql"),
+        down: include_str!("../../db/0.15.0_down.sql"),
+    },
+    Migration {
+        version: "0.16.0",
+        up: include_str!("../../db/0.16.0_up.sql"),
+        down: include_str!("../../db/0.16.0_down.sql"),
+    },
+    Migration {
+        version: "0.17.0",
+        up: include_str!("../../db/0.17.0_up.sql"),
+        down: include_str!("../../db/0.17.0_down.sql"),
+    },
+    Migration {
+        version: "0.18.0",
+        up: include_str!("../../db/0.18.0_up.sql"),
+        down: include_str!("../../db/0.18.0_down.sql"),
+    },
+    Migration {
+        version: "0.19.0",
+        up: include_str!("../../db/0.19.0_up.sql"),
+        down: include_str!("../../db/0.19.0_down.sql"),
+    },
+];
+
+#[derive(thiserror::Error, Debug)]
+pub enum MigrationError {
+    #[error("Schema version {0} is newer than this build understands; upgrade the server")]
+    UnknownVersion(String),
+    #[error("Unknown schema version: {0}")]
+    UnknownTargetVersion(String),
+    #[error("Applied schema versions {0:?} skip over an older version")]
+    InconsistentHistory(Vec<String>),
+    #[error(
+        "The db has a schema, but no record of its version; \
+        run the `baseline <version>` subcommand to record it"
+    )]
+    Unversioned,
+    #[error("Schema version {0} is not older than the current version")]
+    NotARollback(String),
+}
+
+// Returns the versions which were applied, in order
+pub async fn migrate_to_latest(pool: &DbAdminPool) -> Result<Vec<&'static str>> {
+    let num_applied = get_num_applied_migrations(pool).await?;
+    let mut newly_applied_versions = Vec::new();
+    for migration in &MIGRATIONS[num_applied..] {
+        let mut tx = pool.0.begin().await?;
+        tx.execute(migration.up).await?;
+        sqlx::query("insert into schema_versions (version) values ($1)")
+            .bind(migration.version)
+            .execute(&mut tx).await?;
+        tx.commit().await?;
+        newly_applied_versions.push(migration.version);
+    }
+    Ok(newly_applied_versions)
+}
+
+// Runs the down migrations of every version newer than `target_version`, newest first,
+// and returns the versions which were rolled back
+pub async fn rollback_to(
+    pool: &DbAdminPool,
+    target_version: &str,
+) -> Result<Vec<&'static str>> {
+    let target_idx = find_migration_idx(target_version)?;
+    let num_applied = get_num_applied_migrations(pool).await?;
+    if target_idx + 1 >= num_applied {
+        return Err(MigrationError::NotARollback(target_version.to_owned()).into());
+    }
+    let mut rolled_back_versions = Vec::new();
+    for migration in MIGRATIONS[target_idx + 1..num_applied].iter().rev() {
+        let mut tx = pool.0.begin().await?;
+        tx.execute(migration.down).await?;
+        sqlx::query("delete from schema_versions where version = $1")
+            .bind(migration.version)
+            .execute(&mut tx).await?;
+        tx.commit().await?;
+        rolled_back_versions.push(migration.version);
+    }
+    Ok(rolled_back_versions)
+}
+
+// For dbs whose schema was applied by hand, before versions were tracked: records every
+// version up to and including `version` as applied, without running anything
+pub async fn baseline(pool: &DbAdminPool, version: &str) -> Result<()> {
+    let idx = find_migration_idx(version)?;
+    let mut tx = pool.0.begin().await?;
+    tx.execute(CREATE_SCHEMA_VERSIONS_TABLE).await?;
+    for migration in &MIGRATIONS[..idx + 1] {
+        sqlx::query(
+            "insert into schema_versions (version) values ($1)
+            on conflict (version) do nothing")
+            .bind(migration.version)
+            .execute(&mut tx).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_schema_version(pool: &DbAdminPool) -> Result<Option<&'static str>> {
+    let num_applied = get_num_applied_migrations(pool).await?;
+    Ok(MIGRATIONS[..num_applied].last().map(|migration| migration.version))
+}
+
+const CREATE_SCHEMA_VERSIONS_TABLE: &str = "
+    create table if not exists schema_versions (
+        version text primary key
+      , applied_at timestamptz not null default now()
+    );";
+
+fn find_migration_idx(version: &str) -> Result<usize, MigrationError> {
+    MIGRATIONS.iter().position(|migration| migration.version == version)
+        .ok_or_else(|| MigrationError::UnknownTargetVersion(version.to_owned()))
+}
+
+// Also checks that the applied versions are ones this build knows about, with none skipped
+async fn get_num_applied_migrations(pool: &DbAdminPool) -> Result<usize> {
+    let has_versions_table: bool = sqlx::query("select to_regclass('schema_versions') is not null")
+        .map(|row: PgRow| row.get(0))
+        .fetch_one(&pool.0).await?;
+    if !has_versions_table {
+        let has_schema: bool = sqlx::query("select to_regclass('competitions') is not null")
+            .map(|row: PgRow| row.get(0))
+            .fetch_one(&pool.0).await?;
+        if has_schema {
+            return Err(MigrationError::Unversioned.into());
+        }
+        (&pool.0).execute(CREATE_SCHEMA_VERSIONS_TABLE).await?;
+    }
+    let applied_versions: Vec<String> = sqlx::query("select version from schema_versions")
+        .map(|row: PgRow| row.get(0))
+        .fetch_all(&pool.0).await?;
+    if let Some(unknown_version) = applied_versions.iter()
+        .find(|&version| find_migration_idx(version).is_err())
+    {
+        return Err(MigrationError::UnknownVersion(unknown_version.clone()).into());
+    }
+    // the applied versions must be exactly the oldest ones
+    let num_applied = applied_versions.len();
+    if !MIGRATIONS[..num_applied].iter()
+        .all(|migration| applied_versions.iter().any(|version| version == migration.version))
+    {
+        return Err(MigrationError::InconsistentHistory(applied_versions).into());
+    }
+    Ok(num_applied)
+}
@@ -0,0 +1,379 @@
+// Proposes the variants and player counts of upcoming competitions, so that admins don't have
+// to remember which ones were played recently
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use crate::{
+    DbViewerPool,
+    DbAdminPool,
+    model::{
+        UtcDateTime,
+        audit::{commit_audited, AuditContext},
+        competition::{add_competitions_in_tx, PartiallySpecifiedCompetition},
+        schedule::{get_schedule, mark_rounds_created, RotationEntry, Schedule},
+    },
+};
+use anyhow::Result;
+
+const DEFAULT_NUM_RECENT_TO_AVOID: u8 = 8;
+
+type VariantAndPlayerCount = (i16, String);
+
+#[derive(Serialize, Deserialize)]
+pub struct PlanRequest {
+    // Exactly one of these
+    pub series_name: Option<String>,
+    pub schedule_name: Option<String>,
+    pub num_competitions: u8,
+    // Defaults to the schedule's rotation, or else to whatever the series' past competitions
+    // used, or else to whatever any past competition used
+    pub candidates: Option<Vec<RotationEntry>>,
+    // How many of the latest competitions not to repeat
+    pub num_recent_to_avoid: Option<u8>,
+    #[serde(default)]
+    pub pinned: Vec<PinnedChoice>,
+    // Only for a series, since a schedule's competitions end with their rounds
+    pub end_datetime: Option<UtcDateTime>,
+}
+
+// Fixes the choice of one of the planned competitions, counting from 0
+#[derive(Serialize, Deserialize)]
+pub struct PinnedChoice {
+    pub index: u8,
+    pub num_players: i16,
+    pub variant_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PlannedCompetition {
+    pub num_players: i16,
+    pub variant_name: String,
+    // mean score of its past games, if it's been played before
+    pub mean_score: Option<f64>,
+    pub last_end_datetime: Option<UtcDateTime>,
+    pub pinned: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Plan {
+    pub series_name: Option<String>,
+    pub schedule_name: Option<String>,
+    pub planned_competitions: Vec<PlannedCompetition>,
+    // What would be committed; the same as what POST /competitions accepts
+    pub competitions: Vec<PartiallySpecifiedCompetition>,
+    // For a schedule, the last of its rounds that the plan covers
+    pub last_round: Option<i32>,
+    // Set when it's committed with ?dry_run=true, so nothing was created
+    pub dry_run: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlanError {
+    #[error("Exactly one of a series or a schedule must be given")]
+    SeriesOrSchedule,
+    #[error("No series named {0} was found")]
+    SeriesNotFound(String),
+    #[error("There are no variants and player counts to choose from")]
+    NoCandidates,
+    #[error("Pinned choice {0} is past the end of the plan")]
+    PinnedOutOfRange(u8),
+    #[error("Choice {0} is pinned more than once")]
+    DuplicatePin(u8),
+    #[error("The number of competitions must be a multiple of the schedule's {0} per round")]
+    PartialRound(i16),
+}
+
+struct CompetitionHistoryRecord {
+    end_datetime: UtcDateTime,
+    num_players: i16,
+    variant_name: String,
+}
+
+pub async fn plan_competitions(
+    pool: &DbViewerPool,
+    request: &PlanRequest,
+    now: UtcDateTime,
+) -> Result<Plan> {
+    let schedule = match (&request.series_name, &request.schedule_name) {
+        (Some(series_name), None) => {
+            if sqlx::query!(
+                "select id
+                from series
+                where name = $1",
+                series_name,
+            ).fetch_optional(&pool.0).await?.is_none() {
+                return Err(PlanError::SeriesNotFound(series_name.clone()).into());
+            }
+            None
+        },
+        (None, Some(schedule_name)) => {
+            let schedule = get_schedule(pool, schedule_name).await?;
+            if request.num_competitions as i16 % schedule.competitions_per_round != 0 {
+                return Err(PlanError::PartialRound(schedule.competitions_per_round).into());
+            }
+            Some(schedule)
+        },
+        _ => return Err(PlanError::SeriesOrSchedule.into()),
+    };
+    if let Some(pinned_choice) = request.pinned.iter()
+        .find(|pinned_choice| pinned_choice.index >= request.num_competitions) {
+        return Err(PlanError::PinnedOutOfRange(pinned_choice.index).into());
+    }
+    // the pins are keyed by index when choosing, so a second pin for one would leave a choice
+    // without candidates
+    for (i, pinned_choice) in request.pinned.iter().enumerate() {
+        if request.pinned[..i].iter().any(|other| other.index == pinned_choice.index) {
+            return Err(PlanError::DuplicatePin(pinned_choice.index).into());
+        }
+    }
+    let history_series_names = match &schedule {
+        Some(schedule) => schedule.series_names.clone(),
+        None => request.series_name.iter().cloned().collect(),
+    };
+    let history = get_competition_history(pool, &history_series_names).await?;
+    let mean_scores = get_mean_scores(pool).await?;
+    let candidates: Vec<VariantAndPlayerCount> = match (&request.candidates, &schedule) {
+        (Some(candidates), _) => to_keys(candidates),
+        (None, Some(schedule)) => to_keys(&schedule.rotation),
+        (None, None) => {
+            let mut candidates: Vec<VariantAndPlayerCount> = history.iter()
+                .map(|record| (record.num_players, record.variant_name.clone()))
+                .collect();
+            if candidates.is_empty() {
+                candidates = get_competition_history(pool, &[]).await?.into_iter()
+                    .map(|record| (record.num_players, record.variant_name))
+                    .collect();
+            }
+            candidates.sort();
+            candidates.dedup();
+            candidates
+        },
+    };
+    if candidates.is_empty() && request.pinned.len() < request.num_competitions as usize {
+        return Err(PlanError::NoCandidates.into());
+    }
+    let mut last_end_datetimes: HashMap<VariantAndPlayerCount, UtcDateTime> = HashMap::new();
+    for record in &history {
+        last_end_datetimes.insert((record.num_players, record.variant_name.clone()), record.end_datetime);
+    }
+    let choices = choose_competitions(
+        &candidates,
+        &history,
+        &mean_scores,
+        request,
+    );
+
+    let planned_competitions: Vec<PlannedCompetition> = choices.iter()
+        .map(|(key, pinned)| PlannedCompetition {
+            num_players: key.0,
+            variant_name: key.1.clone(),
+            mean_score: mean_scores.get(key).copied(),
+            last_end_datetime: last_end_datetimes.get(key).copied(),
+            pinned: *pinned,
+        })
+        .collect();
+    let (competitions, last_round) = match &schedule {
+        Some(schedule) => plan_schedule_competitions(schedule, &choices, now),
+        None => (choices.iter().map(|((num_players, variant_name), _)| PartiallySpecifiedCompetition {
+            num_players: *num_players,
+            variant_name: Some(variant_name.clone()),
+            title: None,
+            start_datetime: None,
+            end_datetime: request.end_datetime,
+            deckplay_enabled: None,
+            empty_clues_enabled: None,
+            characters_enabled: None,
+            scoring_type: None,
+            time_control: None,
+            additional_rules: None,
+            base_seed_names: None,
+            seeds: None,
+            series_names: Some(request.series_name.iter().cloned().collect()),
+            registration_required: None,
+            tie_breaks: None,
+        }).collect(), None),
+    };
+    Ok(Plan {
+        series_name: request.series_name.clone(),
+        schedule_name: request.schedule_name.clone(),
+        planned_competitions,
+        competitions,
+        last_round,
+        dry_run: false,
+    })
+}
+
+// A schedule's planned rounds are marked as created, so that the scheduler leaves them be
+pub async fn commit_plan(
+    pool: &DbAdminPool,
+    plan: Plan,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_competitions = plan.competitions.len();
+    tx = add_competitions_in_tx(tx, plan.competitions).await?;
+    if let (Some(schedule_name), Some(last_round)) = (&plan.schedule_name, plan.last_round) {
+        tx = mark_rounds_created(tx, schedule_name, last_round).await?;
+    }
+    commit_audited(tx, audit, num_competitions as u64).await
+}
+
+fn to_keys(rotation: &[RotationEntry]) -> Vec<VariantAndPlayerCount> {
+    let mut keys: Vec<VariantAndPlayerCount> = rotation.iter()
+        .map(|entry| (entry.num_players, entry.variant_name.clone()))
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+// Fills the unpinned slots one at a time. Each takes the candidate that keeps the plan's mean
+// score closest to the candidates' overall mean, among those that weren't used recently,
+// preferring the one that was used longest ago.
+fn choose_competitions(
+    candidates: &[VariantAndPlayerCount],
+    history: &[CompetitionHistoryRecord],
+    mean_scores: &HashMap<VariantAndPlayerCount, f64>,
+    request: &PlanRequest,
+) -> Vec<(VariantAndPlayerCount, bool)> {
+    let num_recent_to_avoid = request.num_recent_to_avoid.unwrap_or(DEFAULT_NUM_RECENT_TO_AVOID) as usize;
+    let known_mean_scores: Vec<f64> = candidates.iter()
+        .filter_map(|candidate| mean_scores.get(candidate).copied())
+        .collect();
+    // candidates that have never been played count as average
+    let target_mean_score = if known_mean_scores.is_empty() {
+        0.0
+    } else {
+        known_mean_scores.iter().sum::<f64>() / known_mean_scores.len() as f64
+    };
+    let pinned_choices: HashMap<u8, VariantAndPlayerCount> = request.pinned.iter()
+        .map(|pinned_choice| (
+            pinned_choice.index,
+            (pinned_choice.num_players, pinned_choice.variant_name.clone()),
+        ))
+        .collect();
+    // oldest first
+    let mut recent: Vec<VariantAndPlayerCount> = history.iter()
+        .map(|record| (record.num_players, record.variant_name.clone()))
+        .collect();
+    let mut choices = Vec::new();
+    let mut planned_mean_score_sum = 0.0;
+    for index in 0..request.num_competitions {
+        let choice = match pinned_choices.get(&index) {
+            Some(pinned_choice) => (pinned_choice.clone(), true),
+            None => {
+                let recent_window = &recent[recent.len().saturating_sub(num_recent_to_avoid)..];
+                let is_fresh = |candidate: &&VariantAndPlayerCount| {
+                    !recent_window.contains(candidate)
+                        && !pinned_choices.values().any(|pinned_choice| pinned_choice == *candidate)
+                };
+                let mut eligible: Vec<&VariantAndPlayerCount> = candidates.iter().filter(is_fresh).collect();
+                // when everything was used recently, repeat the one used longest ago
+                if eligible.is_empty() {
+                    eligible = candidates.iter().collect();
+                }
+                let mean_score_gap = |candidate: &VariantAndPlayerCount| {
+                    let mean_score = mean_scores.get(candidate).copied().unwrap_or(target_mean_score);
+                    ((planned_mean_score_sum + mean_score) / (choices.len() + 1) as f64
+                        - target_mean_score).abs()
+                };
+                let last_used = |candidate: &VariantAndPlayerCount| {
+                    recent.iter().rposition(|recent_choice| recent_choice == candidate)
+                };
+                let chosen = eligible.into_iter().min_by(|a, b| {
+                    mean_score_gap(a).partial_cmp(&mean_score_gap(b)).unwrap()
+                        .then_with(|| last_used(a).cmp(&last_used(b)))
+                        .then_with(|| a.cmp(b))
+                }).unwrap();
+                (chosen.clone(), false)
+            },
+        };
+        planned_mean_score_sum += mean_scores.get(&choice.0).copied().unwrap_or(target_mean_score);
+        recent.push(choice.0.clone());
+        choices.push(choice);
+    }
+    choices
+}
+
+// Returns the competitions, and the last round they belong to
+fn plan_schedule_competitions(
+    schedule: &Schedule,
+    choices: &[(VariantAndPlayerCount, bool)],
+    now: UtcDateTime,
+) -> (Vec<PartiallySpecifiedCompetition>, Option<i32>) {
+    let first_round = schedule.next_uncreated_round(now);
+    let mut last_round = None;
+    let competitions = choices.chunks(schedule.competitions_per_round as usize).enumerate()
+        .flat_map(|(round_idx, round_choices)| {
+            let round = first_round + round_idx as i32;
+            last_round = Some(round);
+            schedule.round_competitions(round).into_iter().zip(round_choices)
+                .map(|(mut competition, ((num_players, variant_name), _))| {
+                    competition.num_players = *num_players;
+                    competition.variant_name = Some(variant_name.clone());
+                    competition
+                })
+                .collect::<Vec<PartiallySpecifiedCompetition>>()
+        })
+        .collect();
+    (competitions, last_round)
+}
+
+// Oldest first
+async fn get_competition_history(
+    pool: &DbViewerPool,
+    series_names: &[String],
+) -> Result<Vec<CompetitionHistoryRecord>> {
+    Ok(sqlx::query_as!(
+        CompetitionHistoryRecord,
+        "select
+            competitions.end_datetime
+          , competitions.num_players
+          , variants.name variant_name
+        from competitions
+        join variants
+            on variants.id = competitions.variant_id
+        where cardinality($1::text[]) = 0
+            or exists (
+                select 1
+                from series_competitions
+                join series
+                    on series.id = series_competitions.series_id
+                where series_competitions.competition_id = competitions.id
+                    and series.name = any($1)
+            )
+        order by
+            competitions.end_datetime
+          , competitions.id",
+        series_names,
+    ).fetch_all(&pool.0).await?)
+}
+
+// Blacklisted games don't count
+async fn get_mean_scores(
+    pool: &DbViewerPool,
+) -> Result<HashMap<VariantAndPlayerCount, f64>> {
+    Ok(sqlx::query!(
+        "select
+            competitions.num_players
+          , variants.name variant_name
+          , avg(games.score)::float8 mean_score
+        from games
+        join competition_seeds
+            on competition_seeds.id = games.seed_id
+        join competitions
+            on competitions.id = competition_seeds.competition_id
+        join variants
+            on variants.id = competitions.variant_id
+        where not exists (
+            select 1
+            from blacklisted_games
+            where blacklisted_games.game_id = games.id
+        )
+        group by
+            competitions.num_players
+          , variants.name",
+    ).fetch_all(&pool.0).await?.into_iter()
+        .filter_map(|record| record.mean_score
+            .map(|mean_score| ((record.num_players, record.variant_name), mean_score)))
+        .collect())
+}
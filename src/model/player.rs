@@ -0,0 +1,105 @@
+use serde::{Serialize, Deserialize};
+use crate::{
+    DbAdminPool,
+    DbViewerPool,
+    model::{
+        Tx,
+        audit::{commit_audited, AuditContext},
+        game::upsert_players,
+        competition::update_competition_standings,
+        team::attribute_games_to_teams,
+    },
+};
+use anyhow::Result;
+
+// Games played on an alias account are credited to its primary account
+#[derive(Serialize, Deserialize)]
+pub struct Alias {
+    pub alias: String,
+    pub primary: String,
+}
+
+pub async fn add_aliases(
+    pool: &DbAdminPool,
+    aliases: &[Alias],
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let mut alias_ids = Vec::new();
+    for alias in aliases {
+        let tx_and_player_ids = upsert_players(
+            tx,
+            &vec![alias.alias.clone(), alias.primary.clone()],
+        ).await?;
+        tx = tx_and_player_ids.0;
+        let player_ids = tx_and_player_ids.1;
+        sqlx::query!(
+            "insert into aliases (
+                alias_id
+              , primary_id
+            ) values (
+                $1
+              , $2
+            ) on conflict (alias_id) do update set primary_id = excluded.primary_id",
+            player_ids[0],
+            player_ids[1],
+        ).execute(&mut tx).await?;
+        alias_ids.push(player_ids[0]);
+    }
+    tx = update_standings_of_players(tx, &alias_ids).await?;
+    commit_audited(tx, audit, aliases.len() as u64).await
+}
+
+pub async fn remove_aliases(
+    pool: &DbAdminPool,
+    alias_names: &[String],
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let alias_ids: Vec<i32> = sqlx::query!(
+        "delete from aliases
+        where alias_id in (select id from players where name = any($1))
+        returning alias_id",
+        alias_names,
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| record.alias_id).collect();
+    tx = update_standings_of_players(tx, &alias_ids).await?;
+    commit_audited(tx, audit, alias_ids.len() as u64).await
+}
+
+pub async fn get_aliases(
+    pool: &DbViewerPool,
+) -> Result<Vec<Alias>> {
+    Ok(sqlx::query_as!(
+        Alias,
+        "select
+            alias_accounts.name alias
+          , primary_accounts.name primary
+        from aliases
+        join players alias_accounts on alias_id = alias_accounts.id
+        join players primary_accounts on primary_id = primary_accounts.id
+        order by primary_accounts.name, alias_accounts.name",
+    ).fetch_all(&pool.0).await?)
+}
+
+// Aliasing changes who played a game, so it affects every competition the alias played in, and
+// who's on a team, so also every competition where the alias is on a team's roster
+async fn update_standings_of_players(
+    mut tx: Tx,
+    player_ids: &[i32],
+) -> Result<Tx> {
+    let competition_ids: Vec<i16> = sqlx::query!(
+        "select competition_id
+        from game_players
+        join games on game_id = games.id
+        join competition_seeds on seed_id = competition_seeds.id
+        where player_id = any($1)
+        union
+        select competition_id
+        from team_members
+        where player_id = any($1)",
+        player_ids,
+    ).fetch_all(&mut tx).await?.into_iter().filter_map(|record| record.competition_id).collect();
+    // and so which team its games belong to
+    tx = attribute_games_to_teams(tx, &competition_ids).await?;
+    update_competition_standings(tx, &competition_ids).await
+}
@@ -0,0 +1,224 @@
+// Replays ingested games from their exports, and flags those whose reported score or turns
+// disagree with the replay
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use crate::{
+    DbAdminPool,
+    DbViewerPool,
+    hanabi::{replay, EndCondition, ReplayError, ReplayOutcome, Ruleset, VariantRules},
+    model::{
+        Tx,
+        audit::{commit_audited, AuditContext},
+        game::update_standings_of_site_games,
+        game_export::get_game_exports,
+        variant::VariantMetadata,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GameReplay {
+    pub site_game_id: i64,
+    pub reported_score: i16,
+    pub reported_turns: i16,
+    // None when the game couldn't be replayed
+    pub outcome: Option<ReplayOutcome>,
+    pub error: Option<String>,
+    pub mismatched: bool,
+}
+
+// Games without a stored export are skipped. Returns the replays that were flagged.
+pub(crate) async fn replay_games(
+    mut tx: Tx,
+    site_game_ids: &[i64],
+) -> Result<(Tx, Vec<GameReplay>)> {
+    let records = sqlx::query!(
+        "select
+            games.id game_id
+          , games.site_game_id
+          , games.score
+          , games.turns
+          , coalesce(competition_seeds.deckplay_enabled, competitions.deckplay_enabled)
+                deckplay_enabled
+          , coalesce(competition_seeds.empty_clues_enabled, competitions.empty_clues_enabled)
+                empty_clues_enabled
+          , variants.site_variant_id
+          , variants.suits
+          , variants.clue_colors
+          , variants.clue_ranks
+          , variants.special_rank
+          , variants.max_score
+          , variants.flags
+          , variants.removed_at is not null as removed
+        from games
+        join game_exports using(site_game_id)
+        join competition_seeds on games.seed_id = competition_seeds.id
+        join competitions on competition_seeds.competition_id = competitions.id
+        join variants on competition_seeds.variant_id = variants.id
+        where games.site_game_id = any($1)",
+        site_game_ids,
+    ).fetch_all(&mut tx).await?;
+    let exports = get_game_exports(&mut tx, site_game_ids).await?;
+    let mut flagged_replays = Vec::new();
+    for record in records {
+        // joined on the export above
+        let export = &exports[&record.site_game_id.unwrap()];
+        let variant = VariantMetadata {
+            site_variant_id: record.site_variant_id,
+            suits: record.suits,
+            clue_colors: record.clue_colors,
+            clue_ranks: record.clue_ranks,
+            special_rank: record.special_rank,
+            max_score: record.max_score,
+            flags: record.flags,
+            removed: record.removed.unwrap_or(false),
+        };
+        let ruleset = Ruleset {
+            deck_plays: record.deckplay_enabled.unwrap(),
+            empty_clues: record.empty_clues_enabled.unwrap(),
+        };
+        let result = VariantRules::new(&variant)
+            .and_then(|variant_rules| replay(export, &variant_rules, &ruleset));
+        let game_replay = GameReplay {
+            site_game_id: record.site_game_id.unwrap(),
+            reported_score: record.score,
+            reported_turns: record.turns,
+            mismatched: match &result {
+                Ok(outcome) => outcome.score != record.score || outcome.turns != record.turns,
+                Err(ReplayError::IllegalAction { .. }) => true,
+                Err(_) => false,
+            },
+            error: result.as_ref().err().map(|err| err.to_string()),
+            outcome: result.ok(),
+        };
+        sqlx::query!(
+            "insert into game_replays (
+                game_id
+              , score
+              , turns
+              , num_strikes
+              , end_condition
+              , error
+              , mismatched
+              , pre_strikeout_score
+            ) values (
+                $1
+              , $2
+              , $3
+              , $4
+              , $5
+              , $6
+              , $7
+              , $8
+            )
+            on conflict (game_id) do update set
+                score = excluded.score
+              , pre_strikeout_score = excluded.pre_strikeout_score
+              , turns = excluded.turns
+              , num_strikes = excluded.num_strikes
+              , end_condition = excluded.end_condition
+              , error = excluded.error
+              , mismatched = excluded.mismatched
+              , replayed_at = now()",
+            record.game_id,
+            game_replay.outcome.as_ref().map(|outcome| outcome.score),
+            game_replay.outcome.as_ref().map(|outcome| outcome.turns),
+            game_replay.outcome.as_ref().map(|outcome| outcome.num_strikes),
+            game_replay.outcome.as_ref().map(|outcome| outcome.end_condition.as_str()),
+            game_replay.error,
+            game_replay.mismatched,
+            game_replay.outcome.as_ref().map(|outcome| outcome.pre_strikeout_score),
+        ).execute(&mut tx).await?;
+        // the export is the better record of how the game ended, so it overrides what was
+        // reported
+        if let Some(outcome) = &game_replay.outcome {
+            sqlx::query!(
+                "update games
+                set end_condition = $2
+                  , pre_strikeout_score = $3
+                where id = $1",
+                record.game_id,
+                outcome.end_condition.as_str(),
+                outcome.pre_strikeout_score,
+            ).execute(&mut tx).await?;
+        }
+        if game_replay.mismatched {
+            flagged_replays.push(game_replay);
+        }
+    }
+    Ok((tx, flagged_replays))
+}
+
+// Appended to the responses of the endpoints that ingest games
+pub fn summarize_flagged_replays(flagged_replays: &[GameReplay]) -> String {
+    if flagged_replays.is_empty() {
+        return "".to_owned();
+    }
+    // a site game can be in the db more than once
+    let site_game_ids: std::collections::BTreeSet<i64> = flagged_replays.iter()
+        .map(|game_replay| game_replay.site_game_id).collect();
+    format!(
+        " {} of them disagree with their replays: {}.",
+        site_game_ids.len(),
+        site_game_ids.iter().map(i64::to_string).collect::<Vec<String>>().join(", "),
+    )
+}
+
+// For when the engine or the variant catalog has changed since the games were ingested
+pub async fn replay_all_games(
+    pool: &DbAdminPool,
+    audit: &AuditContext,
+) -> Result<Vec<GameReplay>> {
+    let mut tx = pool.0.begin().await?;
+    let site_game_ids: Vec<i64> = sqlx::query!(
+        "select site_game_id
+        from game_exports",
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| record.site_game_id).collect();
+    let (tx, flagged_replays) = replay_games(tx, &site_game_ids).await?;
+    let tx = update_standings_of_site_games(tx, &site_game_ids).await?;
+    commit_audited(tx, audit, site_game_ids.len() as u64).await?;
+    Ok(flagged_replays)
+}
+
+pub async fn get_game_replays(
+    pool: &DbViewerPool,
+    flagged_only: bool,
+) -> Result<Vec<GameReplay>> {
+    Ok(sqlx::query!(
+        "select
+            games.site_game_id
+          , games.score reported_score
+          , games.turns reported_turns
+          , game_replays.score
+          , game_replays.pre_strikeout_score
+          , game_replays.turns
+          , game_replays.num_strikes
+          , game_replays.end_condition
+          , game_replays.error
+          , game_replays.mismatched
+        from game_replays
+        join games on game_id = games.id
+        where mismatched or not $1
+        order by games.site_game_id",
+        flagged_only,
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| GameReplay {
+        site_game_id: record.site_game_id.unwrap(),
+        reported_score: record.reported_score,
+        reported_turns: record.reported_turns,
+        outcome: match (record.score, record.turns, record.num_strikes, record.end_condition) {
+            (Some(score), Some(turns), Some(num_strikes), Some(end_condition)) => {
+                Some(ReplayOutcome {
+                    score,
+                    // replayed before pre-strikeout scores were recorded
+                    pre_strikeout_score: record.pre_strikeout_score.unwrap_or(score),
+                    turns,
+                    num_strikes,
+                    // only ever written from EndCondition::as_str
+                    end_condition: EndCondition::parse(&end_condition).unwrap(),
+                })
+            },
+            _ => None,
+        },
+        error: record.error,
+        mismatched: record.mismatched,
+    }).collect())
+}
@@ -21,6 +21,12 @@ pub struct CombinedResult {
     pub datetime_game_started: UtcDateTime,
     pub datetime_game_ended: UtcDateTime,
     pub character_name: Option<String>,
+    // the rest are null when the variant's max score is unknown
+    pub max_score: Option<i16>,
+    pub normalized_score: Option<f64>,
+    pub is_perfect_score: Option<bool>,
+    // null when unknown, e.g. for games ingested without an export or an end condition
+    pub end_condition: Option<String>,
 }
 
 pub async fn get_combined_results(
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Utc, Weekday};
+use sqlx::{PgConnection, Row, postgres::PgRow};
+use crate::{
+    DbViewerPool,
+    DbAdminPool,
+    model::{
+        Date,
+        Tx,
+        UtcDateTime,
+        audit::{commit_audited, AuditContext},
+        competition::{
+            add_competitions_in_tx,
+            generate_base_seed_names,
+            Competition,
+            PartiallySpecifiedCompetition,
+            TimeControl,
+            DEFAULT_BASE_SEED_NAME_PATTERN,
+            DEFAULT_NUM_SEEDS,
+        },
+    },
+};
+use anyhow::Result;
+
+// Who the scheduler's changes are recorded as in the audit log
+const SCHEDULER_USER_ID: &str = "scheduler";
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+#[derive(Serialize, Deserialize)]
+pub struct RotationEntry {
+    pub num_players: i16,
+    pub variant_name: String,
+}
+
+// A recurring competition. Every cadence_days days, a round of competitions is created ahead
+// of time, taking the next competitions_per_round entries of the rotation.
+// Rounds are numbered from 0.
+#[derive(Serialize, Deserialize)]
+pub struct Schedule {
+    pub name: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    pub cadence_days: i16,
+    pub end_weekday: Weekday,
+    // UTC, e.g. "13:00:00"
+    pub end_time: NaiveTime,
+    // Round 0 ends on the first end_weekday on or after this date
+    pub first_round_date: Date,
+    // How many days before a round starts its competitions are created
+    #[serde(default = "default_lead_days")]
+    pub lead_days: i16,
+    #[serde(default = "default_num_seeds")]
+    pub num_seeds: i16,
+    // As for ad hoc competitions, plus {round}
+    #[serde(default = "default_seed_name_pattern")]
+    pub seed_name_pattern: String,
+    #[serde(default = "default_competitions_per_round")]
+    pub competitions_per_round: i16,
+    pub rotation: Vec<RotationEntry>,
+    #[serde(default)]
+    pub series_names: Vec<String>,
+    // Left out to use the same defaults as a competition posted without them
+    pub deckplay_enabled: Option<bool>,
+    pub empty_clues_enabled: Option<bool>,
+    pub characters_enabled: Option<bool>,
+    pub scoring_type: Option<String>,
+    pub time_control: Option<TimeControl>,
+    pub additional_rules: Option<String>,
+    // Maintained by the scheduler, and ignored when posted
+    #[serde(default)]
+    pub last_created_round: Option<i32>,
+}
+
+fn default_enabled() -> bool { true }
+fn default_lead_days() -> i16 { 7 }
+fn default_num_seeds() -> i16 { DEFAULT_NUM_SEEDS }
+fn default_seed_name_pattern() -> String { DEFAULT_BASE_SEED_NAME_PATTERN.to_owned() }
+fn default_competitions_per_round() -> i16 { 1 }
+
+#[derive(Serialize, Deserialize)]
+pub struct ScheduledRound {
+    pub schedule_name: String,
+    pub round: i32,
+    pub start_datetime: UtcDateTime,
+    pub end_datetime: UtcDateTime,
+    // whether its competitions exist yet
+    pub created: bool,
+    pub competitions: Vec<Competition>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+    #[error("No schedule named {0} was found")]
+    NotFound(String),
+    #[error("Schedule {0} has no variants and player counts to rotate through")]
+    EmptyRotation(String),
+    #[error("No variant named {0} was found")]
+    VariantNotFound(String),
+    #[error("No series named {0} was found")]
+    SeriesNotFound(String),
+}
+
+impl Schedule {
+    fn first_round_end_datetime(&self) -> UtcDateTime {
+        let days_to_end_weekday = (
+            7 + self.end_weekday.num_days_from_monday()
+            - self.first_round_date.weekday().num_days_from_monday()
+        ) % 7;
+        let first_round_end_date = self.first_round_date + Duration::days(days_to_end_weekday as i64);
+        DateTime::<Utc>::from_utc(first_round_end_date.and_time(self.end_time), Utc)
+    }
+
+    fn cadence(&self) -> Duration {
+        Duration::days(self.cadence_days as i64)
+    }
+
+    pub fn round_end_datetime(&self, round: i32) -> UtcDateTime {
+        self.first_round_end_datetime() + self.cadence() * round
+    }
+
+    pub fn round_start_datetime(&self, round: i32) -> UtcDateTime {
+        self.round_end_datetime(round) - self.cadence()
+    }
+
+    // The first round that hasn't ended yet
+    pub fn current_round(&self, now: UtcDateTime) -> i32 {
+        let time_since_first_round_end = now - self.first_round_end_datetime();
+        if time_since_first_round_end < Duration::zero() {
+            return 0;
+        }
+        (time_since_first_round_end.num_seconds() / self.cadence().num_seconds()) as i32 + 1
+    }
+
+    // Rounds that have already ended are skipped
+    pub fn next_uncreated_round(&self, now: UtcDateTime) -> i32 {
+        self.last_created_round
+            .map_or(0, |last_created_round| last_created_round + 1)
+            .max(self.current_round(now))
+    }
+
+    // The rounds whose competitions should have been created by now, but haven't
+    fn due_rounds(&self, now: UtcDateTime) -> std::ops::Range<i32> {
+        let first_due_round = self.next_uncreated_round(now);
+        let mut last_due_round = first_due_round;
+        while self.round_start_datetime(last_due_round) - Duration::days(self.lead_days as i64) <= now {
+            last_due_round += 1;
+        }
+        first_due_round..last_due_round
+    }
+
+    pub fn round_competitions(&self, round: i32) -> Vec<PartiallySpecifiedCompetition> {
+        let end_datetime = self.round_end_datetime(round);
+        let seed_name_pattern = self.seed_name_pattern.replace("{round}", &round.to_string());
+        (0..self.competitions_per_round as usize).map(|idx| {
+            // competitions in the same round would otherwise share their seeds' names
+            let seed_name_pattern = if self.competitions_per_round == 1 {
+                seed_name_pattern.clone()
+            } else if seed_name_pattern.contains("{competition}") {
+                seed_name_pattern.replace("{competition}", &(idx + 1).to_string())
+            } else {
+                format!("{}-{}", seed_name_pattern, idx + 1)
+            };
+            let base_seed_names = generate_base_seed_names(&seed_name_pattern, end_datetime, self.num_seeds);
+            let rotation_idx = round as usize * self.competitions_per_round as usize + idx;
+            let rotation_entry = &self.rotation[rotation_idx % self.rotation.len()];
+            PartiallySpecifiedCompetition {
+                num_players: rotation_entry.num_players,
+                variant_name: Some(rotation_entry.variant_name.clone()),
+                title: None,
+                // created ahead of time, so games on the seeds only count once the round starts
+                start_datetime: Some(self.round_start_datetime(round)),
+                end_datetime: Some(end_datetime),
+                deckplay_enabled: self.deckplay_enabled,
+                empty_clues_enabled: self.empty_clues_enabled,
+                characters_enabled: self.characters_enabled,
+                scoring_type: self.scoring_type.clone(),
+                time_control: self.time_control.as_ref().map(|time_control| TimeControl {
+                    base_time_seconds: time_control.base_time_seconds,
+                    turn_time_seconds: time_control.turn_time_seconds,
+                }),
+                additional_rules: self.additional_rules.clone(),
+                base_seed_names: Some(base_seed_names),
+                seeds: None,
+                series_names: Some(self.series_names.clone()),
+                registration_required: None,
+                tie_breaks: None,
+            }
+        }).collect()
+    }
+
+    fn scheduled_round(&self, round: i32) -> ScheduledRound {
+        ScheduledRound {
+            schedule_name: self.name.clone(),
+            round,
+            start_datetime: self.round_start_datetime(round),
+            end_datetime: self.round_end_datetime(round),
+            created: self.last_created_round.is_some_and(|last_created_round| round <= last_created_round),
+            competitions: self.round_competitions(round).into_iter()
+                .map(PartiallySpecifiedCompetition::fill_missing_values_with_defaults)
+                .collect(),
+        }
+    }
+}
+
+// Schedules that already exist are replaced, apart from which of their rounds were created
+pub async fn add_schedules(
+    pool: &DbAdminPool,
+    schedules: &[Schedule],
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    for schedule in schedules {
+        if schedule.rotation.is_empty() {
+            return Err(ScheduleError::EmptyRotation(schedule.name.clone()).into());
+        }
+        let (base_time_seconds, turn_time_seconds) = match &schedule.time_control {
+            None => (None, None),
+            Some(time_control) => (
+                Some(time_control.base_time_seconds),
+                Some(time_control.turn_time_seconds),
+            ),
+        };
+        let schedule_id: i16 = sqlx::query(
+            "insert into competition_schedules (
+                name
+              , enabled
+              , cadence_days
+              , end_weekday
+              , end_time
+              , first_round_date
+              , lead_days
+              , num_seeds
+              , seed_name_pattern
+              , competitions_per_round
+              , deckplay_enabled
+              , empty_clues_enabled
+              , characters_enabled
+              , scoring_type
+              , base_time_seconds
+              , turn_time_seconds
+              , additional_rules
+            ) values (
+                $1
+              , $2
+              , $3
+              , $4
+              , $5
+              , $6
+              , $7
+              , $8
+              , $9
+              , $10
+              , $11
+              , $12
+              , $13
+              , cast($14 as scoring_type)
+              , $15
+              , $16
+              , $17
+            )
+            on conflict (name) do update set
+                enabled = excluded.enabled
+              , cadence_days = excluded.cadence_days
+              , end_weekday = excluded.end_weekday
+              , end_time = excluded.end_time
+              , first_round_date = excluded.first_round_date
+              , lead_days = excluded.lead_days
+              , num_seeds = excluded.num_seeds
+              , seed_name_pattern = excluded.seed_name_pattern
+              , competitions_per_round = excluded.competitions_per_round
+              , deckplay_enabled = excluded.deckplay_enabled
+              , empty_clues_enabled = excluded.empty_clues_enabled
+              , characters_enabled = excluded.characters_enabled
+              , scoring_type = excluded.scoring_type
+              , base_time_seconds = excluded.base_time_seconds
+              , turn_time_seconds = excluded.turn_time_seconds
+              , additional_rules = excluded.additional_rules
+            returning id")
+            .bind(&schedule.name)
+            .bind(schedule.enabled)
+            .bind(schedule.cadence_days)
+            .bind(schedule.end_weekday.number_from_monday() as i16)
+            .bind(schedule.end_time)
+            .bind(schedule.first_round_date)
+            .bind(schedule.lead_days)
+            .bind(schedule.num_seeds)
+            .bind(&schedule.seed_name_pattern)
+            .bind(schedule.competitions_per_round)
+            .bind(schedule.deckplay_enabled)
+            .bind(schedule.empty_clues_enabled)
+            .bind(schedule.characters_enabled)
+            .bind(&schedule.scoring_type)
+            .bind(base_time_seconds)
+            .bind(turn_time_seconds)
+            .bind(&schedule.additional_rules)
+            .map(|row: PgRow| row.get(0))
+            .fetch_one(&mut tx).await?;
+        sqlx::query!(
+            "delete from competition_schedule_rotations
+            where schedule_id = $1",
+            schedule_id,
+        ).execute(&mut tx).await?;
+        for (position, rotation_entry) in schedule.rotation.iter().enumerate() {
+            let num_rows = sqlx::query!(
+                "insert into competition_schedule_rotations (
+                    schedule_id
+                  , position
+                  , num_players
+                  , variant_id
+                )
+                select
+                    $1
+                  , $2
+                  , $3
+                  , id
+                from variants
+                where name = $4
+                    and removed_at is null",
+                schedule_id,
+                position as i16,
+                rotation_entry.num_players,
+                rotation_entry.variant_name,
+            ).execute(&mut tx).await?;
+            if num_rows == 0 {
+                return Err(ScheduleError::VariantNotFound(rotation_entry.variant_name.clone()).into());
+            }
+        }
+        sqlx::query!(
+            "delete from competition_schedule_series
+            where schedule_id = $1",
+            schedule_id,
+        ).execute(&mut tx).await?;
+        for series_name in &schedule.series_names {
+            let num_rows = sqlx::query!(
+                "insert into competition_schedule_series (
+                    schedule_id
+                  , series_id
+                )
+                select
+                    $1
+                  , id
+                from series
+                where name = $2",
+                schedule_id,
+                series_name,
+            ).execute(&mut tx).await?;
+            if num_rows == 0 {
+                return Err(ScheduleError::SeriesNotFound(series_name.clone()).into());
+            }
+        }
+    }
+    commit_audited(tx, audit, schedules.len() as u64).await
+}
+
+// The competitions it already created are left alone
+pub async fn remove_schedule(
+    pool: &DbAdminPool,
+    schedule_name: &str,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_rows = sqlx::query!(
+        "delete from competition_schedules
+        where name = $1",
+        schedule_name,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        return Err(ScheduleError::NotFound(schedule_name.to_owned()).into());
+    }
+    commit_audited(tx, audit, num_rows).await
+}
+
+pub async fn get_schedules(
+    pool: &DbViewerPool,
+) -> Result<Vec<Schedule>> {
+    let mut conn = pool.0.acquire().await?;
+    Ok(load_schedules(&mut conn).await?.into_iter().map(|(_, schedule)| schedule).collect())
+}
+
+pub async fn get_schedule(
+    pool: &DbViewerPool,
+    schedule_name: &str,
+) -> Result<Schedule> {
+    let mut conn = pool.0.acquire().await?;
+    load_schedules(&mut conn).await?.into_iter()
+        .map(|(_, schedule)| schedule)
+        .find(|schedule| schedule.name == schedule_name)
+        .ok_or_else(|| ScheduleError::NotFound(schedule_name.to_owned()).into())
+}
+
+// For competitions of the schedule's rounds that were created some other way, so that the
+// scheduler doesn't create them again
+pub(crate) async fn mark_rounds_created(
+    mut tx: Tx,
+    schedule_name: &str,
+    last_round: i32,
+) -> Result<Tx> {
+    sqlx::query!(
+        "update competition_schedules
+        set last_created_round = greatest(last_created_round, $2)
+        where name = $1",
+        schedule_name,
+        last_round,
+    ).execute(&mut tx).await?;
+    Ok(tx)
+}
+
+// The next `num_rounds` rounds of every enabled schedule, starting from the current one,
+// in order of when they end
+pub async fn get_calendar(
+    pool: &DbViewerPool,
+    now: UtcDateTime,
+    num_rounds: i32,
+) -> Result<Vec<ScheduledRound>> {
+    let mut conn = pool.0.acquire().await?;
+    let mut scheduled_rounds: Vec<ScheduledRound> = load_schedules(&mut conn).await?.into_iter()
+        .map(|(_, schedule)| schedule)
+        .filter(|schedule| schedule.enabled && !schedule.rotation.is_empty())
+        .flat_map(|schedule| {
+            let current_round = schedule.current_round(now);
+            (current_round..current_round + num_rounds)
+                .map(|round| schedule.scheduled_round(round))
+                .collect::<Vec<ScheduledRound>>()
+        })
+        .collect();
+    scheduled_rounds.sort_by(|a, b| a.end_datetime.cmp(&b.end_datetime)
+        .then_with(|| a.schedule_name.cmp(&b.schedule_name)));
+    Ok(scheduled_rounds)
+}
+
+// Creates the competitions of every round that's due, each round in its own transaction.
+// Returns the rounds that were created.
+pub async fn create_due_competitions(
+    pool: &DbAdminPool,
+    now: UtcDateTime,
+) -> Result<Vec<ScheduledRound>> {
+    let schedules = {
+        let mut conn = pool.0.acquire().await?;
+        load_schedules(&mut conn).await?
+    };
+    let mut created_rounds = Vec::new();
+    for (schedule_id, schedule) in schedules {
+        if !schedule.enabled || schedule.rotation.is_empty() {
+            continue;
+        }
+        // one schedule failing (e.g. on a seed name that's taken) doesn't hold up the others
+        let schedule_name = schedule.name.clone();
+        match create_due_rounds(pool, schedule_id, schedule, now).await {
+            Ok(schedule_created_rounds) => created_rounds.extend(schedule_created_rounds),
+            Err(err) => error!("Couldn't create the due rounds of schedule {}: {}", schedule_name, err),
+        }
+    }
+    Ok(created_rounds)
+}
+
+// The schedule's transaction is rolled back when it's dropped on an error
+async fn create_due_rounds(
+    pool: &DbAdminPool,
+    schedule_id: i16,
+    mut schedule: Schedule,
+    now: UtcDateTime,
+) -> Result<Vec<ScheduledRound>> {
+    let mut tx = pool.0.begin().await?;
+    // keeps two servers from creating the same round
+    schedule.last_created_round = sqlx::query!(
+        "select last_created_round
+        from competition_schedules
+        where id = $1
+        for update",
+        schedule_id,
+    ).fetch_one(&mut tx).await?.last_created_round;
+    let due_rounds = schedule.due_rounds(now);
+    if due_rounds.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut competitions = Vec::new();
+    for round in due_rounds.clone() {
+        competitions.extend(schedule.round_competitions(round));
+    }
+    let audit = AuditContext::new(
+        &format!("scheduler {}", schedule.name),
+        SCHEDULER_USER_ID,
+        &competitions,
+        false,
+    );
+    let num_competitions = competitions.len();
+    tx = add_competitions_in_tx(tx, competitions).await?;
+    sqlx::query!(
+        "update competition_schedules
+        set last_created_round = $2
+        where id = $1",
+        schedule_id,
+        due_rounds.end - 1,
+    ).execute(&mut tx).await?;
+    commit_audited(tx, &audit, num_competitions as u64).await?;
+    schedule.last_created_round = Some(due_rounds.end - 1);
+    Ok(due_rounds.map(|round| schedule.scheduled_round(round)).collect())
+}
+
+async fn load_schedules(
+    conn: &mut PgConnection,
+) -> Result<Vec<(i16, Schedule)>> {
+    let mut rotations: HashMap<i16, Vec<RotationEntry>> = HashMap::new();
+    for record in sqlx::query!(
+        "select
+            competition_schedule_rotations.schedule_id
+          , competition_schedule_rotations.num_players
+          , variants.name variant_name
+        from competition_schedule_rotations
+        join variants
+            on variants.id = competition_schedule_rotations.variant_id
+        order by
+            competition_schedule_rotations.schedule_id
+          , competition_schedule_rotations.position",
+    ).fetch_all(&mut *conn).await? {
+        rotations.entry(record.schedule_id).or_default().push(RotationEntry {
+            num_players: record.num_players,
+            variant_name: record.variant_name,
+        });
+    }
+    let mut series_names: HashMap<i16, Vec<String>> = HashMap::new();
+    for record in sqlx::query!(
+        "select
+            competition_schedule_series.schedule_id
+          , series.name
+        from competition_schedule_series
+        join series
+            on series.id = competition_schedule_series.series_id
+        order by series.name",
+    ).fetch_all(&mut *conn).await? {
+        series_names.entry(record.schedule_id).or_default().push(record.name);
+    }
+    let schedule_records = sqlx::query!(
+        "select
+            id
+          , name
+          , enabled
+          , cadence_days
+          , end_weekday
+          , end_time
+          , first_round_date
+          , lead_days
+          , num_seeds
+          , seed_name_pattern
+          , competitions_per_round
+          , deckplay_enabled
+          , empty_clues_enabled
+          , characters_enabled
+          , scoring_type::text
+          , base_time_seconds
+          , turn_time_seconds
+          , additional_rules
+          , last_created_round
+        from competition_schedules
+        order by name",
+    ).fetch_all(&mut *conn).await?;
+    Ok(schedule_records.into_iter().map(|record| (record.id, Schedule {
+        name: record.name,
+        enabled: record.enabled,
+        cadence_days: record.cadence_days,
+        end_weekday: WEEKDAYS[record.end_weekday as usize - 1],
+        end_time: record.end_time,
+        first_round_date: record.first_round_date,
+        lead_days: record.lead_days,
+        num_seeds: record.num_seeds,
+        seed_name_pattern: record.seed_name_pattern,
+        competitions_per_round: record.competitions_per_round,
+        rotation: rotations.remove(&record.id).unwrap_or_default(),
+        series_names: series_names.remove(&record.id).unwrap_or_default(),
+        deckplay_enabled: record.deckplay_enabled,
+        empty_clues_enabled: record.empty_clues_enabled,
+        characters_enabled: record.characters_enabled,
+        scoring_type: record.scoring_type,
+        time_control: match (record.base_time_seconds, record.turn_time_seconds) {
+            (Some(base_time_seconds), Some(turn_time_seconds)) => Some(TimeControl {
+                base_time_seconds,
+                turn_time_seconds,
+            }),
+            _ => None,
+        },
+        additional_rules: record.additional_rules,
+        last_created_round: record.last_created_round,
+    })).collect())
+}
@@ -5,26 +5,46 @@ use crate::{
     DbAdminPool,
     model::{
         Tx,
+        UtcDateTime,
+        audit::{commit_audited, AuditContext},
         competition::{
-            get_competition_with_ids,
-            competition_with_derived_quantities_from_ruleset_with_ids, 
+            get_competitions_with_derived_quantities,
             CompetitionWithDerivedQuantities,
-        }
+        },
+        tie_break::{
+            explain_tie_break,
+            format_tie_breaks,
+            parse_tie_breaks,
+            tie_break_strs,
+            validate_tie_breaks,
+            TieBreak,
+            TieBreakStats,
+        },
     }
 };
 use anyhow::Result;
 
 #[derive(Serialize, Deserialize)]
 pub struct Series {
-    name: String,
-    first_n: Option<i16>,
-    top_n: Option<i16>,
+    pub name: String,
+    pub first_n: Option<i16>,
+    pub top_n: Option<i16>,
+    // rank by the fraction of the max score instead of by fractional MP, so that variants with
+    // different max scores can be compared
+    #[serde(default)]
+    pub normalized_scores: bool,
+    // breaks ties on score, in order, over the competitions that count
+    #[serde(default)]
+    pub tie_breaks: Vec<TieBreak>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SeriesView {
     series: Series,
+    // e.g. "total score, then fewer turns"; empty when ties are left as shared ranks
+    formatted_tie_breaks: String,
     active_competitions: Vec<CompetitionWithDerivedQuantities>,
+    upcoming_competitions: Vec<CompetitionWithDerivedQuantities>,
     past_competition_names: Vec<String>,
     leaderboard_records: Vec<LeaderboardRecord>,
     competition_scores_table_headers: Vec<String>,
@@ -36,13 +56,19 @@ pub struct LeaderboardRecord {
     pub player_name: String,
     pub score: f64,
     pub mean_frac_mp: f64,
+    pub mean_normalized_score: Option<f64>,
+    pub perfect_score_rate: Option<f64>,
     pub competition_results: Vec<Option<CompetitionResultRecordSummary>>,
+    pub tie_break_stats: TieBreakStats,
+    // why the player is ahead of the next one, when they're tied on score
+    pub tie_break_note: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct CompetitionResultRecordSummary {
     pub competition_name: String,
     pub frac_mp: f64,
+    pub normalized_score: Option<f64>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -54,16 +80,104 @@ enum GetSeriesError {
 pub async fn add_series(
     pool: &DbAdminPool,
     series: Vec<Series>,
+    audit: &AuditContext,
 ) -> Result<()> {
     // if a single competition causes an error, don't commit any
     let mut tx = pool.0.begin().await?;
+    let num_series = series.len();
     for series in series {
         // I'd prefer to use references throughout, but I don't know a better pattern that would
         // allow me to pass the same mutable borrow to multiple functions.
         tx = add_single_series(tx, series).await?;
     }
-    tx.commit().await?;
-    Ok(())
+    commit_audited(tx, audit, num_series as u64).await
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SeriesMembershipError {
+    #[error("No series named {0} was found")]
+    SeriesNotFound(String),
+    #[error("No competition named {0} was found")]
+    CompetitionNotFound(String),
+}
+
+// The series leaderboards are views over the competition standings, so nothing needs to be
+// recomputed when a competition joins or leaves a series
+pub async fn add_competition_to_series(
+    pool: &DbAdminPool,
+    series_name: &str,
+    competition_name: &str,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_rows = sqlx::query!(
+        "insert into series_competitions (
+            series_id
+          , competition_id
+        )
+        select series.id, competition_names.competition_id
+        from series, competition_names
+        where series.name = $1
+            and competition_names.name = $2
+        on conflict do nothing",
+        series_name,
+        competition_name,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        if let Some(err) = find_series_membership_error(pool, series_name, competition_name).await? {
+            return Err(err.into());
+        }
+    }
+    commit_audited(tx, audit, num_rows).await
+}
+
+pub async fn remove_competition_from_series(
+    pool: &DbAdminPool,
+    series_name: &str,
+    competition_name: &str,
+    audit: &AuditContext,
+) -> Result<()> {
+    let mut tx = pool.0.begin().await?;
+    let num_rows = sqlx::query!(
+        "delete from series_competitions
+        using series, competition_names
+        where series_id = series.id
+            and series_competitions.competition_id = competition_names.competition_id
+            and series.name = $1
+            and competition_names.name = $2",
+        series_name,
+        competition_name,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        if let Some(err) = find_series_membership_error(pool, series_name, competition_name).await? {
+            return Err(err.into());
+        }
+    }
+    commit_audited(tx, audit, num_rows).await
+}
+
+// A change that touched no rows is fine if the membership was already as requested,
+// but not if the series or competition doesn't exist
+async fn find_series_membership_error(
+    pool: &DbAdminPool,
+    series_name: &str,
+    competition_name: &str,
+) -> Result<Option<SeriesMembershipError>> {
+    let series_exists = sqlx::query!(
+        "select id from series where name = $1",
+        series_name,
+    ).fetch_optional(&pool.0).await?.is_some();
+    if !series_exists {
+        return Ok(Some(SeriesMembershipError::SeriesNotFound(series_name.to_owned())));
+    }
+    let competition_exists = sqlx::query!(
+        "select competition_id from competition_names where name = $1",
+        competition_name,
+    ).fetch_optional(&pool.0).await?.is_some();
+    if !competition_exists {
+        return Ok(Some(SeriesMembershipError::CompetitionNotFound(competition_name.to_owned())));
+    }
+    Ok(None)
 }
 
 pub async fn get_series_view(
@@ -76,11 +190,15 @@ pub async fn get_series_view(
     if series_result.is_err(){
         return Err(GetSeriesError::NotFound.into());
     }
-    let (leaderboard_records, num_comps) =
+    let series = series_result.unwrap();
+    let (mut leaderboard_records, num_comps) =
         get_series_leaderboard(pool, series_name, max_num_comps).await?;
+    note_tie_breaks(&mut leaderboard_records, &series.tie_breaks);
     Ok(SeriesView {
-        series: series_result.unwrap(),
+        formatted_tie_breaks: format_tie_breaks(&series.tie_breaks),
+        series,
         active_competitions: get_series_active_competitions(pool, series_name).await?,
+        upcoming_competitions: get_series_upcoming_competitions(pool, series_name).await?,
         past_competition_names: get_series_past_competition_names(pool, series_name).await?,
         leaderboard_records,
         competition_scores_table_headers:
@@ -113,11 +231,17 @@ async fn get_series_leaderboard(
           , player_name
           , score
           , mean_frac_mp
+          , mean_normalized_score
+          , perfect_score_rate
+          , total_score
+          , total_turns
+          , seeds_won
+          , completed_at
         from series_player_scores
         where series_name = $1",
         series_name,
     ).fetch_all(&pool.0).await?;
-    if !series_name.starts_with("All-time") && leaderboard_aggregate_records.len() != 0 {
+    if !series_name.starts_with("All-time") && !leaderboard_aggregate_records.is_empty() {
         let num_comps = sqlx::query!(
             "select max(num_comps) max_num_comps
             from (
@@ -132,13 +256,24 @@ async fn get_series_leaderboard(
             let mut leaderboard_games = HashMap::new();
             for record in leaderboard_aggregate_records.into_iter() {
                 let player_name = record.player_name.unwrap();
+                let tie_break_stats = tie_break_stats(
+                    record.total_score,
+                    record.total_turns,
+                    record.seeds_won,
+                    record.completed_at,
+                );
                 leaderboard_games.insert(
                     player_name,
                     (
                         Some((
                             record.rank.unwrap(),
-                            record.score.unwrap(),
+                            // null in normalized series when none of the player's variants
+                            // has a known max score
+                            record.score.unwrap_or(0.0),
                             record.mean_frac_mp.unwrap(),
+                            record.mean_normalized_score,
+                            record.perfect_score_rate,
+                            tie_break_stats,
                         )),
                         vec![]
                     ),
@@ -149,13 +284,18 @@ async fn get_series_leaderboard(
                     player_name
                   , competition_name
                   , fractional_mp
+                  , normalized_score
                 from series_competition_results
                 where series_name = $1",
                 series_name,
             ).fetch_all(&pool.0).await?;
             for record in leaderboard_records.into_iter() {
                 let (_, competitions) = leaderboard_games.get_mut(&record.player_name.unwrap()).unwrap();
-                competitions.push((record.competition_name.unwrap(), record.fractional_mp.unwrap()))
+                competitions.push((
+                    record.competition_name.unwrap(),
+                    record.fractional_mp.unwrap(),
+                    record.normalized_score,
+                ))
             }
             let mut records = leaderboard_games.into_iter().map(|(player, record)| {
                 let mut competition_results: Vec<Option<CompetitionResultRecordSummary>> =
@@ -163,21 +303,35 @@ async fn get_series_leaderboard(
                         Some(CompetitionResultRecordSummary {
                             competition_name: result.0,
                             frac_mp: result.1,
+                            normalized_score: result.2,
                         })
                     ).collect();
                 competition_results.extend(
                     (competition_results.len()..num_comps as usize).map(|_| None)
                 );
-                let (rank, score, mean_frac_mp) = record.0.unwrap();
+                let (
+                    rank,
+                    score,
+                    mean_frac_mp,
+                    mean_normalized_score,
+                    perfect_score_rate,
+                    tie_break_stats,
+                ) = record.0.unwrap();
                 LeaderboardRecord {
                     rank,
                     player_name: player,
                     score,
                     mean_frac_mp,
+                    mean_normalized_score,
+                    perfect_score_rate,
                     competition_results,
+                    tie_break_stats,
+                    tie_break_note: None,
                 }
             }).collect::<Vec<LeaderboardRecord>>();
-            records.sort_unstable_by(|r1, r2| r2.score.partial_cmp(&r1.score).unwrap());
+            // the ranks have the series' tie-breaks applied; players sharing one go by name
+            records.sort_unstable_by(|record1, record2| record1.rank.cmp(&record2.rank)
+                .then_with(|| record1.player_name.cmp(&record2.player_name)));
             return Ok((
                 records,
                 num_comps,
@@ -188,15 +342,56 @@ async fn get_series_leaderboard(
         LeaderboardRecord {
             rank: record.rank.unwrap(),
             player_name: record.player_name.unwrap(),
-            score: record.score.unwrap(),
+            score: record.score.unwrap_or(0.0),
             mean_frac_mp: record.mean_frac_mp.unwrap(),
+            mean_normalized_score: record.mean_normalized_score,
+            perfect_score_rate: record.perfect_score_rate,
             competition_results: vec![],
+            tie_break_stats: tie_break_stats(
+                record.total_score,
+                record.total_turns,
+                record.seeds_won,
+                record.completed_at,
+            ),
+            tie_break_note: None,
         }
     }).collect::<Vec<LeaderboardRecord>>();
-    records.sort_unstable_by(|r1, r2| r2.score.partial_cmp(&r1.score).unwrap());
+    records.sort_unstable_by(|record1, record2| record1.rank.cmp(&record2.rank)
+        .then_with(|| record1.player_name.cmp(&record2.player_name)));
     Ok((records, 0))
 }
 
+// Nulls come from players none of whose games count
+fn tie_break_stats(
+    total_score: Option<i64>,
+    total_turns: Option<i64>,
+    seeds_won: Option<i64>,
+    completed_at: Option<UtcDateTime>,
+) -> TieBreakStats {
+    TieBreakStats {
+        total_score: total_score.unwrap_or(0),
+        total_turns: total_turns.unwrap_or(0),
+        seeds_won: seeds_won.unwrap_or(0),
+        completed_at,
+    }
+}
+
+// Records are in order of rank, so each one tied on score with the next is ahead of it on a
+// tie-break, unless they share a rank
+fn note_tie_breaks(records: &mut [LeaderboardRecord], tie_breaks: &[TieBreak]) {
+    for idx in 1..records.len() {
+        let (ahead, behind) = (&records[idx - 1], &records[idx]);
+        if ahead.score != behind.score || ahead.rank == behind.rank {
+            continue;
+        }
+        records[idx - 1].tie_break_note = explain_tie_break(
+            tie_breaks,
+            &ahead.tie_break_stats,
+            &behind.tie_break_stats,
+        ).map(|explanation| format!("Ahead of {} {}", behind.player_name, explanation));
+    }
+}
+
 async fn get_series_active_competitions(
     pool: &DbViewerPool,
     series_name: &str,
@@ -209,25 +404,30 @@ async fn get_series_active_competitions(
         join competitions on competition_id = competitions.id
         where series.name = $1
             and end_datetime > now()
+            and (start_datetime is null or start_datetime <= now())
         order by competition_names.name desc"#,
         series_name,
     ).fetch_all(&pool.0).await?;
     let series_active_competition_ids: Vec<i16> = series_active_competition_id_records.into_iter().map(
         |record| record.id).collect();
-    let mut active_competitions_rulesets_with_ids = Vec::new();
-    for id in series_active_competition_ids.into_iter() {
-        active_competitions_rulesets_with_ids.push(get_competition_with_ids(pool, id).await?);
-    }
-    let mut competitions_with_derived_quantities = Vec::new();
-    for ruleset_with_ids in active_competitions_rulesets_with_ids {
-        competitions_with_derived_quantities.push(
-            competition_with_derived_quantities_from_ruleset_with_ids(
-                pool,
-                ruleset_with_ids,
-            ).await?
-        );
-    }
-    Ok(competitions_with_derived_quantities)
+    get_competitions_with_derived_quantities(pool, &series_active_competition_ids).await
+}
+
+async fn get_series_upcoming_competitions(
+    pool: &DbViewerPool,
+    series_name: &str,
+) -> Result<Vec<CompetitionWithDerivedQuantities>> {
+    let series_upcoming_competition_ids: Vec<i16> = sqlx::query!(
+        r#"select competitions.id
+        from series
+        join series_competitions on series.id = series_id
+        join competitions on competition_id = competitions.id
+        where series.name = $1
+            and start_datetime > now()
+        order by start_datetime, end_datetime"#,
+        series_name,
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| record.id).collect();
+    get_competitions_with_derived_quantities(pool, &series_upcoming_competition_ids).await
 }
 
 async fn get_series_past_competition_names(
@@ -254,36 +454,51 @@ async fn get_series(
     pool: &DbViewerPool,
     series_name: &str,
 ) -> Result<Series> {
-    let series = sqlx::query_as!(
-        Series,
+    let record = sqlx::query!(
         "select
             name,
             first_n,
-            top_n
+            top_n,
+            normalized_scores,
+            tie_breaks
         from series
         where name = $1",
         series_name,
     ).fetch_one(&pool.0).await?;
-    Ok(series)
+    Ok(Series {
+        name: record.name,
+        first_n: record.first_n,
+        top_n: record.top_n,
+        normalized_scores: record.normalized_scores,
+        tie_breaks: parse_tie_breaks(&record.tie_breaks),
+    })
 }
 
 async fn add_single_series(
     mut tx: Tx,
     series: Series,
 ) -> Result<Tx> {
+    validate_tie_breaks(&series.tie_breaks)?;
+    let tie_breaks = tie_break_strs(&series.tie_breaks);
     sqlx::query!(
         "INSERT INTO series (
             name
           , first_n
           , top_n
+          , normalized_scores
+          , tie_breaks
         ) VALUES (
             $1
           , $2
           , $3
+          , $4
+          , $5
         )",
         series.name,
         series.first_n,
         series.top_n,
+        series.normalized_scores,
+        &tie_breaks,
     ).execute(&mut tx).await?;
     Ok(tx)
 }
@@ -0,0 +1,324 @@
+// Teams that register their roster for a competition ahead of time, so that their games are
+// attributed to them even when a substitute plays
+use std::collections::HashSet;
+use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use crate::{
+    DbAdminPool,
+    DbViewerPool,
+    model::{
+        Tx,
+        UtcDateTime,
+        audit::{commit_audited, AuditContext},
+        competition::update_competition_standings,
+        game::upsert_players,
+    },
+};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TeamRegistration {
+    pub team_name: String,
+    pub players: Vec<String>,
+    // may play in place of any of `players`
+    #[serde(default)]
+    pub substitutes: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RegisteredTeam {
+    pub team_name: String,
+    pub players: Vec<String>,
+    pub substitutes: Vec<String>,
+    pub registered_at: UtcDateTime,
+    // teams registered without credentials don't get their games until an admin confirms them
+    pub confirmed: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum TeamRegistrationError {
+    #[error("No competition with that name was found")]
+    CompetitionNotFound,
+    #[error("The competition has ended")]
+    CompetitionEnded,
+    #[error("The team needs a name")]
+    MissingName,
+    #[error("The competition is for teams of {0}, but {1} players were given")]
+    RosterSize(i16, usize),
+    #[error("{0} is on the roster more than once")]
+    DuplicatePlayer(String),
+    #[error("There's already a team named {0}")]
+    NameTaken(String),
+    #[error("{0} is already on team {1}")]
+    PlayerTaken(String, String),
+    #[error("No team named {0} is registered for the competition")]
+    TeamNotFound(String),
+    #[error("Only admins can register teams for a competition that requires registration")]
+    AdminOnly,
+}
+
+struct CompetitionForRegistration {
+    id: i16,
+    num_players: i16,
+    ended: bool,
+    registration_required: bool,
+}
+
+async fn get_competition_for_registration(
+    mut tx: Tx,
+    competition_name: &str,
+) -> Result<(Tx, CompetitionForRegistration)> {
+    match sqlx::query!(
+        "select competitions.id, num_players, end_datetime <= now() ended, registration_required
+        from competition_names
+        join competitions on competition_id = competitions.id
+        where competition_names.name = $1",
+        competition_name,
+    ).fetch_optional(&mut tx).await? {
+        Some(record) => Ok((tx, CompetitionForRegistration {
+            id: record.id,
+            num_players: record.num_players,
+            ended: record.ended.unwrap_or(false),
+            registration_required: record.registration_required,
+        })),
+        None => Err(TeamRegistrationError::CompetitionNotFound.into()),
+    }
+}
+
+impl TeamRegistration {
+    fn validate(&self, num_players: i16) -> Result<(), TeamRegistrationError> {
+        if self.team_name.trim().is_empty() {
+            return Err(TeamRegistrationError::MissingName);
+        }
+        if self.players.len() != num_players as usize {
+            return Err(TeamRegistrationError::RosterSize(num_players, self.players.len()));
+        }
+        let mut names = HashSet::new();
+        for player_name in self.players.iter().chain(&self.substitutes) {
+            if !names.insert(player_name) {
+                return Err(TeamRegistrationError::DuplicatePlayer(player_name.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Open to anyone until the competition ends, like the site's tables, but only teams registered
+// by an admin get their games straight away; anyone else's registration waits for an admin to
+// confirm it. Where only registered teams' games count, a bogus registration could lock players
+// out, so only admins can register.
+pub async fn register_team(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    registration: &TeamRegistration,
+    by_admin: bool,
+    audit: &AuditContext,
+) -> Result<()> {
+    let tx = pool.0.begin().await?;
+    let (mut tx, competition) = get_competition_for_registration(tx, competition_name).await?;
+    if competition.ended {
+        return Err(TeamRegistrationError::CompetitionEnded.into());
+    }
+    if competition.registration_required && !by_admin {
+        return Err(TeamRegistrationError::AdminOnly.into());
+    }
+    registration.validate(competition.num_players)?;
+    let team_name = registration.team_name.trim();
+    if sqlx::query!(
+        "select id
+        from teams
+        where competition_id = $1 and name = $2",
+        competition.id,
+        team_name,
+    ).fetch_optional(&mut tx).await?.is_some() {
+        return Err(TeamRegistrationError::NameTaken(team_name.to_owned()).into());
+    }
+    let roster: Vec<String> = registration.players.iter()
+        .chain(&registration.substitutes).cloned().collect();
+    if let Some(record) = sqlx::query!(
+        "select players.name player_name, teams.name team_name
+        from team_members
+        join players on player_id = players.id
+        join teams on team_id = teams.id
+        where team_members.competition_id = $1 and players.name = any($2)
+        limit 1",
+        competition.id,
+        &roster,
+    ).fetch_optional(&mut tx).await? {
+        return Err(TeamRegistrationError::PlayerTaken(record.player_name, record.team_name).into());
+    }
+    let team_id = sqlx::query!(
+        "insert into teams (competition_id, name, confirmed)
+        values ($1, $2, $3)
+        returning id",
+        competition.id,
+        team_name,
+        by_admin,
+    ).fetch_one(&mut tx).await?.id;
+    let (mut tx, player_ids) = upsert_players(tx, &roster).await?;
+    for (idx, player_id) in player_ids.into_iter().enumerate() {
+        sqlx::query!(
+            "insert into team_members (
+                team_id
+              , competition_id
+              , player_id
+              , is_substitute
+            ) values (
+                $1
+              , $2
+              , $3
+              , $4
+            )",
+            team_id,
+            competition.id,
+            player_id,
+            idx >= registration.players.len(),
+        ).execute(&mut tx).await?;
+    }
+    // the team may have played already
+    tx = attribute_games_to_teams(tx, &[competition.id]).await?;
+    tx = update_competition_standings(tx, &[competition.id]).await?;
+    commit_audited(tx, audit, roster.len() as u64).await
+}
+
+// Its games are attributed to it from then on
+pub async fn confirm_team(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    team_name: &str,
+    audit: &AuditContext,
+) -> Result<()> {
+    let tx = pool.0.begin().await?;
+    let (mut tx, competition) = get_competition_for_registration(tx, competition_name).await?;
+    let num_rows = sqlx::query!(
+        "update teams
+        set confirmed = true
+        where competition_id = $1 and name = $2",
+        competition.id,
+        team_name,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        return Err(TeamRegistrationError::TeamNotFound(team_name.to_owned()).into());
+    }
+    tx = attribute_games_to_teams(tx, &[competition.id]).await?;
+    tx = update_competition_standings(tx, &[competition.id]).await?;
+    commit_audited(tx, audit, num_rows).await
+}
+
+// Its games are no longer attributed to any team
+pub async fn remove_team(
+    pool: &DbAdminPool,
+    competition_name: &str,
+    team_name: &str,
+    audit: &AuditContext,
+) -> Result<()> {
+    let tx = pool.0.begin().await?;
+    let (mut tx, competition) = get_competition_for_registration(tx, competition_name).await?;
+    let num_rows = sqlx::query!(
+        "delete from teams
+        where competition_id = $1 and name = $2",
+        competition.id,
+        team_name,
+    ).execute(&mut tx).await?;
+    if num_rows == 0 {
+        return Err(TeamRegistrationError::TeamNotFound(team_name.to_owned()).into());
+    }
+    tx = update_competition_standings(tx, &[competition.id]).await?;
+    commit_audited(tx, audit, num_rows).await
+}
+
+pub async fn get_registered_teams(
+    pool: &DbViewerPool,
+    competition_name: &str,
+) -> Result<Vec<RegisteredTeam>> {
+    let mut registered_teams: Vec<RegisteredTeam> = Vec::new();
+    for record in sqlx::query!(
+        "select
+            teams.name team_name
+          , teams.registered_at
+          , teams.confirmed
+          , players.name player_name
+          , team_members.is_substitute
+        from competition_names
+        join teams using(competition_id)
+        join team_members on team_members.team_id = teams.id
+        join players on team_members.player_id = players.id
+        where competition_names.name = $1
+        order by teams.registered_at, teams.id, players.name",
+        competition_name,
+    ).fetch_all(&pool.0).await? {
+        let team_name = record.team_name;
+        if registered_teams.last().is_none_or(|team| team.team_name != team_name) {
+            registered_teams.push(RegisteredTeam {
+                team_name,
+                players: Vec::new(),
+                substitutes: Vec::new(),
+                registered_at: record.registered_at,
+                confirmed: record.confirmed,
+            });
+        }
+        // pushed just above if there wasn't one
+        let team = registered_teams.last_mut().unwrap();
+        if record.is_substitute {
+            team.substitutes.push(record.player_name);
+        } else {
+            team.players.push(record.player_name);
+        }
+    }
+    Ok(registered_teams)
+}
+
+// A game belongs to the confirmed team whose roster has all of its players, counting alias
+// accounts as their primary account
+pub(crate) async fn attribute_games_to_teams(
+    mut tx: Tx,
+    competition_ids: &[i16],
+) -> Result<Tx> {
+    sqlx::query!(
+        "with competition_games as (
+            select games.id game_id, competition_seeds.competition_id
+            from games
+            join competition_seeds on games.seed_id = competition_seeds.id
+            where competition_seeds.competition_id = any($1)
+        ),
+        game_player_accounts as (
+            select
+                competition_games.game_id
+              , competition_games.competition_id
+              , coalesce(aliases.primary_id, game_players.player_id) player_id
+            from competition_games
+            join game_players using(game_id)
+            left join aliases on game_players.player_id = aliases.alias_id
+        ),
+        team_member_accounts as (
+            select
+                team_members.team_id
+              , team_members.competition_id
+              , coalesce(aliases.primary_id, team_members.player_id) player_id
+            from team_members
+            join teams on team_members.team_id = teams.id
+            left join aliases on team_members.player_id = aliases.alias_id
+            where team_members.competition_id = any($1) and teams.confirmed
+        ),
+        game_teams as (
+            select game_player_accounts.game_id, team_member_accounts.team_id
+            from game_player_accounts
+            left join team_member_accounts using(competition_id, player_id)
+            group by game_player_accounts.game_id, team_member_accounts.team_id
+        ),
+        -- games whose players are all on one team
+        attributed_games as (
+            select game_id, min(team_id) team_id
+            from game_teams
+            group by game_id
+            having count(*) = 1
+        )
+        update games
+        set team_id = attributed_games.team_id
+        from competition_games
+        left join attributed_games using(game_id)
+        where games.id = competition_games.game_id
+            and games.team_id is distinct from attributed_games.team_id",
+        competition_ids,
+    ).execute(&mut tx).await?;
+    Ok(tx)
+}
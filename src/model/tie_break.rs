@@ -0,0 +1,215 @@
+// Tie-breaks between teams, or series players, with the same matchpoints. Each competition and
+// series picks its own chain when it's created; ties left after the whole chain are shared ranks.
+// The totals are over the seeds actually played, like the matchpoints, rather than averaged: a
+// team that skipped a seed is behind on total score and seeds won, and ahead on fewer turns.
+// Averaging instead would reward skipping a hard seed; chains that mind the turns putting a team
+// that skipped one ahead should have total score first.
+use std::{cmp::Ordering, fmt, str::FromStr};
+use serde::{Serialize, Deserialize};
+use crate::model::UtcDateTime;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    // higher total score across seeds
+    TotalScore,
+    // fewer total turns across seeds
+    FewerTurns,
+    // more seeds with the best game
+    SeedsWon,
+    // whoever's last game ended first
+    EarliestCompletion,
+}
+
+impl TieBreak {
+    pub const ALL: [TieBreak; 4] = [
+        TieBreak::TotalScore,
+        TieBreak::FewerTurns,
+        TieBreak::SeedsWon,
+        TieBreak::EarliestCompletion,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TieBreak::TotalScore => "total_score",
+            TieBreak::FewerTurns => "fewer_turns",
+            TieBreak::SeedsWon => "seeds_won",
+            TieBreak::EarliestCompletion => "earliest_completion",
+        }
+    }
+
+    // as shown on the competition and series pages
+    pub fn label(&self) -> &'static str {
+        match self {
+            TieBreak::TotalScore => "total score",
+            TieBreak::FewerTurns => "fewer turns",
+            TieBreak::SeedsWon => "seeds won",
+            TieBreak::EarliestCompletion => "earliest completion",
+        }
+    }
+
+    // Less when `stats1` is ahead
+    fn compare(&self, stats1: &TieBreakStats, stats2: &TieBreakStats) -> Ordering {
+        match self {
+            TieBreak::TotalScore => stats2.total_score.cmp(&stats1.total_score),
+            TieBreak::FewerTurns => stats1.total_turns.cmp(&stats2.total_turns),
+            TieBreak::SeedsWon => stats2.seeds_won.cmp(&stats1.seeds_won),
+            // no games counted is never ahead
+            TieBreak::EarliestCompletion => match (stats1.completed_at, stats2.completed_at) {
+                (Some(completed_at1), Some(completed_at2)) => completed_at1.cmp(&completed_at2),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+
+    fn format_value(&self, stats: &TieBreakStats) -> String {
+        match self {
+            TieBreak::TotalScore => stats.total_score.to_string(),
+            TieBreak::FewerTurns => stats.total_turns.to_string(),
+            TieBreak::SeedsWon => stats.seeds_won.to_string(),
+            TieBreak::EarliestCompletion => match stats.completed_at {
+                None => "never".to_owned(),
+                Some(completed_at) => completed_at.format("%Y-%m-%d %H:%M UTC").to_string(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for TieBreak {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Unknown tie-break: {0}")]
+pub struct UnknownTieBreakError(String);
+
+impl FromStr for TieBreak {
+    type Err = UnknownTieBreakError;
+
+    fn from_str(tie_break_str: &str) -> Result<Self, Self::Err> {
+        TieBreak::ALL.iter()
+            .find(|tie_break| tie_break.as_str() == tie_break_str).copied()
+            .ok_or_else(|| UnknownTieBreakError(tie_break_str.to_owned()))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+#[error("Tie-break {0} is in the chain more than once")]
+pub struct DuplicateTieBreakError(TieBreak);
+
+pub fn validate_tie_breaks(tie_breaks: &[TieBreak]) -> Result<(), DuplicateTieBreakError> {
+    for (i, tie_break) in tie_breaks.iter().enumerate() {
+        if tie_breaks[..i].contains(tie_break) {
+            return Err(DuplicateTieBreakError(*tie_break));
+        }
+    }
+    Ok(())
+}
+
+// The chain as stored, which the db constrains to known tie-breaks
+pub fn parse_tie_breaks(tie_break_strs: &[String]) -> Vec<TieBreak> {
+    tie_break_strs.iter().filter_map(|tie_break_str| tie_break_str.parse().ok()).collect()
+}
+
+pub fn tie_break_strs(tie_breaks: &[TieBreak]) -> Vec<String> {
+    tie_breaks.iter().map(|tie_break| tie_break.as_str().to_owned()).collect()
+}
+
+// e.g. "total score, then fewer turns"
+pub fn format_tie_breaks(tie_breaks: &[TieBreak]) -> String {
+    tie_breaks.iter().map(|tie_break| tie_break.label()).collect::<Vec<&str>>().join(", then ")
+}
+
+// What the tie-breaks are decided on, over the games that count
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct TieBreakStats {
+    pub total_score: i64,
+    pub total_turns: i64,
+    pub seeds_won: i64,
+    pub completed_at: Option<UtcDateTime>,
+}
+
+// Less when `stats1` is ahead; Equal when the whole chain is
+pub fn compare_by_tie_breaks(
+    tie_breaks: &[TieBreak],
+    stats1: &TieBreakStats,
+    stats2: &TieBreakStats,
+) -> Ordering {
+    tie_breaks.iter()
+        .map(|tie_break| tie_break.compare(stats1, stats2))
+        .find(|ordering| ordering != &Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+// Why `ahead` outranks `behind`, e.g. "on total score (52 vs 49)", if a tie-break decided it
+pub fn explain_tie_break(
+    tie_breaks: &[TieBreak],
+    ahead: &TieBreakStats,
+    behind: &TieBreakStats,
+) -> Option<String> {
+    tie_breaks.iter()
+        .find(|tie_break| tie_break.compare(ahead, behind) != Ordering::Equal)
+        .map(|tie_break| format!(
+            "on {} ({} vs {})",
+            tie_break.label(),
+            tie_break.format_value(ahead),
+            tie_break.format_value(behind),
+        ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(total_score: i64, total_turns: i64, seeds_won: i64) -> TieBreakStats {
+        TieBreakStats { total_score, total_turns, seeds_won, completed_at: None }
+    }
+
+    #[test]
+    fn first_tie_break_that_differs_decides() {
+        let (ahead, behind) = (stats(48, 200, 0), stats(48, 210, 1));
+        let tie_breaks = [TieBreak::TotalScore, TieBreak::FewerTurns, TieBreak::SeedsWon];
+        assert_eq!(compare_by_tie_breaks(&tie_breaks, &ahead, &behind), Ordering::Less);
+        assert_eq!(compare_by_tie_breaks(&tie_breaks, &behind, &ahead), Ordering::Greater);
+        assert_eq!(
+            explain_tie_break(&tie_breaks, &ahead, &behind).as_deref(),
+            Some("on fewer turns (200 vs 210)"),
+        );
+    }
+
+    #[test]
+    fn tied_after_whole_chain_is_equal() {
+        let tie_breaks = [TieBreak::TotalScore, TieBreak::EarliestCompletion];
+        assert_eq!(compare_by_tie_breaks(&tie_breaks, &stats(48, 200, 1), &stats(48, 210, 0)),
+            Ordering::Equal);
+        assert_eq!(compare_by_tie_breaks(&[], &stats(48, 200, 1), &stats(40, 210, 0)),
+            Ordering::Equal);
+        assert_eq!(explain_tie_break(&tie_breaks, &stats(48, 200, 1), &stats(48, 210, 0)), None);
+    }
+
+    // Totals over the seeds played: three seeds against two that scored more each
+    #[test]
+    fn totals_count_seeds_played() {
+        let (all_seeds, skipped_seed) = (stats(66, 180, 0), stats(50, 120, 2));
+        assert_eq!(TieBreak::TotalScore.compare(&all_seeds, &skipped_seed), Ordering::Less);
+        assert_eq!(TieBreak::FewerTurns.compare(&all_seeds, &skipped_seed), Ordering::Greater);
+        assert_eq!(TieBreak::SeedsWon.compare(&all_seeds, &skipped_seed), Ordering::Greater);
+    }
+
+    #[test]
+    fn no_games_counted_is_never_earliest() {
+        let completed = TieBreakStats { completed_at: Some(chrono::Utc::now()), ..stats(0, 0, 0) };
+        let tie_breaks = [TieBreak::EarliestCompletion];
+        assert_eq!(compare_by_tie_breaks(&tie_breaks, &completed, &stats(0, 0, 0)), Ordering::Less);
+    }
+
+    #[test]
+    fn duplicate_tie_break_is_rejected() {
+        assert!(validate_tie_breaks(&[TieBreak::TotalScore, TieBreak::FewerTurns]).is_ok());
+        assert!(validate_tie_breaks(&[TieBreak::TotalScore, TieBreak::TotalScore]).is_err());
+    }
+}
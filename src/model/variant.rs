@@ -1,5 +1,7 @@
+use std::collections::HashMap;
 use serde::{Serialize, Deserialize};
 use anyhow::Result;
+use crate::{DbAdminPool, DbViewerPool, model::audit::{commit_audited, AuditContext}};
 
 #[derive(Serialize, Deserialize)]
 pub struct Variant {
@@ -7,9 +9,256 @@ pub struct Variant {
     pub name: String,
 }
 
+// A variant as described by hanab.live's variants.json
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CatalogVariant {
+    pub site_variant_id: i32,
+    pub name: String,
+    pub suits: Vec<String>,
+    // None means the default clue colors for the suits
+    pub clue_colors: Option<Vec<String>>,
+    pub clue_ranks: Vec<i16>,
+    pub special_rank: Option<i16>,
+    pub max_score: i16,
+    pub flags: Vec<String>,
+}
+
+// Everything in an entry of variants.json that we don't name here is collected into `other`,
+// and any of it that's `true` becomes a flag, e.g. upOrDown or throwItInAHole
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawCatalogVariant {
+    id: i32,
+    name: Option<String>,
+    suits: Vec<String>,
+    clue_colors: Option<Vec<String>>,
+    clue_ranks: Option<Vec<i16>>,
+    special_rank: Option<i16>,
+    #[serde(flatten)]
+    other: HashMap<String, serde_json::Value>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum VariantCatalogError {
+    #[error("Expected an array of variants, or an object of variants keyed by name")]
+    UnknownFormat,
+    #[error("Variant with site id {0} has no name")]
+    MissingName(i32),
+    #[error("Variant {0} has no suits")]
+    NoSuits(String),
+    #[error("Site variant id {0} appears more than once")]
+    DuplicateId(i32),
+    #[error("Variant name {0} appears more than once")]
+    DuplicateName(String),
+}
+
+pub fn parse_variant_catalog(json: serde_json::Value) -> Result<Vec<CatalogVariant>> {
+    // the site has shipped variants.json both as an array of variants that carry their own
+    // names, and as an object keyed by variant name
+    let raw_variants: Vec<(Option<String>, RawCatalogVariant)> = match json {
+        serde_json::Value::Array(_) => serde_json::from_value::<Vec<RawCatalogVariant>>(json)?
+            .into_iter().map(|raw_variant| (None, raw_variant)).collect(),
+        serde_json::Value::Object(_) =>
+            serde_json::from_value::<HashMap<String, RawCatalogVariant>>(json)?
+                .into_iter().map(|(name, raw_variant)| (Some(name), raw_variant)).collect(),
+        _ => return Err(VariantCatalogError::UnknownFormat.into()),
+    };
+    let mut variants = Vec::new();
+    for (key_name, raw_variant) in raw_variants {
+        let name = match raw_variant.name.or(key_name) {
+            Some(name) => name,
+            None => return Err(VariantCatalogError::MissingName(raw_variant.id).into()),
+        };
+        if raw_variant.suits.is_empty() {
+            return Err(VariantCatalogError::NoSuits(name).into());
+        }
+        let mut flags: Vec<String> = raw_variant.other.into_iter()
+            .filter(|(_, value)| value.as_bool() == Some(true))
+            .map(|(key, _)| key)
+            .collect();
+        flags.sort();
+        variants.push(CatalogVariant {
+            site_variant_id: raw_variant.id,
+            max_score: raw_variant.suits.len() as i16 * 5,
+            name,
+            suits: raw_variant.suits,
+            clue_colors: raw_variant.clue_colors,
+            clue_ranks: raw_variant.clue_ranks.unwrap_or_else(|| vec![1, 2, 3, 4, 5]),
+            special_rank: raw_variant.special_rank,
+            flags,
+        });
+    }
+    variants.sort_by_key(|variant| variant.site_variant_id);
+    let mut names = std::collections::HashSet::new();
+    for (i, variant) in variants.iter().enumerate() {
+        if i > 0 && variants[i - 1].site_variant_id == variant.site_variant_id {
+            return Err(VariantCatalogError::DuplicateId(variant.site_variant_id).into());
+        }
+        if !names.insert(&variant.name) {
+            return Err(VariantCatalogError::DuplicateName(variant.name.clone()).into());
+        }
+    }
+    Ok(variants)
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct RenamedVariant {
+    pub site_variant_id: i32,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct VariantSyncReport {
+    pub added: Vec<String>,
+    pub renamed: Vec<RenamedVariant>,
+    pub removed: Vec<String>,
+    // variants that were marked removed by an earlier sync and are back
+    pub restored: Vec<String>,
+    pub unchanged: usize,
+}
+
+// Upserts the whole catalog by site id. Variants that are missing from the catalog are marked
+// removed rather than deleted, since competitions still reference them.
+pub async fn sync_variants(
+    pool: &DbAdminPool,
+    variants: &[CatalogVariant],
+    audit: &AuditContext,
+) -> Result<VariantSyncReport> {
+    let mut tx = pool.0.begin().await?;
+    sqlx::query("set constraints variants_name_key deferred").execute(&mut tx).await?;
+    let mut existing: HashMap<i32, (String, bool)> = sqlx::query!(
+        "select site_variant_id, name, removed_at is not null as removed
+        from variants",
+    ).fetch_all(&mut tx).await?.into_iter().map(|record|
+        (record.site_variant_id, (record.name, record.removed.unwrap_or(false)))).collect();
+    let mut report = VariantSyncReport::default();
+    for variant in variants {
+        match existing.remove(&variant.site_variant_id) {
+            None => report.added.push(variant.name.clone()),
+            Some((old_name, removed)) => {
+                if old_name != variant.name {
+                    report.renamed.push(RenamedVariant {
+                        site_variant_id: variant.site_variant_id,
+                        old_name,
+                        new_name: variant.name.clone(),
+                    });
+                } else if removed {
+                    report.restored.push(variant.name.clone());
+                } else {
+                    report.unchanged += 1;
+                }
+            },
+        }
+        sqlx::query!(
+            "insert into variants (
+                site_variant_id
+              , name
+              , suits
+              , clue_colors
+              , clue_ranks
+              , special_rank
+              , max_score
+              , flags
+            ) values (
+                $1
+              , $2
+              , $3
+              , $4
+              , $5
+              , $6
+              , $7
+              , $8
+            )
+            on conflict (site_variant_id) do update set
+                name = excluded.name
+              , suits = excluded.suits
+              , clue_colors = excluded.clue_colors
+              , clue_ranks = excluded.clue_ranks
+              , special_rank = excluded.special_rank
+              , max_score = excluded.max_score
+              , flags = excluded.flags
+              , removed_at = null",
+            variant.site_variant_id,
+            variant.name,
+            &variant.suits,
+            variant.clue_colors.as_deref(),
+            &variant.clue_ranks,
+            variant.special_rank,
+            variant.max_score,
+            &variant.flags,
+        ).execute(&mut tx).await?;
+    }
+    // whatever's left over wasn't in the catalog
+    let missing_ids: Vec<i32> = existing.keys().copied().collect();
+    report.removed = sqlx::query!(
+        "update variants
+        set removed_at = now()
+        where site_variant_id = any($1)
+            and removed_at is null
+        returning name",
+        &missing_ids,
+    ).fetch_all(&mut tx).await?.into_iter().map(|record| record.name).collect();
+    report.added.sort();
+    report.removed.sort();
+    report.restored.sort();
+    if !report.renamed.is_empty() {
+        // competition names include the variant name
+        sqlx::query("select update_competition_names()").execute(&mut tx).await?;
+    }
+    let affected_rows = report.added.len() + report.renamed.len() + report.removed.len()
+        + report.restored.len();
+    commit_audited(tx, audit, affected_rows as u64).await?;
+    Ok(report)
+}
+
+// Shown alongside a competition's ruleset
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VariantMetadata {
+    pub site_variant_id: i32,
+    pub suits: Vec<String>,
+    pub clue_colors: Option<Vec<String>>,
+    pub clue_ranks: Vec<i16>,
+    pub special_rank: Option<i16>,
+    pub max_score: Option<i16>,
+    pub flags: Vec<String>,
+    pub removed: bool,
+}
+
+pub async fn get_variant_metadata(
+    pool: &DbViewerPool,
+    variant_ids: &[i32],
+) -> Result<HashMap<i32, VariantMetadata>> {
+    Ok(sqlx::query!(
+        "select
+            id
+          , site_variant_id
+          , suits
+          , clue_colors
+          , clue_ranks
+          , special_rank
+          , max_score
+          , flags
+          , removed_at is not null as removed
+        from variants
+        where id = any($1)",
+        variant_ids,
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| (record.id, VariantMetadata {
+        site_variant_id: record.site_variant_id,
+        suits: record.suits,
+        clue_colors: record.clue_colors,
+        clue_ranks: record.clue_ranks,
+        special_rank: record.special_rank,
+        max_score: record.max_score,
+        flags: record.flags,
+        removed: record.removed.unwrap_or(false),
+    })).collect())
+}
+
 pub async fn add_variants(
     pool: &crate::DbAdminPool,
     variants: &Vec<Variant>,
+    audit: &AuditContext,
 ) -> Result<()> {
     let mut tx = pool.0.begin().await?;
     for variant in variants {
@@ -25,6 +274,17 @@ pub async fn add_variants(
             variant.name,
         ).execute(&mut tx).await?;
     }
-    tx.commit().await?;
-    Ok(())
+    commit_audited(tx, audit, variants.len() as u64).await
+}
+
+// Removed variants are left out, since no new competitions should use them
+pub async fn get_variant_names(
+    pool: &crate::DbViewerPool,
+) -> Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "select name
+        from variants
+        where removed_at is null
+        order by name",
+    ).fetch_all(&pool.0).await?.into_iter().map(|record| record.name).collect())
 }
@@ -0,0 +1,177 @@
+use std::{
+    collections::{HashMap, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use actix_web::{HttpRequest, HttpResponse, http::header};
+use actix_http::http::header::{
+    Header,
+    EntityTag,
+    ETag,
+    HttpDate,
+    IfModifiedSince,
+    IfNoneMatch,
+    LastModified,
+};
+
+// Which competitions are active depends on the current time, and not only on what's in the db,
+// so entries also expire on their own.
+const MAX_PAGE_AGE: Duration = Duration::from_secs(60);
+// The query string is part of the key, so anyone can add pages; past this, the oldest go
+const MAX_CACHED_PAGES: usize = 1000;
+
+// In-process cache of rendered pages, keyed by path and query string.
+// The data behind the pages only changes through the admin endpoints, which invalidate it.
+#[derive(Clone)]
+pub struct PageCache(Arc<RwLock<PageCacheContents>>);
+
+struct PageCacheContents {
+    pages: HashMap<String, CachedPage>,
+    // Bumped by every invalidation, so that a page read from the db before an admin write isn't
+    // cached after it
+    generation: u64,
+}
+
+// The cache's generation when a page wasn't found in it, taken before the page is read from the db
+pub struct CacheMiss {
+    generation: u64,
+}
+
+struct CachedPage {
+    content_type: &'static str,
+    body: String,
+    etag: EntityTag,
+    // Doubles as the page's Last-Modified date, since it may change with the time alone
+    cached_at: SystemTime,
+}
+
+impl Default for PageCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PageCache {
+    pub fn new() -> Self {
+        Self(Arc::new(RwLock::new(PageCacheContents {
+            pages: HashMap::new(),
+            generation: 0,
+        })))
+    }
+
+    pub fn invalidate(&self) {
+        let mut contents = self.0.write().unwrap();
+        contents.pages.clear();
+        contents.generation += 1;
+    }
+
+    // Errs if the page isn't cached, or has gone stale; pass the miss on to `cache_and_respond`
+    pub fn respond_from_cache(&self, req: &HttpRequest) -> Result<HttpResponse, CacheMiss> {
+        let contents = self.0.read().unwrap();
+        let cache_miss = CacheMiss { generation: contents.generation };
+        let page = match contents.pages.get(&cache_key(req)) {
+            Some(page) => page,
+            None => return Err(cache_miss),
+        };
+        let age = page.cached_at.elapsed().unwrap_or(MAX_PAGE_AGE);
+        if age >= MAX_PAGE_AGE {
+            return Err(cache_miss);
+        }
+        Ok(build_response(req, page))
+    }
+
+    pub fn cache_and_respond(
+        &self,
+        req: &HttpRequest,
+        cache_miss: &CacheMiss,
+        content_type: &'static str,
+        body: String,
+    ) -> HttpResponse {
+        let mut hasher = DefaultHasher::new();
+        body.hash(&mut hasher);
+        let page = CachedPage {
+            content_type,
+            etag: EntityTag::strong(format!("{:x}", hasher.finish())),
+            body,
+            cached_at: SystemTime::now(),
+        };
+        let response = build_response(req, &page);
+        let mut contents = self.0.write().unwrap();
+        // The cache was invalidated since the page was read, so it may already be stale
+        if contents.generation != cache_miss.generation {
+            return response;
+        }
+        contents.pages.retain(|_, cached_page| cached_page.cached_at.elapsed()
+            .is_ok_and(|age| age < MAX_PAGE_AGE));
+        let key = cache_key(req);
+        if contents.pages.len() >= MAX_CACHED_PAGES && !contents.pages.contains_key(&key) {
+            let oldest_key = contents.pages.iter()
+                .min_by_key(|(_, cached_page)| cached_page.cached_at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest_key) = oldest_key {
+                contents.pages.remove(&oldest_key);
+            }
+        }
+        contents.pages.insert(key, page);
+        response
+    }
+}
+
+fn cache_key(req: &HttpRequest) -> String {
+    format!("{}?{}", req.path(), req.query_string())
+}
+
+fn build_response(req: &HttpRequest, page: &CachedPage) -> HttpResponse {
+    // HTTP dates have a resolution of one second
+    let last_modified = UNIX_EPOCH + Duration::from_secs(
+        page.cached_at.duration_since(UNIX_EPOCH).unwrap().as_secs());
+    // If-None-Match takes precedence over If-Modified-Since when both are present
+    let is_not_modified = if req.headers().contains_key(header::IF_NONE_MATCH) {
+        match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|etag| etag.weak_eq(&page.etag)),
+            Err(_) => false,
+        }
+    } else {
+        match IfModifiedSince::parse(req) {
+            Ok(IfModifiedSince(date)) => SystemTime::from(date) >= last_modified,
+            Err(_) => false,
+        }
+    };
+    let mut builder = if is_not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    builder
+        .set(ETag(page.etag.clone()))
+        .set(LastModified(HttpDate::from(last_modified)))
+        // let clients and the reverse proxy keep a copy, as long as they revalidate it
+        .header(header::CACHE_CONTROL, "no-cache");
+    if is_not_modified {
+        builder.finish()
+    } else {
+        builder.content_type(page.content_type).body(page.body.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn page_read_before_invalidation_isnt_cached() {
+        let page_cache = PageCache::new();
+        let req = TestRequest::with_uri("/series/test").to_http_request();
+        let cache_miss = page_cache.respond_from_cache(&req).err().unwrap();
+        page_cache.invalidate();
+        page_cache.cache_and_respond(&req, &cache_miss, "text/plain", "stale".to_owned());
+        assert!(page_cache.respond_from_cache(&req).is_err());
+
+        let cache_miss = page_cache.respond_from_cache(&req).err().unwrap();
+        page_cache.cache_and_respond(&req, &cache_miss, "text/plain", "fresh".to_owned());
+        assert!(page_cache.respond_from_cache(&req).is_ok());
+    }
+}
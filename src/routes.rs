@@ -4,41 +4,76 @@ pub mod competitions;
 pub mod games;
 pub mod variants;
 pub mod results;
+pub mod aliases;
+pub mod admin;
+pub mod api_tokens;
+pub mod audit;
+pub mod schedules;
+pub mod planner;
+pub mod replays;
+pub mod characters;
+pub mod teams;
+pub mod adjustments;
 
 use actix_web::{web, HttpResponse, HttpRequest};
-use std::{fs, io::{BufReader, prelude::*}};
 use actix_http::http::header::Header;
 use actix_web_httpauth::headers::authorization;
-use std::collections::HashMap;
-use anyhow::Result;
 use thiserror;
+use crate::{
+    credentials::{AdminCredentials, Scope},
+    model::{api_token::use_api_token, audit::{record_failed_change, AuditContext}},
+    page_cache::PageCache,
+    DbAdminPool,
+};
 
-#[derive(Clone)]
-pub struct AdminCredentials(pub HashMap<String, String>);
+// Accepted by every endpoint that changes the db.
+// A dry run makes the change, checks it for errors, and then rolls it back.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DryRunQueryParams {
+    pub dry_run: Option<bool>,
+}
 
-impl AdminCredentials {
-    pub fn read_credentials_from_file(file_path: &str) -> Result<Self> {
-        let file = fs::File::open(file_path)?;
-        let buf = BufReader::new(file);
-        let credentials: HashMap<String, String> = itertools::process_results(
-            buf.lines(),
-            |lines| {
-                lines.filter_map(|line| {
-                    match line.chars().next() {
-                        // Ignore empty lines and lines beginning with a #
-                        Some('#') => None,
-                        None => None,
-                        _ => {
-                            let mut tokens = line.splitn(2, ':');
-                            let user_id = tokens.next().unwrap().to_owned();
-                            let password = tokens.next().unwrap().to_owned();
-                            Some((user_id, password))
-                        },
-                    }
-                }).collect()
-            }
-        )?;
-        Ok(Self(credentials))
+fn build_audit_context<T: serde::Serialize + ?Sized>(
+    req: &HttpRequest,
+    user_id: &str,
+    payload: &T,
+    query_params: &DryRunQueryParams,
+) -> AuditContext {
+    AuditContext::new(
+        &format!("{} {}", req.method(), req.path()),
+        user_id,
+        payload,
+        query_params.dry_run.unwrap_or(false),
+    )
+}
+
+// Failed changes are audited too, with their error, once their transaction has been rolled back
+async fn audit_failed_change(db_pool: &DbAdminPool, audit: &AuditContext, err: &anyhow::Error) {
+    if let Err(audit_err) = record_failed_change(db_pool, audit, err).await {
+        error!("Couldn't audit a failed change: {}", audit_err);
+    }
+}
+
+async fn build_audited_error_response(
+    db_pool: &web::Data<DbAdminPool>,
+    audit: &AuditContext,
+    err: anyhow::Error,
+) -> HttpResponse {
+    audit_failed_change(db_pool, audit, &err).await;
+    HttpResponse::BadRequest().body(format!("{}", err))
+}
+
+// Nothing changed after a dry run, so the cached pages are still good
+fn build_audited_success_response(
+    audit: &AuditContext,
+    page_cache: &PageCache,
+    message: &str,
+) -> HttpResponse {
+    if audit.dry_run {
+        HttpResponse::Ok().body(format!("Dry run, nothing was committed: {}", message))
+    } else {
+        page_cache.invalidate();
+        HttpResponse::Ok().body(message.to_owned())
     }
 }
 
@@ -50,6 +85,10 @@ enum CredentialsError {
     MissingPassword,
     #[error("credentials did not match any known admin")]
     BadCredentials,
+    #[error("credentials lack the {0} scope")]
+    MissingScope(Scope),
+    #[error("credentials couldn't be checked")]
+    Unavailable,
 }
 
 impl CredentialsError {
@@ -59,33 +98,59 @@ impl CredentialsError {
                 HttpResponse::BadRequest()
             },
             CredentialsError::BadCredentials => HttpResponse::Unauthorized(),
+            CredentialsError::MissingScope(_) => HttpResponse::Forbidden(),
+            CredentialsError::Unavailable => HttpResponse::InternalServerError(),
         };
         builder.body(format!("{}", self))
     }
 }
 
+// Accepts an admin's basic auth credentials, or an API token as a bearer token.
+// Returns who was authenticated, for the record.
 async fn authenticate(
     req: &HttpRequest,
     admin_credentials: &AdminCredentials,
-) -> Result<(), CredentialsError> {
+    db_pool: &DbAdminPool,
+    scope: Scope,
+) -> Result<String, CredentialsError> {
+    if let Ok(bearer) = authorization::Authorization::<authorization::Bearer>::parse(req) {
+        return match use_api_token(db_pool, bearer.into_scheme().token()).await {
+            Err(err) => {
+                error!("Couldn't look up API token: {}", err);
+                Err(CredentialsError::Unavailable)
+            },
+            Ok(None) => Err(CredentialsError::BadCredentials),
+            Ok(Some((_, _, scopes))) if !scope.is_granted_by(&scopes) => {
+                Err(CredentialsError::MissingScope(scope))
+            },
+            Ok(Some((id, owner, _))) => Ok(format!("{} (token {})", owner, id)),
+        };
+    }
     match authorization::Authorization::<authorization::Basic>::parse(req) {
-        Err(_) => Err(CredentialsError::Parse.into()),
+        Err(_) => Err(CredentialsError::Parse),
         Ok(credentials_str) => {
             let credentials = credentials_str.into_scheme();
             let supplied_pw = credentials.password();
             if supplied_pw.is_none() {
-                return Err(CredentialsError::MissingPassword.into());
+                return Err(CredentialsError::MissingPassword);
             }
-            let stored_pw = admin_credentials.0.get(credentials.user_id() as &str);
-            let are_credentials_valid = stored_pw.is_some() && stored_pw.unwrap() == supplied_pw.unwrap();
-            match are_credentials_valid {
-                false => Err(CredentialsError::BadCredentials),
-                true => Ok(())
+            match admin_credentials.verify(credentials.user_id(), supplied_pw.unwrap()) {
+                None => Err(CredentialsError::BadCredentials),
+                Some(admin) if !admin.has_scope(scope) => Err(CredentialsError::MissingScope(scope)),
+                Some(admin) => Ok(admin.user_id),
             }
         }
     }
 }
 
+pub const DEFAULT_JSON_LIMIT: usize = 100000;
+// hanab.live's variants.json is a few MB.
+// A resource's json config replaces the app's, along with anything else set with
+// `App::app_data`, but not what's set with `App::data`, which is all these handlers use.
+const CATALOG_JSON_LIMIT: usize = 1 << 24;
+// a batch of games with their full exports, which are tens of kB each
+const GAMES_PAYLOAD_LIMIT: usize = 1 << 24;
+
 pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(series::get_series);
     cfg.service(series::post_series);
@@ -93,6 +158,59 @@ pub fn init(cfg: &mut web::ServiceConfig) {
     cfg.service(results::get_results);
     cfg.service(competitions::get_competition);
     cfg.service(competitions::post_competitions);
+    cfg.service(competitions::post_table_links);
+    cfg.service(teams::get_teams);
+    cfg.service(teams::post_teams);
+    cfg.service(teams::post_team_confirmation);
+    cfg.service(teams::delete_team);
+    cfg.service(adjustments::get_competition_adjustments);
+    cfg.service(adjustments::post_adjustments);
+    cfg.service(adjustments::delete_adjustment);
     cfg.service(variants::post_variants);
-    cfg.service(games::post_games);
+    cfg.service(web::resource("/variants/sync")
+        .app_data(web::JsonConfig::default().limit(CATALOG_JSON_LIMIT))
+        .route(web::post().to(variants::post_variants_sync)));
+    cfg.service(web::resource("/characters/sync")
+        .app_data(web::JsonConfig::default().limit(CATALOG_JSON_LIMIT))
+        .route(web::post().to(characters::post_characters_sync)));
+    cfg.service(competitions::post_refresh);
+    cfg.service(web::resource("/games")
+        .app_data(web::JsonConfig::default().limit(GAMES_PAYLOAD_LIMIT))
+        .route(web::post().to(games::post_games)));
+    cfg.service(games::get_game_export);
+    cfg.service(games::get_games_list);
+    cfg.service(games::post_games_list);
+    cfg.service(games::delete_games_list);
+    cfg.service(aliases::get_aliases);
+    cfg.service(aliases::post_aliases);
+    cfg.service(aliases::delete_aliases);
+    cfg.service(api_tokens::get_api_tokens);
+    cfg.service(api_tokens::post_api_tokens);
+    cfg.service(api_tokens::delete_api_token);
+    cfg.service(audit::get_audit_log);
+    cfg.service(schedules::get_schedules);
+    cfg.service(schedules::post_schedules);
+    cfg.service(schedules::delete_schedule);
+    cfg.service(schedules::get_calendar);
+    cfg.service(planner::post_planner);
+    cfg.service(replays::get_replays);
+    cfg.service(replays::post_replays);
+    cfg.service(admin::get_login);
+    cfg.service(admin::post_login);
+    cfg.service(admin::post_logout);
+    cfg.service(admin::get_dashboard);
+    cfg.service(admin::get_competition);
+    cfg.service(admin::post_competition);
+    cfg.service(admin::post_competitions);
+    cfg.service(admin::post_series_competitions);
+    cfg.service(admin::delete_series_competitions);
+    cfg.service(web::resource("/admin/games")
+        .app_data(web::FormConfig::default().limit(GAMES_PAYLOAD_LIMIT))
+        .route(web::post().to(admin::post_games)));
+    cfg.service(admin::post_aliases);
+    cfg.service(admin::delete_aliases);
+    cfg.service(admin::post_game_list);
+    cfg.service(admin::delete_game_list);
+    cfg.service(admin::post_refresh);
+    cfg.service(admin::get_audit_log);
 }
@@ -0,0 +1,97 @@
+use actix_web::{get, post, delete, web, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::adjustment::{add_adjustment, get_adjustments, remove_adjustment, AdjustmentRequest},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[get("/competitions/{name}/adjustments")]
+async fn get_competition_adjustments(
+    wrapped_competition_name: web::Path<String>,
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_adjustments(db_pool.get_ref(), &wrapped_competition_name).await {
+        Ok(adjustments) => Ok(HttpResponse::Ok().json(adjustments)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+#[post("/competitions/{name}/adjustments")]
+async fn post_adjustments(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_competition_name: web::Path<String>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<AdjustmentRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let request = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &request, &query_params);
+    match add_adjustment(
+        wrapped_db_pool.get_ref(),
+        &wrapped_competition_name,
+        &request,
+        &audit,
+    ).await {
+        Ok(adjustment_id) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            &format!("Adjustment {} was successfully added.", adjustment_id),
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+#[delete("/competitions/{name}/adjustments/{id}")]
+async fn delete_adjustment(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    path: web::Path<(String, i32)>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let (competition_name, adjustment_id) = path.into_inner();
+    let audit = build_audit_context(&req, &user_id, &adjustment_id, &query_params);
+    match remove_adjustment(
+        wrapped_db_pool.get_ref(),
+        &competition_name,
+        adjustment_id,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Adjustment was successfully removed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
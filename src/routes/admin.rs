@@ -0,0 +1,938 @@
+// Browser-based admin console, as an alternative to posting JSON to the API.
+// Admins log in once and get a session cookie; every form also carries the session's CSRF token.
+use actix_web::{get, post, web, HttpResponse, Error, HttpRequest, HttpMessage, http::header};
+use actix_web::cookie::{Cookie, SameSite};
+use serde::{Serialize, Deserialize};
+use chrono::{NaiveDateTime, DateTime, Utc};
+use crate::{
+    model::{
+        UtcDateTime,
+        audit::{get_audit_entries, AuditContext, AuditEntry, AuditFilter},
+        competition::{
+            add_competitions,
+            get_competition_names,
+            get_competition_seeds,
+            get_competition_with_derived_quantities,
+            refresh_all_standings,
+            update_competition,
+            Competition,
+            CompetitionRulesetEdit,
+            CompetitionSeed,
+            CompetitionWithDerivedQuantities,
+            PartiallySpecifiedCompetition,
+            TimeControl,
+        },
+        game::{
+            add_competitions_games,
+            add_to_game_list,
+            get_game_list,
+            remove_from_game_list,
+            CompetitionGames,
+            GameList,
+            GameListEntry,
+        },
+        player::{add_aliases, get_aliases, remove_aliases, Alias},
+        series::{add_competition_to_series, get_series_names, remove_competition_from_series},
+        tie_break::{TieBreak, UnknownTieBreakError},
+        variant::get_variant_names,
+    },
+    credentials::{Admin, AdminCredentials, Scope},
+    page_cache::PageCache,
+    routes::audit_failed_change,
+    session::{AdminSession, AdminSessions, SESSION_COOKIE_NAME},
+    DbViewerPool,
+    DbAdminPool,
+};
+
+// Format of <input type="datetime-local">; times are entered in UTC
+const DATETIME_INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+const AUDIT_ENTRIES_PER_PAGE: i64 = 50;
+
+#[derive(Serialize, Deserialize)]
+pub struct MessageQueryParams {
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LoginView {
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DashboardView {
+    user_id: String,
+    csrf_token: String,
+    message: Option<String>,
+    competition_names: Vec<String>,
+    series_names: Vec<String>,
+    variant_names: Vec<String>,
+    default_competition: Competition,
+    default_end_datetime: String,
+    aliases: Vec<Alias>,
+    whitelist: Vec<GameListEntry>,
+    blacklist: Vec<GameListEntry>,
+}
+
+#[derive(Serialize)]
+struct AuditLogView {
+    message: Option<String>,
+    query_params: AuditQueryParams,
+    audit_entries: Vec<AuditEntry>,
+    page: i64,
+    // the filter's query string, to which the pagination links add the page
+    filter_query: String,
+    previous_page: Option<i64>,
+    next_page: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct CompetitionView {
+    csrf_token: String,
+    message: Option<String>,
+    competition: CompetitionWithDerivedQuantities,
+    // withheld from `competition` until it starts
+    seeds: Vec<CompetitionSeed>,
+    // empty when the competition has no start
+    start_datetime: String,
+    end_datetime: String,
+    series_names: Vec<String>,
+}
+
+// Forms that don't carry anything other than the CSRF token
+#[derive(Serialize, Deserialize)]
+pub struct CsrfForm {
+    csrf_token: String,
+}
+
+// Blank inputs are submitted empty rather than omitted
+#[derive(Serialize, Deserialize)]
+pub struct AuditQueryParams {
+    message: Option<String>,
+    user_id: Option<String>,
+    endpoint: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    // "true" or "false", or empty for both
+    dry_run: Option<String>,
+    // likewise
+    failed: Option<String>,
+    page: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginForm {
+    user_id: String,
+    password: String,
+}
+
+// Text inputs are submitted empty rather than omitted, and checkboxes are omitted when unchecked
+#[derive(Serialize, Deserialize)]
+pub struct CompetitionForm {
+    csrf_token: String,
+    num_players: Option<String>,
+    variant_name: Option<String>,
+    start_datetime: String,
+    end_datetime: String,
+    deckplay_enabled: Option<String>,
+    empty_clues_enabled: Option<String>,
+    characters_enabled: Option<String>,
+    registration_required: Option<String>,
+    scoring_type: String,
+    base_time_seconds: String,
+    turn_time_seconds: String,
+    additional_rules: String,
+    // one per line
+    base_seed_names: Option<String>,
+    series_name: Option<String>,
+    // comma-separated, in order; only when creating, since they're picked at creation
+    tie_breaks: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SeriesCompetitionForm {
+    csrf_token: String,
+    series_name: String,
+    competition_name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GamesForm {
+    csrf_token: String,
+    games_json: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AliasForm {
+    csrf_token: String,
+    alias: String,
+    primary: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct GameListForm {
+    csrf_token: String,
+    game_list: GameList,
+    site_game_id: i64,
+    reason: Option<String>,
+}
+
+#[derive(thiserror::Error, Debug)]
+enum AdminFormError {
+    #[error("{0} is not a valid number")]
+    InvalidNumber(String),
+    #[error("{0} is not a valid date and time")]
+    InvalidDatetime(String),
+    #[error("Both or neither of the base time and turn time must be given")]
+    IncompleteTimeControl,
+    #[error("The number of players and the variant are required")]
+    MissingRequiredField,
+    #[error(transparent)]
+    UnknownTieBreak(#[from] UnknownTieBreakError),
+}
+
+#[get("/admin/login")]
+async fn get_login(
+    query_params: web::Query<MessageQueryParams>,
+    hb: web::Data<handlebars::Handlebars<'_>>,
+) -> Result<HttpResponse, Error> {
+    let view = LoginView { message: query_params.into_inner().message };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(hb.render("admin_login", &view).unwrap()))
+}
+
+#[post("/admin/login")]
+async fn post_login(
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    form: web::Form<LoginForm>,
+) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    let admin = match admin_credentials.verify(&form.user_id, &form.password) {
+        Some(admin) => admin,
+        None => return Ok(redirect_with_message(
+            "/admin/login",
+            "Those credentials did not match any known admin.",
+        )),
+    };
+    let session_token = sessions.create(&admin.user_id);
+    let cookie = Cookie::build(SESSION_COOKIE_NAME, session_token)
+        .path("/admin")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Strict)
+        .finish();
+    Ok(HttpResponse::SeeOther()
+        .header(header::LOCATION, "/admin")
+        .cookie(cookie)
+        .finish())
+}
+
+#[post("/admin/logout")]
+async fn post_logout(
+    req: HttpRequest,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    form: web::Form<CsrfForm>,
+) -> Result<HttpResponse, Error> {
+    if let Err(resp) = verify_session(&req, &sessions, &admin_credentials, &form.csrf_token, None) {
+        return Ok(resp);
+    }
+    if let Some(cookie) = req.cookie(SESSION_COOKIE_NAME) {
+        sessions.remove(cookie.value());
+        Ok(HttpResponse::SeeOther()
+            .header(header::LOCATION, "/admin/login")
+            .del_cookie(&cookie)
+            .finish())
+    } else {
+        Ok(redirect_with_message("/admin/login", "Logged out."))
+    }
+}
+
+#[get("/admin")]
+async fn get_dashboard(
+    req: HttpRequest,
+    query_params: web::Query<MessageQueryParams>,
+    db_pool: web::Data<DbViewerPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    hb: web::Data<handlebars::Handlebars<'_>>,
+) -> Result<HttpResponse, Error> {
+    let (session, _) = match get_session(&req, &sessions, &admin_credentials) {
+        Some(session_and_admin) => session_and_admin,
+        None => return Ok(redirect_to_login()),
+    };
+    let pool = db_pool.get_ref();
+    let lists = futures::try_join!(
+        get_competition_names(pool),
+        get_series_names(pool),
+        get_variant_names(pool),
+        get_aliases(pool),
+        get_game_list(pool, GameList::Whitelist),
+        get_game_list(pool, GameList::Blacklist),
+    );
+    let (competition_names, series_names, variant_names, aliases, whitelist, blacklist) =
+        match lists {
+            Ok(lists) => lists,
+            Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+        };
+    // Shows what the API would fill in for a competition that only specifies the required fields
+    let default_competition = PartiallySpecifiedCompetition {
+        num_players: 2,
+        variant_name: None,
+        title: None,
+        start_datetime: None,
+        end_datetime: None,
+        deckplay_enabled: None,
+        empty_clues_enabled: None,
+        characters_enabled: None,
+        scoring_type: None,
+        time_control: None,
+        additional_rules: None,
+        base_seed_names: None,
+        seeds: None,
+        series_names: None,
+        registration_required: None,
+        tie_breaks: None,
+    }.fill_missing_values_with_defaults();
+    let view = DashboardView {
+        user_id: session.user_id,
+        csrf_token: session.csrf_token,
+        message: query_params.into_inner().message,
+        competition_names,
+        series_names,
+        variant_names,
+        default_end_datetime: default_competition.ruleset.end_datetime
+            .format(DATETIME_INPUT_FORMAT).to_string(),
+        default_competition,
+        aliases,
+        whitelist,
+        blacklist,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(hb.render("admin", &view).unwrap()))
+}
+
+#[get("/admin/competitions/{name}")]
+async fn get_competition(
+    req: HttpRequest,
+    query_params: web::Query<MessageQueryParams>,
+    wrapped_competition_name: web::Path<String>,
+    db_pool: web::Data<DbViewerPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    hb: web::Data<handlebars::Handlebars<'_>>,
+) -> Result<HttpResponse, Error> {
+    let (session, _) = match get_session(&req, &sessions, &admin_credentials) {
+        Some(session_and_admin) => session_and_admin,
+        None => return Ok(redirect_to_login()),
+    };
+    let pool = db_pool.get_ref();
+    let competition_and_series_names = futures::try_join!(
+        get_competition_with_derived_quantities(pool, &wrapped_competition_name),
+        get_competition_seeds(pool, &wrapped_competition_name),
+        get_series_names(pool),
+    );
+    match competition_and_series_names {
+        Ok((competition, seeds, series_names)) => {
+            let view = CompetitionView {
+                csrf_token: session.csrf_token,
+                message: query_params.into_inner().message,
+                start_datetime: competition.competition.ruleset.start_datetime
+                    .map(|start_datetime| start_datetime.format(DATETIME_INPUT_FORMAT).to_string())
+                    .unwrap_or_default(),
+                end_datetime: competition.competition.ruleset.end_datetime
+                    .format(DATETIME_INPUT_FORMAT).to_string(),
+                competition,
+                seeds,
+                series_names,
+            };
+            Ok(HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                .body(hb.render("admin_competition", &view).unwrap()))
+        },
+        Err(err) => Ok(redirect_with_message("/admin", &format!("{}", err))),
+    }
+}
+
+#[post("/admin/competitions/{name}")]
+async fn post_competition(
+    req: HttpRequest,
+    wrapped_competition_name: web::Path<String>,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<CompetitionForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::CompetitionsWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let competition_name = wrapped_competition_name.into_inner();
+    let redirect_path = competition_path(&competition_name);
+    let form = form.into_inner();
+    let edit = match parse_competition_edit(form) {
+        Ok(edit) => edit,
+        Err(err) => return Ok(redirect_with_message(&redirect_path, &format!("{}", err))),
+    };
+    let audit = build_audit_context(&req, &session, &(&competition_name, &edit));
+    match update_competition(db_pool.get_ref(), &competition_name, &edit, &audit).await {
+        Ok(new_competition_name) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message(
+                &competition_path(&new_competition_name),
+                "Competition was successfully updated.",
+            ))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, &redirect_path, err).await),
+    }
+}
+
+#[post("/admin/competitions")]
+async fn post_competitions(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<CompetitionForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::CompetitionsWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let competition = match parse_new_competition(form.into_inner()) {
+        Ok(competition) => competition,
+        Err(err) => return Ok(redirect_with_message("/admin", &format!("{}", err))),
+    };
+    let audit = build_audit_context(&req, &session, &[&competition]);
+    match add_competitions(db_pool.get_ref(), vec![competition], &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Competition and seeds were successfully inserted."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/series-competitions")]
+async fn post_series_competitions(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<SeriesCompetitionForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::CompetitionsWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let redirect_path = competition_path(&form.competition_name);
+    let audit = build_audit_context(&req, &session, &(&form.series_name, &form.competition_name));
+    match add_competition_to_series(
+        db_pool.get_ref(),
+        &form.series_name,
+        &form.competition_name,
+        &audit,
+    ).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message(&redirect_path, "Competition was added to the series."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, &redirect_path, err).await),
+    }
+}
+
+#[post("/admin/series-competitions/delete")]
+async fn delete_series_competitions(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<SeriesCompetitionForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::CompetitionsWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let redirect_path = competition_path(&form.competition_name);
+    let audit = build_audit_context(&req, &session, &(&form.series_name, &form.competition_name));
+    match remove_competition_from_series(
+        db_pool.get_ref(),
+        &form.series_name,
+        &form.competition_name,
+        &audit,
+    ).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message(&redirect_path, "Competition was removed from the series."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, &redirect_path, err).await),
+    }
+}
+
+// Takes the same JSON as POST /games, pasted or loaded from a file in the browser.
+// POST /admin/games, registered in routes::init with a larger form limit
+pub async fn post_games(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<GamesForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::GamesWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let competitions_games: Vec<CompetitionGames> = match serde_json::from_str(&form.games_json) {
+        Ok(competitions_games) => competitions_games,
+        Err(err) => return Ok(redirect_with_message("/admin", &format!("{}", err))),
+    };
+    for competition_games in &competitions_games {
+        if competition_games.validate().is_err() {
+            return Ok(redirect_with_message("/admin", "Competition results are malformed."));
+        }
+    }
+    let audit = build_audit_context(&req, &session, &competitions_games);
+    match add_competitions_games(db_pool.get_ref(), &competitions_games, &audit).await {
+        Ok(ingest_report) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", &format!(
+                "Games were successfully inserted.{}",
+                ingest_report.summarize(),
+            )))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/aliases")]
+async fn post_aliases(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<AliasForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::PlayersWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let form = form.into_inner();
+    let alias = Alias {
+        alias: form.alias,
+        primary: form.primary.unwrap_or_default(),
+    };
+    let aliases = [alias];
+    let audit = build_audit_context(&req, &session, &aliases);
+    match add_aliases(db_pool.get_ref(), &aliases, &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Alias was successfully inserted."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/aliases/delete")]
+async fn delete_aliases(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<AliasForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::PlayersWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let alias_names = [form.into_inner().alias];
+    let audit = build_audit_context(&req, &session, &alias_names);
+    match remove_aliases(db_pool.get_ref(), &alias_names, &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Alias was successfully removed."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/game-lists")]
+async fn post_game_list(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<GameListForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::GamesWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let form = form.into_inner();
+    let entry = GameListEntry {
+        site_game_id: form.site_game_id,
+        reason: form.reason.filter(|reason| !reason.is_empty()),
+    };
+    let entries = [entry];
+    let audit = build_audit_context(&req, &session, &entries);
+    match add_to_game_list(db_pool.get_ref(), form.game_list, &entries, &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Game was successfully listed."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/game-lists/delete")]
+async fn delete_game_list(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<GameListForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::GamesWrite),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let site_game_ids = [form.site_game_id];
+    let audit = build_audit_context(&req, &session, &site_game_ids);
+    match remove_from_game_list(db_pool.get_ref(), form.game_list, &site_game_ids, &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Game was successfully unlisted."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[post("/admin/refresh")]
+async fn post_refresh(
+    req: HttpRequest,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    page_cache: web::Data<PageCache>,
+    form: web::Form<CsrfForm>,
+) -> Result<HttpResponse, Error> {
+    let session = match verify_session(
+        &req,
+        &sessions,
+        &admin_credentials,
+        &form.csrf_token,
+        Some(Scope::Admin),
+    ) {
+        Ok(session) => session,
+        Err(resp) => return Ok(resp),
+    };
+    let audit = build_audit_context(&req, &session, &());
+    match refresh_all_standings(db_pool.get_ref(), &audit).await {
+        Ok(_) => {
+            page_cache.invalidate();
+            Ok(redirect_with_message("/admin", "Standings were successfully refreshed."))
+        },
+        Err(err) => Ok(redirect_with_audited_error(&db_pool, &audit, "/admin", err).await),
+    }
+}
+
+#[get("/admin/audit")]
+async fn get_audit_log(
+    req: HttpRequest,
+    query_params: web::Query<AuditQueryParams>,
+    db_pool: web::Data<DbAdminPool>,
+    admin_credentials: web::Data<AdminCredentials>,
+    sessions: web::Data<AdminSessions>,
+    hb: web::Data<handlebars::Handlebars<'_>>,
+) -> Result<HttpResponse, Error> {
+    let (_, admin) = match get_session(&req, &sessions, &admin_credentials) {
+        Some(session_and_admin) => session_and_admin,
+        None => return Ok(redirect_to_login()),
+    };
+    if !admin.has_scope(Scope::Admin) {
+        return Ok(HttpResponse::Forbidden().body(format!("admin lacks the {} scope", Scope::Admin)));
+    }
+    let mut query_params = query_params.into_inner();
+    let filter = match parse_audit_filter(&query_params) {
+        Ok(filter) => filter,
+        Err(err) => return Ok(redirect_with_message("/admin/audit", &format!("{}", err))),
+    };
+    let audit_entries = match get_audit_entries(db_pool.get_ref(), &filter).await {
+        Ok(audit_entries) => audit_entries,
+        Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    };
+    let page = filter.page.unwrap_or(1).max(1);
+    let filter_query = [
+        ("user_id", &query_params.user_id),
+        ("endpoint", &query_params.endpoint),
+        ("since", &query_params.since),
+        ("until", &query_params.until),
+        ("dry_run", &query_params.dry_run),
+        ("failed", &query_params.failed),
+    ].iter()
+        .filter_map(|(key, value)| value.as_ref().map(|value| {
+            format!("{}={}&", key, urlencoding::encode(value))
+        }))
+        .collect();
+    let view = AuditLogView {
+        message: query_params.message.take(),
+        previous_page: Some(page - 1).filter(|previous_page| *previous_page >= 1),
+        // a full page means there may be more
+        next_page: page.checked_add(1)
+            .filter(|_| audit_entries.len() as i64 == AUDIT_ENTRIES_PER_PAGE),
+        query_params,
+        audit_entries,
+        page,
+        filter_query,
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(hb.render("admin_audit", &view).unwrap()))
+}
+
+// Sessions of admins who have since been removed from the credentials file are ignored
+fn get_session(
+    req: &HttpRequest,
+    sessions: &AdminSessions,
+    admin_credentials: &AdminCredentials,
+) -> Option<(AdminSession, Admin)> {
+    let session = sessions.get(req.cookie(SESSION_COOKIE_NAME)?.value())?;
+    let admin = admin_credentials.get_admin(&session.user_id)?;
+    Some((session, admin))
+}
+
+// For form submissions, which must come from a logged-in admin and carry their CSRF token
+fn verify_session(
+    req: &HttpRequest,
+    sessions: &AdminSessions,
+    admin_credentials: &AdminCredentials,
+    csrf_token: &str,
+    scope: Option<Scope>,
+) -> Result<AdminSession, HttpResponse> {
+    let (session, admin) = match get_session(req, sessions, admin_credentials) {
+        Some(session_and_admin) => session_and_admin,
+        None => return Err(redirect_to_login()),
+    };
+    if !constant_time_eq::constant_time_eq(session.csrf_token.as_bytes(), csrf_token.as_bytes()) {
+        return Err(HttpResponse::Forbidden().body("The form's CSRF token did not match the session."));
+    }
+    match scope {
+        Some(scope) if !admin.has_scope(scope) => {
+            Err(HttpResponse::Forbidden().body(format!("admin lacks the {} scope", scope)))
+        },
+        _ => Ok(session),
+    }
+}
+
+// The console doesn't offer dry runs
+fn build_audit_context<T: Serialize + ?Sized>(
+    req: &HttpRequest,
+    session: &AdminSession,
+    payload: &T,
+) -> AuditContext {
+    AuditContext::new(
+        &format!("{} {}", req.method(), req.path()),
+        &session.user_id,
+        payload,
+        false,
+    )
+}
+
+fn redirect_to_login() -> HttpResponse {
+    HttpResponse::SeeOther()
+        .header(header::LOCATION, "/admin/login")
+        .finish()
+}
+
+async fn redirect_with_audited_error(
+    db_pool: &web::Data<DbAdminPool>,
+    audit: &AuditContext,
+    path: &str,
+    err: anyhow::Error,
+) -> HttpResponse {
+    audit_failed_change(db_pool, audit, &err).await;
+    redirect_with_message(path, &format!("{}", err))
+}
+
+// Redirecting after a post keeps a refresh from resubmitting the form
+fn redirect_with_message(path: &str, message: &str) -> HttpResponse {
+    HttpResponse::SeeOther()
+        .header(header::LOCATION, format!("{}?message={}", path, urlencoding::encode(message)))
+        .finish()
+}
+
+fn competition_path(competition_name: &str) -> String {
+    format!("/admin/competitions/{}", urlencoding::encode(competition_name))
+}
+
+fn parse_datetime(datetime_str: &str) -> Result<UtcDateTime, AdminFormError> {
+    NaiveDateTime::parse_from_str(datetime_str, DATETIME_INPUT_FORMAT)
+        .map(|naive_datetime| DateTime::<Utc>::from_utc(naive_datetime, Utc))
+        .map_err(|_| AdminFormError::InvalidDatetime(datetime_str.to_owned()))
+}
+
+fn parse_audit_filter(query_params: &AuditQueryParams) -> Result<AuditFilter, AdminFormError> {
+    let non_empty = |value: &Option<String>| value.clone().filter(|value| !value.is_empty());
+    Ok(AuditFilter {
+        user_id: non_empty(&query_params.user_id),
+        endpoint: non_empty(&query_params.endpoint),
+        since: non_empty(&query_params.since).map(|since| parse_datetime(&since)).transpose()?,
+        until: non_empty(&query_params.until).map(|until| parse_datetime(&until)).transpose()?,
+        dry_run: non_empty(&query_params.dry_run).map(|dry_run| dry_run == "true"),
+        failed: non_empty(&query_params.failed).map(|failed| failed == "true"),
+        page: query_params.page,
+        per_page: Some(AUDIT_ENTRIES_PER_PAGE),
+    })
+}
+
+// Blank datetime inputs are submitted empty
+fn parse_optional_datetime(datetime_str: &str) -> Result<Option<UtcDateTime>, AdminFormError> {
+    match datetime_str {
+        "" => Ok(None),
+        datetime_str => Ok(Some(parse_datetime(datetime_str)?)),
+    }
+}
+
+fn parse_number(number_str: &str) -> Result<i16, AdminFormError> {
+    number_str.trim().parse().map_err(|_| AdminFormError::InvalidNumber(number_str.to_owned()))
+}
+
+// e.g. "total_score, fewer_turns"
+fn parse_tie_breaks(tie_breaks_str: &str) -> Result<Vec<TieBreak>, AdminFormError> {
+    Ok(tie_breaks_str.split(',')
+        .map(|tie_break_str| tie_break_str.trim())
+        .filter(|tie_break_str| !tie_break_str.is_empty())
+        .map(|tie_break_str| tie_break_str.parse())
+        .collect::<Result<Vec<TieBreak>, UnknownTieBreakError>>()?)
+}
+
+fn parse_time_control(
+    base_time_seconds: &str,
+    turn_time_seconds: &str,
+) -> Result<Option<TimeControl>, AdminFormError> {
+    match (base_time_seconds.trim().is_empty(), turn_time_seconds.trim().is_empty()) {
+        (true, true) => Ok(None),
+        (false, false) => Ok(Some(TimeControl {
+            base_time_seconds: parse_number(base_time_seconds)?,
+            turn_time_seconds: parse_number(turn_time_seconds)?,
+        })),
+        _ => Err(AdminFormError::IncompleteTimeControl),
+    }
+}
+
+fn parse_competition_edit(form: CompetitionForm) -> Result<CompetitionRulesetEdit, AdminFormError> {
+    Ok(CompetitionRulesetEdit {
+        start_datetime: parse_optional_datetime(&form.start_datetime)?,
+        end_datetime: parse_datetime(&form.end_datetime)?,
+        deckplay_enabled: form.deckplay_enabled.is_some(),
+        empty_clues_enabled: form.empty_clues_enabled.is_some(),
+        characters_enabled: form.characters_enabled.is_some(),
+        scoring_type: form.scoring_type,
+        time_control: parse_time_control(&form.base_time_seconds, &form.turn_time_seconds)?,
+        additional_rules: Some(form.additional_rules).filter(|rules| !rules.is_empty()),
+        registration_required: form.registration_required.is_some(),
+    })
+}
+
+// Blank fields are left for `fill_missing_values_with_defaults`
+fn parse_new_competition(form: CompetitionForm) -> Result<PartiallySpecifiedCompetition, AdminFormError> {
+    let num_players = match form.num_players.as_deref() {
+        None | Some("") => return Err(AdminFormError::MissingRequiredField),
+        Some(num_players) => parse_number(num_players)?,
+    };
+    let variant_name = match form.variant_name {
+        None => return Err(AdminFormError::MissingRequiredField),
+        Some(variant_name) if variant_name.is_empty() => {
+            return Err(AdminFormError::MissingRequiredField)
+        },
+        Some(variant_name) => variant_name,
+    };
+    let start_datetime = parse_optional_datetime(&form.start_datetime)?;
+    let end_datetime = parse_optional_datetime(&form.end_datetime)?;
+    let base_seed_names: Vec<String> = form.base_seed_names.unwrap_or_default().lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect();
+    Ok(PartiallySpecifiedCompetition {
+        num_players,
+        variant_name: Some(variant_name),
+        title: None,
+        start_datetime,
+        end_datetime,
+        deckplay_enabled: Some(form.deckplay_enabled.is_some()),
+        empty_clues_enabled: Some(form.empty_clues_enabled.is_some()),
+        characters_enabled: Some(form.characters_enabled.is_some()),
+        scoring_type: Some(form.scoring_type),
+        time_control: parse_time_control(&form.base_time_seconds, &form.turn_time_seconds)?,
+        additional_rules: Some(form.additional_rules).filter(|rules| !rules.is_empty()),
+        base_seed_names: Some(base_seed_names).filter(|names| !names.is_empty()),
+        seeds: None,
+        series_names: form.series_name.filter(|name| !name.is_empty()).map(|name| vec![name]),
+        registration_required: Some(form.registration_required.is_some()),
+        tie_breaks: Some(parse_tie_breaks(&form.tie_breaks.unwrap_or_default())?),
+    })
+}
@@ -0,0 +1,94 @@
+use actix_web::{get, post, delete, web, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::player::{add_aliases, remove_aliases, get_aliases as get_all_aliases, Alias},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[get("/aliases")]
+async fn get_aliases(
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_all_aliases(db_pool.get_ref()).await {
+        Ok(aliases) => Ok(HttpResponse::Ok().json(aliases)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+#[post("/aliases")]
+async fn post_aliases(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<Vec<Alias>>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::PlayersWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let aliases = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &aliases, &query_params);
+    match add_aliases(
+        wrapped_db_pool.get_ref(),
+        &aliases,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Aliases were successfully inserted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+// Takes the names of the alias accounts
+#[delete("/aliases")]
+async fn delete_aliases(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<Vec<String>>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::PlayersWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let alias_names = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &alias_names, &query_params);
+    match remove_aliases(
+        wrapped_db_pool.get_ref(),
+        &alias_names,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Aliases were successfully removed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
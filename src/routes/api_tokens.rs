@@ -0,0 +1,99 @@
+use actix_web::{get, post, delete, web, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::api_token::{
+        get_api_tokens as get_all_api_tokens,
+        issue_api_token,
+        revoke_api_token,
+        ApiTokenRequest,
+    },
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    DbAdminPool,
+};
+
+#[get("/api-tokens")]
+async fn get_api_tokens(
+    req: HttpRequest,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+) -> Result<HttpResponse, Error> {
+    if let Err(resp) = authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::Admin,
+    ).await {
+        return Ok(resp.build_credentials_error_response());
+    }
+    match get_all_api_tokens(&wrapped_db_pool.into_inner()).await {
+        Ok(api_tokens) => Ok(HttpResponse::Ok().json(api_tokens)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+// Responds with the new token, which can't be retrieved again afterwards. A dry run's token was
+// never stored, so it's left out.
+#[post("/api-tokens")]
+async fn post_api_tokens(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    wrapped_json_payload: web::Json<ApiTokenRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::Admin,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let api_token_request = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &api_token_request, &query_params);
+    match issue_api_token(
+        wrapped_db_pool.get_ref(),
+        &api_token_request,
+        &audit,
+    ).await {
+        Ok(_) if audit.dry_run => {
+            Ok(HttpResponse::Ok().body("Dry run, nothing was committed: Token was successfully issued."))
+        },
+        Ok(issued_api_token) => Ok(HttpResponse::Ok().json(issued_api_token)),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+#[delete("/api-tokens/{id}")]
+async fn delete_api_token(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_id: web::Path<i32>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::Admin,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let id = wrapped_id.into_inner();
+    let audit = build_audit_context(&req, &user_id, &id, &query_params);
+    match revoke_api_token(wrapped_db_pool.get_ref(), id, &audit).await {
+        Ok(_) if audit.dry_run => {
+            Ok(HttpResponse::Ok().body("Dry run, nothing was committed: Token was successfully revoked."))
+        },
+        Ok(_) => Ok(HttpResponse::Ok().body("Token was successfully revoked.")),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
@@ -0,0 +1,29 @@
+use actix_web::{get, web, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::audit::{get_audit_entries, AuditFilter},
+    routes::authenticate,
+    credentials::{AdminCredentials, Scope},
+    DbAdminPool,
+};
+
+// Newest first, filtered and paginated by the query params
+#[get("/audit")]
+async fn get_audit_log(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<AuditFilter>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+) -> Result<HttpResponse, Error> {
+    if let Err(resp) = authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::Admin,
+    ).await {
+        return Ok(resp.build_credentials_error_response());
+    }
+    match get_audit_entries(&wrapped_db_pool.into_inner(), &query_params.into_inner()).await {
+        Ok(audit_entries) => Ok(HttpResponse::Ok().json(audit_entries)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
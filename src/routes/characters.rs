@@ -0,0 +1,52 @@
+use actix_web::{web, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::character::{parse_character_catalog, sync_characters},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbAdminPool,
+};
+
+// Takes hanab.live's characters.json as is, and responds with what changed.
+// POST /characters/sync, registered in routes::init with a larger json limit
+pub async fn post_characters_sync(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let characters = match parse_character_catalog(wrapped_json_payload.into_inner()) {
+        Ok(characters) => characters,
+        Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    };
+    let audit = build_audit_context(&req, &user_id, &characters, &query_params);
+    match sync_characters(
+        wrapped_db_pool.get_ref(),
+        &characters,
+        &audit,
+    ).await {
+        Ok(report) => {
+            if !audit.dry_run {
+                page_cache.invalidate();
+            }
+            Ok(HttpResponse::Ok().json(report))
+        },
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
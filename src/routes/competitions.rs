@@ -2,10 +2,21 @@ use actix_web::{get, post, web, HttpResponse, Error, HttpRequest};
 use crate::{
     model::competition::{
         add_competitions,
+        refresh_all_standings,
         PartiallySpecifiedCompetition,
-        get_competition_and_nested_results
+        TableOptions,
+        get_competition_and_nested_results,
+        get_team_create_table_urls,
     },
-    routes::{authenticate, AdminCredentials},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
     DbViewerPool,
     DbAdminPool,
 };
@@ -17,26 +28,41 @@ pub struct CompetitionNestedQueryParams {
 
 #[get("/competitions/{name}")]
 async fn get_competition(
+    req: HttpRequest,
     query_params: serde_qs::actix::QsQuery<CompetitionNestedQueryParams>,
     wrapped_competition_name: web::Path<String>,
     db_pool: web::Data<DbViewerPool>,
     hb: web::Data<handlebars::Handlebars<'_>>,
+    page_cache: web::Data<PageCache>,
 ) -> Result<HttpResponse, Error> {
+    let cache_miss = match page_cache.respond_from_cache(&req) {
+        Ok(cached_response) => return Ok(cached_response),
+        Err(cache_miss) => cache_miss,
+    };
     let unwrapped_query_params = query_params.into_inner();
     let competition_name = wrapped_competition_name.into_inner();
     let raw_output_flag = unwrapped_query_params.raw;
     match get_competition_and_nested_results(
-        &db_pool.get_ref(),
+        db_pool.get_ref(),
         &competition_name,
     ).await {
         Ok(results) => {
             if raw_output_flag.is_some() && raw_output_flag.unwrap() {
-                Ok(HttpResponse::Ok().json(serde_json::to_string(&results).unwrap()))
+                // the raw output has always been the results' JSON, encoded as a JSON string
+                let results_json = serde_json::to_string(&results).unwrap();
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "application/json",
+                    serde_json::to_string(&results_json).unwrap(),
+                ))
             } else {
-                Ok(HttpResponse::Ok()
-                    //.header("LOCATION", "/static/lobby_browser.html")
-                    .content_type("text/html; charset=utf-8")
-                    .body(hb.render("competition", &results).unwrap()))
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "text/html; charset=utf-8",
+                    hb.render("competition", &results).unwrap(),
+                ))
             }
         }
         Err(err) => {
@@ -45,22 +71,83 @@ async fn get_competition(
     }
 }
 
+// Posted rather than sent as query parameters, so that table passwords stay out of URLs, logs
+// and the page cache
+#[post("/competitions/{name}/table-links")]
+async fn post_table_links(
+    wrapped_competition_name: web::Path<String>,
+    db_pool: web::Data<DbViewerPool>,
+    wrapped_json_payload: web::Json<TableOptions>,
+) -> Result<HttpResponse, Error> {
+    match get_team_create_table_urls(
+        db_pool.get_ref(),
+        &wrapped_competition_name,
+        &wrapped_json_payload,
+    ).await {
+        Ok(create_table_urls) => Ok(HttpResponse::Ok().json(create_table_urls)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
 #[post("/competitions")]
 async fn post_competitions(
     req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
     wrapped_db_pool: web::Data<DbAdminPool>,
     wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
     wrapped_json_payload: web::Json<Vec<PartiallySpecifiedCompetition>>,
 ) -> Result<HttpResponse, Error> {
-    match authenticate(&req, &wrapped_admin_credentials.into_inner()).await {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
         Err(resp) => return Ok(resp.build_credentials_error_response()),
-        Ok(_) => (),
-    }
+    };
+    let competitions = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &competitions, &query_params);
     match add_competitions(
-        &wrapped_db_pool.into_inner(),
-        wrapped_json_payload.into_inner()
+        wrapped_db_pool.get_ref(),
+        competitions,
+        &audit,
     ).await {
-        Ok(_) => Ok(HttpResponse::Ok().body("Competitions and seeds were successfully inserted.")),
-        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Competitions and seeds were successfully inserted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+// Recomputes all standings, for when the db was modified by hand
+#[post("/refresh")]
+async fn post_refresh(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::Admin,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let audit = build_audit_context(&req, &user_id, &(), &query_params);
+    match refresh_all_standings(wrapped_db_pool.get_ref(), &audit).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Standings were successfully refreshed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
     }
 }
@@ -1,33 +1,167 @@
-use actix_web::{post, web, HttpResponse, Error, HttpRequest};
+use actix_web::{get, post, delete, web, HttpResponse, Error, HttpRequest};
 use crate::{
-    model::game::{add_competitions_games, CompetitionGames},
-    routes::{authenticate, AdminCredentials},
+    model::game::{
+        add_competitions_games,
+        add_to_game_list,
+        remove_from_game_list,
+        get_game_list,
+        CompetitionGames,
+        GameList,
+        GameListEntry,
+    },
+    model::game_export::{get_game_export_json, GameExportError},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
     DbAdminPool,
 };
 
-#[post("/games")]
-async fn post_games(
+// POST /games, registered in routes::init with a larger json limit, since games come with their
+// exports
+pub async fn post_games(
     req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
     wrapped_db_pool: web::Data<DbAdminPool>,
     wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
     wrapped_json_payload: web::Json<Vec<CompetitionGames>>,
 ) -> Result<HttpResponse, Error> {
-    match authenticate(&req, &wrapped_admin_credentials.into_inner()).await {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
         Err(resp) => return Ok(resp.build_credentials_error_response()),
-        Ok(_) => (),
-    }
+    };
     let competitions_results = wrapped_json_payload.into_inner();
     for competition_results in &competitions_results {
         match competition_results.validate() {
             Ok(_) => (),
-            Err(_) => return Ok(HttpResponse::BadRequest().body("Competition results are malformed.")),
+            Err(err) => return Ok(HttpResponse::BadRequest().body(
+                format!("Competition results are malformed: {}", err))),
         }
     }
+    let audit = build_audit_context(&req, &user_id, &competitions_results, &query_params);
     match add_competitions_games(
-        &wrapped_db_pool.into_inner(),
-        &competitions_results
+        wrapped_db_pool.get_ref(),
+        &competitions_results,
+        &audit,
     ).await {
-        Ok(_) => Ok(HttpResponse::Ok().body("Games were successfully inserted.")),
+        Ok(ingest_report) => {
+            if !audit.dry_run {
+                page_cache.invalidate();
+            }
+            Ok(HttpResponse::Ok().json(ingest_report))
+        },
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+// The game's export from hanab.live, as ingested; only digits match, so that the game lists
+// below keep their routes
+#[get("/games/{site_game_id:\\d+}")]
+async fn get_game_export(
+    wrapped_site_game_id: web::Path<i64>,
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_game_export_json(db_pool.get_ref(), wrapped_site_game_id.into_inner()).await {
+        Ok(export_json) => Ok(HttpResponse::Ok().content_type("application/json").body(export_json)),
+        Err(err) => match err.downcast_ref::<GameExportError>() {
+            Some(GameExportError::NotFound(_)) => Ok(HttpResponse::NotFound().body(format!("{}", err))),
+            _ => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+        },
+    }
+}
+
+#[get("/games/{game_list}")]
+async fn get_games_list(
+    wrapped_game_list: web::Path<GameList>,
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_game_list(db_pool.get_ref(), wrapped_game_list.into_inner()).await {
+        Ok(entries) => Ok(HttpResponse::Ok().json(entries)),
         Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
     }
 }
+
+#[post("/games/{game_list}")]
+async fn post_games_list(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_game_list: web::Path<GameList>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<Vec<GameListEntry>>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let entries = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &entries, &query_params);
+    match add_to_game_list(
+        wrapped_db_pool.get_ref(),
+        wrapped_game_list.into_inner(),
+        &entries,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Games were successfully listed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+// Takes the site IDs of the games
+#[delete("/games/{game_list}")]
+async fn delete_games_list(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_game_list: web::Path<GameList>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<Vec<i64>>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let site_game_ids = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &site_game_ids, &query_params);
+    match remove_from_game_list(
+        wrapped_db_pool.get_ref(),
+        wrapped_game_list.into_inner(),
+        &site_game_ids,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Games were successfully unlisted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
@@ -1,13 +1,15 @@
-use actix_web::{get, web, HttpResponse, Error};
+use actix_web::{get, web, HttpResponse, HttpRequest, Error};
 use crate::{
     model::{
         series::get_series_names,
         competition::{
             get_competition_names,
             get_active_competitions,
+            get_upcoming_competitions,
             CompetitionWithDerivedQuantities
         },
     },
+    page_cache::PageCache,
     DbViewerPool,
 };
 
@@ -15,37 +17,43 @@ use crate::{
 struct IndexContents {
     pub series_names: Vec<String>,
     pub competition_names: Vec<String>,
-    pub active_competitions: Vec<CompetitionWithDerivedQuantities>
+    pub active_competitions: Vec<CompetitionWithDerivedQuantities>,
+    pub upcoming_competitions: Vec<CompetitionWithDerivedQuantities>,
 }
 
 #[get("/")]
 async fn get_index(
+    req: HttpRequest,
     wrapped_db_pool: web::Data<DbViewerPool>,
     hb: web::Data<handlebars::Handlebars<'_>>,
+    page_cache: web::Data<PageCache>,
 ) -> Result<HttpResponse, Error> {
+    let cache_miss = match page_cache.respond_from_cache(&req) {
+        Ok(cached_response) => return Ok(cached_response),
+        Err(cache_miss) => cache_miss,
+    };
     let db_pool = &wrapped_db_pool.into_inner();
-    match get_series_names(db_pool).await {
+    // These are independent of each other, so run them concurrently
+    match futures::try_join!(
+        get_series_names(db_pool),
+        get_competition_names(db_pool),
+        get_active_competitions(db_pool),
+        get_upcoming_competitions(db_pool),
+    ) {
         Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
-        Ok(series_names) => {
-            match get_competition_names(db_pool).await {
-                Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
-                Ok(competition_names) => {
-                    match get_active_competitions(db_pool).await {
-                        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
-                        Ok(active_competitions) => {
-                            let index_contents = IndexContents {
-                                series_names,
-                                competition_names,
-                                active_competitions,
-                            };
-                            Ok(HttpResponse::Ok()
-                                .content_type("text/html; charset=utf-8")
-                                .body(hb.render("index", &index_contents).unwrap())
-                            )
-                        }
-                    }
-                },
-            }
+        Ok((series_names, competition_names, active_competitions, upcoming_competitions)) => {
+            let index_contents = IndexContents {
+                series_names,
+                competition_names,
+                active_competitions,
+                upcoming_competitions,
+            };
+            Ok(page_cache.cache_and_respond(
+                &req,
+                &cache_miss,
+                "text/html; charset=utf-8",
+                hb.render("index", &index_contents).unwrap(),
+            ))
         },
     }
 }
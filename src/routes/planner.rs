@@ -0,0 +1,69 @@
+use actix_web::{post, web, HttpResponse, Error, HttpRequest};
+use chrono::Utc;
+use crate::{
+    model::planner::{commit_plan, plan_competitions, PlanRequest},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PlannerQueryParams {
+    // creates the planned competitions, rather than only proposing them
+    pub commit: Option<bool>,
+    pub dry_run: Option<bool>,
+}
+
+// Responds with the plan, whether or not it was committed, and whether committing it was a dry run
+#[post("/planner")]
+async fn post_planner(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<PlannerQueryParams>,
+    db_viewer_pool: web::Data<DbViewerPool>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<PlanRequest>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let plan_request = wrapped_json_payload.into_inner();
+    let mut plan = match plan_competitions(
+        db_viewer_pool.get_ref(),
+        &plan_request,
+        Utc::now(),
+    ).await {
+        Ok(plan) => plan,
+        Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    };
+    if !query_params.commit.unwrap_or(false) {
+        return Ok(HttpResponse::Ok().json(plan));
+    }
+    let dry_run_query_params = DryRunQueryParams { dry_run: query_params.dry_run };
+    let audit = build_audit_context(&req, &user_id, &plan_request, &dry_run_query_params);
+    plan.dry_run = audit.dry_run;
+    let plan_json = serde_json::to_string(&plan).unwrap();
+    match commit_plan(wrapped_db_pool.get_ref(), plan, &audit).await {
+        Ok(_) => {
+            if !audit.dry_run {
+                page_cache.invalidate();
+            }
+            Ok(HttpResponse::Ok().content_type("application/json").body(plan_json))
+        },
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
@@ -0,0 +1,63 @@
+use actix_web::{get, post, web, HttpResponse, Error, HttpRequest};
+use serde::Deserialize;
+use crate::{
+    model::replay::{get_game_replays, replay_all_games},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[derive(Deserialize)]
+pub struct ReplaysQueryParams {
+    // only the games whose replays disagree with what was reported
+    pub flagged: Option<bool>,
+}
+
+#[get("/replays")]
+async fn get_replays(
+    query_params: serde_qs::actix::QsQuery<ReplaysQueryParams>,
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    let flagged_only = query_params.flagged.unwrap_or(false);
+    match get_game_replays(db_pool.get_ref(), flagged_only).await {
+        Ok(game_replays) => Ok(HttpResponse::Ok().json(game_replays)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+// Replays every stored export again, and responds with the games that were flagged
+#[post("/replays")]
+async fn post_replays(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::GamesWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let audit = build_audit_context(&req, &user_id, &(), &query_params);
+    match replay_all_games(wrapped_db_pool.get_ref(), &audit).await {
+        Ok(flagged_replays) => {
+            if !audit.dry_run {
+                page_cache.invalidate();
+            }
+            Ok(HttpResponse::Ok().json(flagged_replays))
+        },
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
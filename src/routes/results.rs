@@ -20,7 +20,7 @@ async fn get_results(
     let unwrapped_query_params = query_params.into_inner();
     let where_clause = unwrapped_query_params.where_clause;
     let raw_output_flag = unwrapped_query_params.raw;
-    match get_combined_results(&db_pool.get_ref(), &where_clause).await {
+    match get_combined_results(db_pool.get_ref(), &where_clause).await {
         Ok(results) => {
             if raw_output_flag.is_some() && raw_output_flag.unwrap() {
                 Ok(HttpResponse::Ok().json(results))
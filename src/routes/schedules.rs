@@ -0,0 +1,154 @@
+use actix_web::{get, post, delete, web, HttpResponse, Error, HttpRequest};
+use chrono::Utc;
+use crate::{
+    model::schedule::{
+        add_schedules,
+        get_calendar as get_scheduled_rounds,
+        get_schedules as get_all_schedules,
+        remove_schedule,
+        Schedule,
+        ScheduledRound,
+    },
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct CalendarQueryParams {
+    pub raw: Option<bool>,
+    // per schedule, starting from the current one
+    pub num_rounds: Option<u8>,
+}
+
+#[derive(serde::Serialize)]
+struct CalendarView {
+    scheduled_rounds: Vec<ScheduledRound>,
+}
+
+#[get("/schedules")]
+async fn get_schedules(
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_all_schedules(db_pool.get_ref()).await {
+        Ok(schedules) => Ok(HttpResponse::Ok().json(schedules)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+// Schedules with the same name as an existing one replace it
+#[post("/schedules")]
+async fn post_schedules(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<Vec<Schedule>>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let schedules = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &schedules, &query_params);
+    match add_schedules(
+        wrapped_db_pool.get_ref(),
+        &schedules,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Schedules were successfully inserted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+#[delete("/schedules/{name}")]
+async fn delete_schedule(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_schedule_name: web::Path<String>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let schedule_name = wrapped_schedule_name.into_inner();
+    let audit = build_audit_context(&req, &user_id, &schedule_name, &query_params);
+    match remove_schedule(
+        wrapped_db_pool.get_ref(),
+        &schedule_name,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Schedule was successfully removed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+// Upcoming rounds of every schedule, whether or not their competitions exist yet
+#[get("/calendar")]
+async fn get_calendar(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<CalendarQueryParams>,
+    db_pool: web::Data<DbViewerPool>,
+    hb: web::Data<handlebars::Handlebars<'_>>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let cache_miss = match page_cache.respond_from_cache(&req) {
+        Ok(cached_response) => return Ok(cached_response),
+        Err(cache_miss) => cache_miss,
+    };
+    let unwrapped_query_params = query_params.into_inner();
+    let raw_output_flag = unwrapped_query_params.raw;
+    let num_rounds = unwrapped_query_params.num_rounds.unwrap_or(4);
+    match get_scheduled_rounds(db_pool.get_ref(), Utc::now(), num_rounds as i32).await {
+        Ok(scheduled_rounds) => {
+            if raw_output_flag.is_some() && raw_output_flag.unwrap() {
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "application/json",
+                    serde_json::to_string(&scheduled_rounds).unwrap(),
+                ))
+            } else {
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "text/html; charset=utf-8",
+                    hb.render("calendar", &CalendarView { scheduled_rounds }).unwrap(),
+                ))
+            }
+        }
+        Err(err) => {
+            Ok(HttpResponse::BadRequest().body(format!("{}", err)))
+        }
+    }
+}
@@ -1,7 +1,15 @@
 use actix_web::{get, post, web, HttpResponse, Error, HttpRequest};
 use crate::{
     model::series::{add_series, Series},
-    routes::{authenticate, AdminCredentials},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
     DbViewerPool,
     DbAdminPool,
 };
@@ -14,26 +22,37 @@ pub struct SeriesQueryParams {
 
 #[get("/series/{name}")]
 async fn get_series(
+    req: HttpRequest,
     query_params: serde_qs::actix::QsQuery<SeriesQueryParams>,
     wrapped_series_name: web::Path<String>,
     db_pool: web::Data<DbViewerPool>,
     hb: web::Data<handlebars::Handlebars<'_>>,
+    page_cache: web::Data<PageCache>,
 ) -> Result<HttpResponse, Error> {
+    let cache_miss = match page_cache.respond_from_cache(&req) {
+        Ok(cached_response) => return Ok(cached_response),
+        Err(cache_miss) => cache_miss,
+    };
     let unwrapped_query_params = query_params.into_inner();
     let series_name = wrapped_series_name.into_inner();
     let raw_output_flag = unwrapped_query_params.raw;
-    let max_num_comps = match unwrapped_query_params.max_num_comps {
-        Some(max_num_comps) => max_num_comps,
-        None => 16,
-    };
-    match crate::model::series::get_series_view(&db_pool.get_ref(), &series_name, max_num_comps as i64).await {
+    let max_num_comps = unwrapped_query_params.max_num_comps.unwrap_or(16);
+    match crate::model::series::get_series_view(db_pool.get_ref(), &series_name, max_num_comps as i64).await {
         Ok(results) => {
             if raw_output_flag.is_some() && raw_output_flag.unwrap() {
-                Ok(HttpResponse::Ok().json(&results))
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "application/json",
+                    serde_json::to_string(&results).unwrap(),
+                ))
             } else {
-                Ok(HttpResponse::Ok()
-                    .content_type("text/html; charset=utf-8")
-                    .body(hb.render("series", &results).unwrap()))
+                Ok(page_cache.cache_and_respond(
+                    &req,
+                    &cache_miss,
+                    "text/html; charset=utf-8",
+                    hb.render("series", &results).unwrap(),
+                ))
             }
         }
         Err(err) => {
@@ -45,19 +64,33 @@ async fn get_series(
 #[post("/series")]
 async fn post_series(
     req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
     wrapped_db_pool: web::Data<DbAdminPool>,
     wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
     wrapped_json_payload: web::Json<Vec<Series>>,
 ) -> Result<HttpResponse, Error> {
-    match authenticate(&req, &wrapped_admin_credentials.into_inner()).await {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
         Err(resp) => return Ok(resp.build_credentials_error_response()),
-        Ok(_) => (),
-    }
+    };
+    let series = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &series, &query_params);
     match add_series(
-        &wrapped_db_pool.into_inner(),
-        wrapped_json_payload.into_inner()
+        wrapped_db_pool.get_ref(),
+        series,
+        &audit,
     ).await {
-        Ok(_) => Ok(HttpResponse::Ok().body("Series were successfully inserted.")),
-        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Series were successfully inserted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
     }
 }
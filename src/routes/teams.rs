@@ -0,0 +1,158 @@
+use actix_web::{get, post, delete, web, http::header, HttpResponse, Error, HttpRequest};
+use crate::{
+    model::team::{
+        get_registered_teams,
+        register_team,
+        confirm_team,
+        remove_team,
+        TeamRegistration,
+    },
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
+    DbViewerPool,
+    DbAdminPool,
+};
+
+#[get("/competitions/{name}/teams")]
+async fn get_teams(
+    wrapped_competition_name: web::Path<String>,
+    db_pool: web::Data<DbViewerPool>,
+) -> Result<HttpResponse, Error> {
+    match get_registered_teams(db_pool.get_ref(), &wrapped_competition_name).await {
+        Ok(registered_teams) => Ok(HttpResponse::Ok().json(registered_teams)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    }
+}
+
+// Anyone can register a team, the same way anyone can submit their games by playing them, but
+// nothing identifies who sent a registration without credentials, so its games only count for
+// the team once an admin confirms it. Competitions that require registration take credentials
+// with the players:write scope.
+#[post("/competitions/{name}/teams")]
+async fn post_teams(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_competition_name: web::Path<String>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<TeamRegistration>,
+) -> Result<HttpResponse, Error> {
+    // credentials that are given have to be good, rather than falling back to a public request
+    let admin_user_id = if req.headers().contains_key(header::AUTHORIZATION) {
+        match authenticate(
+            &req,
+            &wrapped_admin_credentials.into_inner(),
+            &wrapped_db_pool,
+            Scope::PlayersWrite,
+        ).await {
+            Ok(user_id) => Some(user_id),
+            Err(resp) => return Ok(resp.build_credentials_error_response()),
+        }
+    } else {
+        None
+    };
+    let registration = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(
+        &req,
+        admin_user_id.as_deref().unwrap_or("public"),
+        &registration,
+        &query_params,
+    );
+    match register_team(
+        wrapped_db_pool.get_ref(),
+        &wrapped_competition_name,
+        &registration,
+        admin_user_id.is_some(),
+        &audit,
+    ).await {
+        Ok(_) if admin_user_id.is_some() => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Team was successfully registered.",
+        )),
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Team was successfully registered, pending an admin's confirmation.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+#[post("/competitions/{name}/teams/{team_name}/confirm")]
+async fn post_team_confirmation(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    path: web::Path<(String, String)>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::PlayersWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let (competition_name, team_name) = path.into_inner();
+    let audit = build_audit_context(&req, &user_id, &team_name, &query_params);
+    match confirm_team(
+        wrapped_db_pool.get_ref(),
+        &competition_name,
+        &team_name,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Team was successfully confirmed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
+
+#[delete("/competitions/{name}/teams/{team_name}")]
+async fn delete_team(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    path: web::Path<(String, String)>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::PlayersWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let (competition_name, team_name) = path.into_inner();
+    let audit = build_audit_context(&req, &user_id, &team_name, &query_params);
+    match remove_team(
+        wrapped_db_pool.get_ref(),
+        &competition_name,
+        &team_name,
+        &audit,
+    ).await {
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Team was successfully removed.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
@@ -1,27 +1,87 @@
 use actix_web::{post, web, HttpResponse, Error, HttpRequest};
 use crate::{
-    model::variant::{add_variants, Variant},
-    routes::{authenticate, AdminCredentials},
+    model::variant::{add_variants, parse_variant_catalog, sync_variants, Variant},
+    routes::{
+        authenticate,
+        build_audit_context,
+        build_audited_error_response,
+        build_audited_success_response,
+        DryRunQueryParams,
+    },
+    credentials::{AdminCredentials, Scope},
+    page_cache::PageCache,
     DbAdminPool,
 };
 
 #[post("/variants")]
 async fn post_variants(
     req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
     wrapped_db_pool: web::Data<DbAdminPool>,
     wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
     wrapped_json_payload: web::Json<Vec<Variant>>,
 ) -> Result<HttpResponse, Error> {
-    match authenticate(&req, &wrapped_admin_credentials.into_inner()).await {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
         Err(resp) => return Ok(resp.build_credentials_error_response()),
-        Ok(_) => (),
-    }
+    };
+    let variants = wrapped_json_payload.into_inner();
+    let audit = build_audit_context(&req, &user_id, &variants, &query_params);
     match add_variants(
-        &wrapped_db_pool.into_inner(),
-        &wrapped_json_payload.into_inner(),
+        wrapped_db_pool.get_ref(),
+        &variants,
+        &audit,
     ).await {
-        Ok(_) => Ok(HttpResponse::Ok().body("Variants were successfully inserted.")),
-        Err(err) => Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+        Ok(_) => Ok(build_audited_success_response(
+            &audit,
+            &page_cache,
+            "Variants were successfully inserted.",
+        )),
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
     }
 }
 
+// Takes hanab.live's variants.json as is, and responds with what changed.
+// POST /variants/sync, registered in routes::init with a larger json limit
+pub async fn post_variants_sync(
+    req: HttpRequest,
+    query_params: serde_qs::actix::QsQuery<DryRunQueryParams>,
+    wrapped_db_pool: web::Data<DbAdminPool>,
+    wrapped_admin_credentials: web::Data<AdminCredentials>,
+    page_cache: web::Data<PageCache>,
+    wrapped_json_payload: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let user_id = match authenticate(
+        &req,
+        &wrapped_admin_credentials.into_inner(),
+        &wrapped_db_pool,
+        Scope::CompetitionsWrite,
+    ).await {
+        Ok(user_id) => user_id,
+        Err(resp) => return Ok(resp.build_credentials_error_response()),
+    };
+    let variants = match parse_variant_catalog(wrapped_json_payload.into_inner()) {
+        Ok(variants) => variants,
+        Err(err) => return Ok(HttpResponse::BadRequest().body(format!("{}", err))),
+    };
+    let audit = build_audit_context(&req, &user_id, &variants, &query_params);
+    match sync_variants(
+        wrapped_db_pool.get_ref(),
+        &variants,
+        &audit,
+    ).await {
+        Ok(report) => {
+            if !audit.dry_run {
+                page_cache.invalidate();
+            }
+            Ok(HttpResponse::Ok().json(report))
+        },
+        Err(err) => Ok(build_audited_error_response(&wrapped_db_pool, &audit, err).await),
+    }
+}
@@ -0,0 +1,36 @@
+// Creates the competitions of recurring schedules ahead of time, in the background.
+// Safe to run on several servers at once, since each round is created under a row lock.
+use std::time::Duration;
+use chrono::Utc;
+use crate::{
+    model::schedule::create_due_competitions,
+    page_cache::PageCache,
+    DbAdminPool,
+};
+
+const SCHEDULER_PERIOD: Duration = Duration::from_secs(15 * 60);
+
+pub fn create_scheduled_competitions_periodically(pool: DbAdminPool, page_cache: PageCache) {
+    actix_rt::spawn(async move {
+        let mut ticks = actix_rt::time::interval(SCHEDULER_PERIOD);
+        loop {
+            ticks.tick().await;
+            match create_due_competitions(&pool, Utc::now()).await {
+                Ok(created_rounds) => {
+                    if !created_rounds.is_empty() {
+                        page_cache.invalidate();
+                    }
+                    for created_round in created_rounds {
+                        info!(
+                            "Created round {} of schedule {}, ending {}",
+                            created_round.round,
+                            created_round.schedule_name,
+                            created_round.end_datetime,
+                        );
+                    }
+                },
+                Err(err) => error!("Couldn't create scheduled competitions: {}", err),
+            }
+        }
+    });
+}
@@ -0,0 +1,57 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+use rand::{RngCore, rngs::OsRng};
+
+pub const SESSION_COOKIE_NAME: &str = "hc_admin_session";
+const SESSION_LIFETIME: Duration = Duration::from_secs(12 * 60 * 60);
+
+// Sessions of admins logged into the admin console, keyed by the token in their session cookie.
+// They're kept in memory, so restarting the server logs everyone out.
+#[derive(Clone, Default)]
+pub struct AdminSessions(Arc<RwLock<HashMap<String, AdminSession>>>);
+
+#[derive(Clone)]
+pub struct AdminSession {
+    pub user_id: String,
+    // Must be submitted with every form, so that other sites can't post forms on an admin's
+    // behalf using their cookie
+    pub csrf_token: String,
+    expires_at: SystemTime,
+}
+
+impl AdminSessions {
+    // Returns the session token
+    pub fn create(&self, user_id: &str) -> String {
+        let session_token = generate_token();
+        let session = AdminSession {
+            user_id: user_id.to_owned(),
+            csrf_token: generate_token(),
+            expires_at: SystemTime::now() + SESSION_LIFETIME,
+        };
+        let mut sessions = self.0.write().unwrap();
+        let now = SystemTime::now();
+        sessions.retain(|_, session| session.expires_at > now);
+        sessions.insert(session_token.clone(), session);
+        session_token
+    }
+
+    pub fn get(&self, session_token: &str) -> Option<AdminSession> {
+        let sessions = self.0.read().unwrap();
+        sessions.get(session_token)
+            .filter(|session| session.expires_at > SystemTime::now())
+            .cloned()
+    }
+
+    pub fn remove(&self, session_token: &str) {
+        self.0.write().unwrap().remove(session_token);
+    }
+}
+
+pub(crate) fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
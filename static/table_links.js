@@ -0,0 +1,35 @@
+// Fills in a team's create-table links, with its table password and options, for every seed
+const table_links_form = document.getElementById("table_links_form");
+
+if (table_links_form !== null) {
+    const table_links_list = document.getElementById("table_links");
+    table_links_form.addEventListener("submit", event => {
+        event.preventDefault();
+        const competition_name = table_links_form.dataset.competitionName;
+        fetch(`/competitions/${encodeURIComponent(competition_name)}/table-links`, {
+            method: "POST",
+            headers: {"Content-Type": "application/json"},
+            body: JSON.stringify({
+                password: table_links_form.elements["password"].value,
+                card_cycle: table_links_form.elements["card_cycle"].checked,
+            }),
+        })
+            .then(response => response.ok
+                ? response.json()
+                : response.text().then(text => Promise.reject(text)))
+            .then(table_links => {
+                table_links_list.replaceChildren(...table_links.map(table_link => {
+                    const item = document.createElement("li");
+                    const link = document.createElement("a");
+                    link.href = table_link.create_table_url;
+                    link.target = "_blank";
+                    link.textContent = table_link.base_seed_name;
+                    item.appendChild(link);
+                    return item;
+                }));
+            })
+            .catch(err => {
+                table_links_list.textContent = err;
+            });
+    });
+}
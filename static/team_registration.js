@@ -0,0 +1,26 @@
+// Registers a team's roster, then reloads the page to show it among the registered teams
+const team_registration_form = document.getElementById("team_registration_form");
+
+if (team_registration_form !== null) {
+    const team_registration_message = document.getElementById("team_registration_message");
+    const names = value => value.split(",").map(name => name.trim()).filter(name => name !== "");
+    team_registration_form.addEventListener("submit", event => {
+        event.preventDefault();
+        const competition_name = team_registration_form.dataset.competitionName;
+        fetch(`/competitions/${encodeURIComponent(competition_name)}/teams`, {
+            method: "POST",
+            headers: {"Content-Type": "application/json"},
+            body: JSON.stringify({
+                team_name: team_registration_form.elements["team_name"].value,
+                players: names(team_registration_form.elements["players"].value),
+                substitutes: names(team_registration_form.elements["substitutes"].value),
+            }),
+        })
+            .then(response => response.text()
+                .then(text => response.ok ? text : Promise.reject(text)))
+            .then(() => window.location.reload())
+            .catch(err => {
+                team_registration_message.textContent = err;
+            });
+    });
+}